@@ -0,0 +1,101 @@
+//! Export the whole analysis as a single self-describing archive, and load it
+//! back for offline tools without re-running the compiler.
+//!
+//! The archive is a gzip-compressed JSON document (AnalysisBundle) with the
+//! schema version, the analysis configuration, the summaries of each crate,
+//! the call graph, and the WPA result.
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use rustc_data_structures::fx::FxHashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+use super::error::{SandboxError, SandboxResult};
+use super::summarize_fn::{DefSite, FnID, Summary};
+use super::wpa::WpaResult;
+
+/// Version of the layout of AnalysisBundle. Bump it for incompatible changes.
+pub const ARCHIVE_SCHEMA_VERSION: u32 = 2;
+
+/// Everything about one run of the analysis.
+#[derive(Serialize, Deserialize)]
+pub struct AnalysisBundle {
+    pub schema_version: u32,
+    /// The SANDBOX_* environment variables the analysis ran with, sorted.
+    pub config: Vec<(String, String)>,
+    /// Summaries of each crate, keyed by the crate name.
+    pub crates: BTreeMap<String, Vec<Summary>>,
+    /// Each function and its callees, sorted.
+    pub call_graph: Vec<(FnID, Vec<FnID>)>,
+    /// Unsafe sources of each function found by the WPA, sorted by FnID.
+    pub unsafe_sources: Vec<(FnID, Vec<DefSite>)>,
+    /// Functions along the longest propagation chain of an unsafe source.
+    pub longest_chain: Vec<String>,
+}
+
+/// Get the analysis configuration from the environment.
+fn get_config() -> Vec<(String, String)> {
+    let mut config = std::env::vars()
+        .filter(|(name, _)| name.starts_with("SANDBOX_"))
+        .collect::<Vec<_>>();
+    config.sort();
+
+    config
+}
+
+/// Bundle the summaries and the WPA result, and write them to @path.
+pub fn write_archive(path: &Path, summaries: &FxHashMap<FnID, Summary>,
+                     wpa_result: &WpaResult<'_>) -> SandboxResult<()> {
+    let mut crates = BTreeMap::<String, Vec<&Summary>>::new();
+    for summary in summaries.values() {
+        crates.entry(summary.crate_name.clone()).or_default().push(summary);
+    }
+    for crate_summaries in crates.values_mut() {
+        crate_summaries.sort_by_key(|summary| summary.fn_id);
+    }
+
+    let mut unsafe_sources = Vec::new();
+    for (fn_id, def_sites) in &wpa_result.wp_summary {
+        let mut def_sites = def_sites.iter().copied().collect::<Vec<_>>();
+        def_sites.sort();
+        unsafe_sources.push((*fn_id, def_sites));
+    }
+    unsafe_sources.sort_by_key(|(fn_id, _)| *fn_id);
+
+    // Serialize references to the summaries instead of cloning them. The
+    // layout is the same as AnalysisBundle.
+    let bundle = serde_json::json!({
+        "schema_version": ARCHIVE_SCHEMA_VERSION,
+        "config": get_config(),
+        "crates": crates,
+        "call_graph": wpa_result.call_graph_edges(),
+        "unsafe_sources": unsafe_sources,
+        "longest_chain": wpa_result.longest_chain(),
+    });
+
+    let mut encoder = GzEncoder::new(BufWriter::new(File::create(path)?),
+                                     Compression::default());
+    serde_json::to_writer(&mut encoder, &bundle)?;
+    encoder.finish()?.flush()?;
+
+    Ok(())
+}
+
+/// Load an archive written by write_archive().
+pub fn load_archive(path: &Path) -> SandboxResult<AnalysisBundle> {
+    let decoder = GzDecoder::new(BufReader::new(File::open(path)?));
+    let bundle = serde_json::from_reader::<_, AnalysisBundle>(decoder)?;
+    if bundle.schema_version != ARCHIVE_SCHEMA_VERSION {
+        return Err(SandboxError::SchemaMismatch {
+            expected: ARCHIVE_SCHEMA_VERSION,
+            found: bundle.schema_version,
+        });
+    }
+
+    Ok(bundle)
+}
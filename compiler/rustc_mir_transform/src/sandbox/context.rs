@@ -0,0 +1,88 @@
+//! Per-crate context shared by the passes over the functions of a crate, i.e.,
+//! selecting the functions to summarize, summarizing them, and finding unsafe
+//! memory accesses in them.
+//!
+//! Each pass needs to know whether a function is ignored, its FnID, and its
+//! MIR. rustc memoizes optimized_mir, but not ignore_fn() or hashing the
+//! DefPathHash of a function, so they are computed once per function here.
+//! Whether a whole crate is ignored is computed once per crate, as it needs
+//! the name of the crate. SANDBOX_NO_CONTEXT_CACHE=1 turns both caches off.
+
+use rustc_middle::mir::Body;
+use rustc_middle::ty::TyCtxt;
+use rustc_hir::def_id::{CrateNum, DefId};
+use rustc_data_structures::fx::FxHashMap;
+
+use super::summarize_fn::FnID;
+use super::utils::*;
+
+/// A function that is not ignored and has MIR.
+#[derive(Clone, Copy)]
+pub struct AnalysisFn<'tcx> {
+    pub fn_id: FnID,
+    pub body: &'tcx Body<'tcx>,
+}
+
+/// Functions of the current crate that have been examined by any pass.
+pub struct AnalysisCtxt<'tcx> {
+    tcx: TyCtxt<'tcx>,
+    /// Each examined function, or None if it is ignored or has no MIR.
+    fns: FxHashMap<DefId, Option<AnalysisFn<'tcx>>>,
+    /// Whether each crate of an examined function is ignored (see
+    /// ignore_crate()).
+    ignored_crates: FxHashMap<CrateNum, bool>,
+    /// Whether the answers above are cached; see context_cache_disabled().
+    cached: bool,
+}
+
+impl<'tcx> AnalysisCtxt<'tcx> {
+    pub fn new(tcx: TyCtxt<'tcx>) -> AnalysisCtxt<'tcx> {
+        AnalysisCtxt {
+            tcx,
+            fns: FxHashMap::default(),
+            ignored_crates: FxHashMap::default(),
+            cached: !context_cache_disabled(),
+        }
+    }
+
+    pub fn tcx(&self) -> TyCtxt<'tcx> {
+        self.tcx
+    }
+
+    /// Get the FnID and the MIR of a function, or None if it should not be
+    /// analyzed, i.e., it is ignored (see ignore_fn()) or has no MIR. A
+    /// function is only examined the first time it is queried.
+    pub fn get(&mut self, def_id: DefId) -> Option<AnalysisFn<'tcx>> {
+        if self.cached && let Some(func) = self.fns.get(&def_id) {
+            return *func;
+        }
+
+        // Only functions with a body in Rust can be analyzed, no matter what
+        // ABI they have.
+        let tcx = self.tcx;
+        let func = if self.ignore_fn(def_id) || !tcx.is_mir_available(def_id) {
+            None
+        } else {
+            Some(AnalysisFn {
+                fn_id: get_fn_fingerprint(tcx, def_id),
+                body: tcx.optimized_mir(def_id),
+            })
+        };
+        self.fns.insert(def_id, func);
+
+        func
+    }
+
+    /// Same as ignore_fn(), but whether the crate of the function is ignored
+    /// is only checked the first time a function of the crate is queried.
+    pub fn ignore_fn(&mut self, def_id: DefId) -> bool {
+        let tcx = self.tcx;
+        if !self.cached {
+            return ignore_fn(tcx, def_id);
+        }
+        let crate_ignored = *self.ignored_crates.entry(def_id.krate)
+            .or_insert_with(|| ignore_crate(tcx, def_id.krate));
+
+        crate_ignored || ignore_fn_item(tcx, def_id)
+    }
+}
@@ -1,6 +1,48 @@
 use rustc_data_structures::fx::{FxHashSet};
+use rustc_span::symbol::{sym, Symbol};
+use serde::Deserialize;
+use std::fs;
 
-// The set of native libraries provided by Rust.
+use super::error::SandboxResult;
+
+/// Entries added to the built-in database, e.g., for the allocator of a
+/// project, so that adjusting them does not require rebuilding the compiler.
+/// They are read from the JSON file pointed to by the environment variable
+/// SURUST_DB, e.g.,
+///
+/// ```json
+/// { "heap_alloc": ["alloc_page"], "native_libs": ["my_alloc"] }
+/// ```
+#[derive(Deserialize, Default, Debug, PartialEq)]
+#[serde(default)]
+pub struct ExtraDatabase {
+    /// Names of allocation functions added to HEAP_ALLOC. Unlike the built-in
+    /// ones, they are allocations no matter of which type they are methods.
+    pub heap_alloc: Vec<String>,
+    /// Crate names added to NATIVE_LIBS.
+    pub native_libs: Vec<String>,
+}
+
+/// Read the entries to add to the built-in database from a JSON file.
+pub(crate) fn read_extra_database(path: &str) -> SandboxResult<ExtraDatabase> {
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+// The extra entries of the database, read once at first use. There are none
+// if SURUST_DB is unset or the file cannot be read.
+lazy_static!{
+    pub static ref EXTRA_DB: ExtraDatabase = {
+        match std::env::var("SURUST_DB") {
+            Ok(path) => read_extra_database(&path).unwrap_or_else(|err| {
+                eprintln!("Sandbox: ignored the database {}: {}", path, err);
+                ExtraDatabase::default()
+            }),
+            Err(_) => ExtraDatabase::default()
+        }
+    };
+}
+
+// The set of native libraries provided by Rust, plus those in EXTRA_DB.
 lazy_static!{
     pub static ref NATIVE_LIBS: FxHashSet<String> = {
         let libs = vec![
@@ -21,19 +63,20 @@
             // Any others?
         ];
 
-        libs.into_iter().map(|x| x.to_string()).collect()
+        libs.into_iter().map(|x| x.to_string())
+            .chain(EXTRA_DB.native_libs.iter().cloned()).collect()
     };
 }
 
-// A set of heap allocation calls.
+// A set of heap allocation calls, plus those in EXTRA_DB.
 //
 // TODO: The currently list may be incomplete. A thorough study is needed.
 lazy_static!{
     pub static ref HEAP_ALLOC: FxHashSet<String> = {
         // The name ignors the crate and module and struct and only keeps the
-        // final method, e.g., "new" of "Box::<i32>::new". Perhaps when query
-        // this set, we should check where a method is from; we would otherwise
-        // run the risk of introducing false positives.
+        // final method, e.g., "new" of "Box::<i32>::new". A method is only an
+        // allocation if it is of a type in ALLOCATOR_TYPES (see
+        // utils::is_known_allocator()), so that e.g. NonNull::new is not.
         let allocs = vec![
             "new",
             "new_in",
@@ -49,11 +92,227 @@
             // Unsafe
             "from_raw_parts",
             "from_raw_parts_in",
-            // Others
+                          ];
+
+        allocs.into_iter().map(|x| x.to_string())
+            .chain(EXTRA_DB.heap_alloc.iter().cloned()).collect()
+    };
+}
+
+// The allocator traits, by their diagnostic items. A call to an allocation
+// method of them (see ALLOCATOR_TRAIT_FNS) allocates heap memory no matter
+// which type implements them, e.g., a custom #[global_allocator] like
+// jemalloc's or mimalloc's.
+lazy_static!{
+    pub static ref ALLOCATOR_TRAITS: FxHashSet<Symbol> = {
+        let traits = vec![
+            sym::GlobalAlloc,
+            sym::Allocator,
+                          ];
+
+        traits.into_iter().collect()
+    };
+}
+
+// The allocation methods of ALLOCATOR_TRAITS. Like HEAP_ALLOC, only the final
+// name of a method is kept.
+lazy_static!{
+    pub static ref ALLOCATOR_TRAIT_FNS: FxHashSet<String> = {
+        let allocs = vec![
+            // GlobalAlloc
+            "alloc",
+            "alloc_zeroed",
+            "realloc",
+            // Allocator
+            "allocate",
+            "allocate_zeroed",
+            "grow",
+            "grow_zeroed",
+            "shrink",
+                          ];
+
+        allocs.into_iter().map(|x| x.to_string()).collect()
+    };
+}
+
+// A set of types whose constructors in HEAP_ALLOC allocate heap memory, by
+// their diagnostic items. Box and String are lang items and are checked
+// separately.
+lazy_static!{
+    pub static ref ALLOCATOR_TYPES: FxHashSet<Symbol> = {
+        let types = vec![
+            sym::Vec,
+            sym::VecDeque,
+            sym::LinkedList,
+            sym::BinaryHeap,
+            sym::BTreeMap,
+            sym::BTreeSet,
+            sym::HashMap,
+            sym::HashSet,
+            sym::Rc,
+            sym::Arc,
+            sym::cstring_type,
+                          ];
+
+        types.into_iter().collect()
+    };
+}
+
+// A set of functions of the global allocator API that allocate memory. Unlike
+// HEAP_ALLOC, they are matched by the full path of a function (crate + module
+// + fn, see utils::get_fn_path()), as their final names, e.g., "alloc", are
+// too common. A method is named after its self type; see
+// utils::get_callee_path().
+// A reallocation is regarded as an allocation, as it may move the memory.
+lazy_static!{
+    pub static ref GLOBAL_ALLOC: FxHashSet<String> = {
+        let allocs = vec![
             // From something like vec![..]
-            "exchange_malloc"
+            "alloc::alloc::exchange_malloc",
+            // alloc::alloc, re-exported as std::alloc
+            "alloc::alloc::alloc",
+            "alloc::alloc::alloc_zeroed",
+            "alloc::alloc::realloc",
+            // Allocator for Global
+            "alloc::alloc::Global::allocate",
+            "alloc::alloc::Global::allocate_zeroed",
+            "alloc::alloc::Global::grow",
+            "alloc::alloc::Global::grow_zeroed",
+            // GlobalAlloc for System
+            "std::alloc::System::alloc",
+            "std::alloc::System::alloc_zeroed",
+            "std::alloc::System::realloc",
                           ];
 
         allocs.into_iter().map(|x| x.to_string()).collect()
     };
 }
+
+// A set of functions of the global allocator API that deallocate memory, by
+// full path like GLOBAL_ALLOC. The first argument is the pointer to be freed.
+lazy_static!{
+    pub static ref GLOBAL_DEALLOC: FxHashSet<String> = {
+        let deallocs = vec![
+            "alloc::alloc::dealloc",
+            "alloc::alloc::Global::deallocate",
+            "std::alloc::System::dealloc",
+                          ];
+
+        deallocs.into_iter().map(|x| x.to_string()).collect()
+    };
+}
+
+// A set of pointer arithmetic methods of raw pointers, e.g., "add" of
+// "<*mut T>::add". The first argument is the pointer and the second one is the
+// offset.
+lazy_static!{
+    pub static ref PTR_ARITH: FxHashSet<String> = {
+        let ptr_arith = vec![
+            "offset",
+            "add",
+            "sub",
+            "wrapping_offset",
+            "wrapping_add",
+            "wrapping_sub",
+            "byte_offset",
+            "byte_add",
+            "byte_sub",
+            "arith_offset",  // Intrinsic
+                          ];
+
+        ptr_arith.into_iter().map(|x| x.to_string()).collect()
+    };
+}
+
+// The path of ptr::drop_in_place, which is one of FREE_FNS and whose calls are
+// also recorded as DefSite::DropInPlace.
+pub(crate) const DROP_IN_PLACE: &str = "core::ptr::drop_in_place";
+
+// A set of deallocation calls. A call to one of them frees (or takes back the
+// ownership of, e.g., Box::from_raw) the memory its first argument points to.
+//
+// Like GLOBAL_ALLOC, a function is kept by its full path; see
+// utils::get_fn_path(). The global allocator API is in GLOBAL_DEALLOC.
+lazy_static!{
+    pub static ref FREE_FNS: FxHashSet<String> = {
+        let frees = vec![
+            DROP_IN_PLACE,
+            "alloc::boxed::Box::from_raw",
+            "alloc::boxed::Box::from_raw_in",
+            "alloc::rc::Rc::from_raw",
+            "alloc::rc::Rc::from_raw_in",
+            "alloc::sync::Arc::from_raw",
+            "alloc::sync::Arc::from_raw_in",
+            "alloc::ffi::c_str::CString::from_raw",
+                        ];
+
+        frees.into_iter().map(|x| x.to_string()).collect()
+    };
+}
+
+// A set of native deallocation functions, e.g., free() of libc. A foreign item
+// has no path to match, so only its name is kept, and a function of the same
+// name is one of them only if it is a foreign item.
+lazy_static!{
+    pub static ref FOREIGN_FREE_FNS: FxHashSet<String> = {
+        let frees = vec![
+            "free",
+                        ];
+
+        frees.into_iter().map(|x| x.to_string()).collect()
+    };
+}
+
+// A set of calls that create a value that may be invalid for its type, e.g.,
+// "mem::zeroed::<&u8>()" creates a null reference. Reading such a value is
+// undefined behavior unless it is properly initialized first.
+//
+// Like HEAP_ALLOC, only the final name of a function is kept.
+lazy_static!{
+    pub static ref UNINIT_FNS: FxHashSet<String> = {
+        let uninits = vec![
+            // core::mem
+            "uninitialized",
+            "zeroed",
+            // MaybeUninit
+            "assume_init",
+            "assume_init_read",
+                          ];
+
+        uninits.into_iter().map(|x| x.to_string()).collect()
+    };
+}
+
+// A set of calls that reinterpret the bits of a value as another type, e.g.,
+// "mem::transmute::<usize, *mut u8>(addr)" creates a pointer from an integer.
+// Optimized MIR usually has "transmute" lowered to a Transmute cast (see
+// utils::def_site_from_rvalue()), but "transmute_copy" stays a call.
+//
+// Like HEAP_ALLOC, only the final name of a function is kept.
+lazy_static!{
+    pub static ref TRANSMUTE: FxHashSet<String> = {
+        let transmutes = vec![
+            // core::intrinsics, re-exported as core::mem
+            "transmute",
+            // core::mem
+            "transmute_copy",
+                          ];
+
+        transmutes.into_iter().map(|x| x.to_string()).collect()
+    };
+}
+
+// A set of calls that build a slice from a raw pointer and a length, neither
+// of which is checked, by their full paths like GLOBAL_ALLOC. "from_raw_parts"
+// in HEAP_ALLOC is the method of an allocating type, e.g., Vec::from_raw_parts.
+lazy_static!{
+    pub static ref RAW_SLICE: FxHashSet<String> = {
+        let raw_slices = vec![
+            // core::slice::raw, re-exported as core::slice
+            "core::slice::raw::from_raw_parts",
+            "core::slice::raw::from_raw_parts_mut",
+                          ];
+
+        raw_slices.into_iter().map(|x| x.to_string()).collect()
+    };
+}
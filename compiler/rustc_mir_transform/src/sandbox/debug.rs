@@ -5,120 +5,143 @@
 
 use super::summarize_fn::{DefSite};
 
-// This function whitelist is a helper for development only.
+// The functions to analyze, by name, from the comma-separated list in the
+// environment variable SURUST_ONLY_FNS, e.g., "foo,bar". It is a helper for
+// development only. If it is empty, which is the default, all the functions
+// that are not ignored otherwise are analyzed; see utils::ignore_fn_name().
 lazy_static!{
-    pub static ref FN_TO_PROCESS: FxHashSet<String> = {
-        let mut vec = Vec::new();
-        vec.push("foo".to_string());
-        vec.push("bar".to_string());
-        vec.push("f0".to_string());
-        vec.push("f1".to_string());
-
-        // For exa (https://github.com/ogham/exa)
-        vec.push("add_files_to_table".to_string());
-        vec.push("translate_attribute_name".to_string());
-        vec.push("listxattr_first".to_string());
-
-        // For qdrant
-        vec.push("vf_to_u8".to_string());
-        vec.push("open_read".to_string());
-        vec.push("open_write".to_string());
-        vec.push("iter_points".to_string());
+    pub static ref FN_TO_PROCESS: FxHashSet<String> =
+        parse_fn_list(&std::env::var("SURUST_ONLY_FNS").unwrap_or_default());
+}
 
-        vec.into_iter().collect()
-    };
+/// Parse a comma-separated list of function names.
+pub(crate) fn parse_fn_list(list: &str) -> FxHashSet<String> {
+    list.split(',').map(|name| name.trim()).filter(|name| !name.is_empty())
+        .map(|name| name.to_string()).collect()
 }
 
 
+/// The tracing target of all the debugging output of this module. The output
+/// goes to the compiler's log (stderr) instead of stdout, and can be enabled by
+/// RUSTC_LOG=sandbox::debug=debug.
+const DEBUG_TARGET: &str = "sandbox::debug";
+
 /// Print a Statement for debugging.
 #[allow(dead_code)]
 #[inline(always)]
 pub(crate) fn print_stmt_assign<'tcx>(stmt: &Statement<'tcx>, rvalue: &Rvalue<'tcx>) {
-    print!("[Assign]: {:?}; ", stmt);
-    match rvalue {
-        Rvalue::Use(operand) => {print_operand("Use", operand)},
+    let rvalue_str = match rvalue {
+        Rvalue::Use(operand) => {
+            format!("[Use]: {:?}", operand)
+        },
         Rvalue::Repeat(operand, num) => {
-            println!("Repeat {:?} {} times.", operand, num);
+            format!("Repeat {:?} {} times.", operand, num)
         },
         Rvalue::Ref(.., place) => {
-            println!("Ref: {:?}", place);
+            format!("Ref: {:?}", place)
         },
         Rvalue::ThreadLocalRef(def_id) => {
-            println!("[ThreadLocalRef]: DefId = {:?}", def_id);
+            format!("[ThreadLocalRef]: DefId = {:?}", def_id)
         },
         Rvalue::AddressOf(mutability, place) => {
-            println!("[AddressOf]: {:?} {:?}", mutability, place);
+            format!("[AddressOf]: {:?} {:?}", mutability, place)
         },
         Rvalue::Len(place) => {
-            println!("[Len]: {:?}", place);
+            format!("[Len]: {:?}", place)
         },
         Rvalue::Cast(_cast_kind, operand, ty) => {
-            println!("[Cast]: {:?} to Type {:?}", operand, ty);
+            format!("[Cast]: {:?} to Type {:?}", operand, ty)
         },
         Rvalue::BinaryOp(bin_op, box (op1, op2))
         | Rvalue::CheckedBinaryOp(bin_op, box (op1, op2)) => {
-            println!("[BinaryOp]: {:?} on {:?} and {:?}", bin_op, op1, op2);
+            format!("[BinaryOp]: {:?} on {:?} and {:?}", bin_op, op1, op2)
         },
         Rvalue::NullaryOp(null_op, ty) => {
-            println!("[NullaryOp]: {:?}, {:?}", null_op, ty);
+            format!("[NullaryOp]: {:?}, {:?}", null_op, ty)
         },
         Rvalue::UnaryOp(un_op, operand) => {
-            println!("[UnaryOp]: {:?} {:?}", un_op, operand);
+            format!("[UnaryOp]: {:?} {:?}", un_op, operand)
         },
         Rvalue::Discriminant(place) => {
-            println!("[Discriminant]: {:?}", place);
+            format!("[Discriminant]: {:?}", place)
         },
         Rvalue::Aggregate(box kind, operands) => {
-            println!("[Aggregate]: kind = {:?}, operands = {:?}", kind, operands);
+            format!("[Aggregate]: kind = {:?}, operands = {:?}", kind, operands)
         },
         Rvalue::ShallowInitBox(operand, ty) => {
-            println!("[ShallowInitBox]: operand = {:?}, ty = {:?}", operand, ty);
+            format!("[ShallowInitBox]: operand = {:?}, ty = {:?}", operand, ty)
         }
-    }
+        Rvalue::CopyForDeref(place) => {
+            format!("[CopyForDeref]: {:?}", place)
+        }
+    };
+    debug!(target: DEBUG_TARGET, "[Assign]: {:?}; {}", stmt, rvalue_str);
 }
 
 #[allow(dead_code)]
 #[inline(always)]
 pub(crate) fn print_stmt(type_name: &str, stmt: &Statement<'_>) {
-    println!("[{}]: {:?}", type_name, stmt);
+    debug!(target: DEBUG_TARGET, "[{}]: {:?}", type_name, stmt);
 }
 
 #[allow(dead_code)]
 #[inline(always)]
 pub(crate) fn print_operand<'tcx>(type_name: &str, operand: &Operand<'tcx>) {
-    println!("[{}]: {:?}", type_name, operand);
+    debug!(target: DEBUG_TARGET, "[{}]: {:?}", type_name, operand);
 }
 
 #[allow(dead_code)]
 #[inline(always)]
 pub(crate) fn print_terminator(type_name: &str, terminator: &Terminator<'_>) {
-    println!("[{}]: {:?}", type_name, terminator.kind);
+    debug!(target: DEBUG_TARGET, "[{}]: {:?}", type_name, terminator.kind);
 }
 
 
 #[allow(dead_code)]
 #[inline(always)]
 pub(crate) fn print_local(type_name: &str, local: Local) {
-    println!("{:?} is a {}", local, type_name);
+    debug!(target: DEBUG_TARGET, "{:?} is a {}", local, type_name);
 }
 
 /// Print all the unsafe allocation sites of a function.
 #[allow(dead_code)]
 #[inline(always)]
 pub(crate) fn print_unsafe_def(results: &FxHashSet::<DefSite>) {
-    println!("Unsafe def sites:");
+    debug!(target: DEBUG_TARGET, "Unsafe def sites:");
 
     for site in results.iter() {
         match &site {
             DefSite::HeapAlloc(bb) | DefSite::NativeCall(bb) |
                 DefSite::OtherCall(bb) => {
-                println!("Call at BB {}", bb);
+                debug!(target: DEBUG_TARGET, "Call at BB {}", bb);
+            },
+            DefSite::DropInPlace(bb) => {
+                debug!(target: DEBUG_TARGET, "drop_in_place at BB {}", bb);
+            },
+            DefSite::Uninit(bb) => {
+                debug!(target: DEBUG_TARGET, "Uninit value at BB {}", bb);
             },
             DefSite::Arg(arg) => {
-                println!("Argument: {:?}", arg);
+                debug!(target: DEBUG_TARGET, "Argument: {:?}", arg);
+            },
+            DefSite::Global(def_id) => {
+                debug!(target: DEBUG_TARGET, "Global: {:?}", def_id);
+            },
+            DefSite::UnionRead(bb) => {
+                debug!(target: DEBUG_TARGET, "Union field read at BB {}", bb);
+            },
+            DefSite::Transmute(bb) => {
+                debug!(target: DEBUG_TARGET, "Transmute at BB {}", bb);
+            },
+            DefSite::RawSlice(bb) => {
+                debug!(target: DEBUG_TARGET, "Raw slice at BB {}", bb);
+            },
+            DefSite::IntToPtr(bb) => {
+                debug!(target: DEBUG_TARGET, "Integer-to-pointer cast at BB {}", bb);
+            },
+            DefSite::BoxAlloc(bb) => {
+                debug!(target: DEBUG_TARGET, "Box allocated in place at BB {}", bb);
             }
         }
     }
-
-    println!("");
 }
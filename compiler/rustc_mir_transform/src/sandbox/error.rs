@@ -0,0 +1,83 @@
+//! Errors of the sandboxing unsafe code module.
+//!
+//! The analysis runs inside the compiler on arbitrary code bases. A failure of
+//! it, e.g., a missing or corrupted summary file, should be reported and then
+//! skipped instead of crashing the compiler. The entry points of the module
+//! return a SandboxError for the driver to log.
+
+use std::fmt;
+use std::io;
+
+/// An error of the sandboxing unsafe code module.
+#[derive(Debug)]
+pub enum SandboxError {
+    /// Failed to read or write a summary, result, or report file.
+    SummaryIo(io::Error),
+    /// A summary, result, or archive file is not valid JSON of its type.
+    Deserialize(serde_json::Error),
+    /// A callee needed by the WPA has no summary, e.g., because the summary
+    /// file of its crate is missing.
+    MissingCallee { callee: String, caller: String },
+    /// A file written by an incompatible version of the analysis.
+    SchemaMismatch { expected: u32, found: u32 },
+    /// An environment variable that configures the analysis has an invalid
+    /// value.
+    InvalidEnv { name: String, value: String },
+    /// An access site without a stable key, i.e., not written with
+    /// SANDBOX_STABLE_KEYS=1, in a file to merge.
+    MissingStableKey { line: String },
+    /// A def site of a kind that cannot appear where it is found, e.g., an
+    /// argument among the non-arg def sites of a return value.
+    UnexpectedDefSite { def_site: String, fn_name: String },
+    /// A call whose callee resolves to no function.
+    UnresolvedCall { bb: u32, caller: String },
+    /// More than one main() in a crate, so it is unknown whether to run the
+    /// WPA.
+    MultipleMains { count: usize },
+}
+
+pub type SandboxResult<T> = Result<T, SandboxError>;
+
+impl fmt::Display for SandboxError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SandboxError::SummaryIo(err) => write!(f, "I/O error: {}", err),
+            SandboxError::Deserialize(err) => write!(f, "invalid file: {}", err),
+            SandboxError::MissingCallee { callee, caller } => {
+                write!(f, "cannot find the summary of {}, called by {}", callee, caller)
+            },
+            SandboxError::SchemaMismatch { expected, found } => {
+                write!(f, "unsupported schema version {} (expected {})", found, expected)
+            },
+            SandboxError::InvalidEnv { name, value } => {
+                write!(f, "invalid value {:?} of {}", value, name)
+            },
+            SandboxError::MissingStableKey { line } => {
+                write!(f, "no stable key in access site {:?}", line)
+            },
+            SandboxError::UnexpectedDefSite { def_site, fn_name } => {
+                write!(f, "unexpected def site {} in {}", def_site, fn_name)
+            },
+            SandboxError::UnresolvedCall { bb, caller } => {
+                write!(f, "cannot resolve the callee at BB{} of {}", bb, caller)
+            },
+            SandboxError::MultipleMains { count } => {
+                write!(f, "{} main() in one crate", count)
+            },
+        }
+    }
+}
+
+impl std::error::Error for SandboxError {}
+
+impl From<io::Error> for SandboxError {
+    fn from(err: io::Error) -> SandboxError {
+        SandboxError::SummaryIo(err)
+    }
+}
+
+impl From<serde_json::Error> for SandboxError {
+    fn from(err: serde_json::Error) -> SandboxError {
+        SandboxError::Deserialize(err)
+    }
+}
@@ -0,0 +1,159 @@
+//! Merge the whole-program analysis results of multiple builds of the same
+//! program, e.g., one build per target triple, and report the findings that
+//! only appear on some of the targets. Such findings usually come from
+//! cfg-gated unsafe code.
+//!
+//! The results of the binary crates of a workspace can also be merged into a
+//! single result, so that the unsafe accesses of a library crate shared by
+//! them are found for all of them.
+//!
+//! The findings of the targets are the unsafe accesses in their access site
+//! files written with SANDBOX_STABLE_KEYS=1. A finding is keyed by its stable
+//! key, i.e., the FnID of its function plus a hash of its source code. Unlike
+//! a DefSite, whose BB index changes when cfg-gated code elsewhere in the
+//! function adds or removes blocks, the key is the same on all the targets.
+
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use std::fs;
+use std::path::PathBuf;
+
+use super::error::{SandboxError, SandboxResult};
+use super::summarize_fn::FnID;
+use super::unsafe_access::read_wpa_file_with_reachable;
+use super::utils::get_wp_summary_path;
+use super::wpa::{serialize_wp_summary, WPSummary};
+
+/// The stable key of an unsafe access; see unsafe_access::get_stable_key().
+pub type Finding = String;
+
+/// Combined WPA results of multiple targets.
+pub struct MergedReport {
+    /// Names (usually target triples) of all the merged targets.
+    pub targets: Vec<String>,
+    /// Each finding and the targets on which it is found.
+    pub findings: FxHashMap<Finding, FxHashSet<String>>,
+    /// The access site of each finding, as written on the first target on
+    /// which it is found.
+    pub sites: FxHashMap<Finding, String>,
+}
+
+impl MergedReport {
+    /// Get the findings that appear on some but not all of the targets, sorted
+    /// by their keys, which keeps the findings of a function together.
+    pub fn target_specific(&self) -> Vec<(&Finding, &FxHashSet<String>)> {
+        let mut results = Vec::new();
+        for (finding, targets) in &self.findings {
+            if targets.len() < self.targets.len() {
+                results.push((finding, targets));
+            }
+        }
+        results.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        results
+    }
+
+    /// Format the target-specific findings to a human-readable report.
+    pub fn format(&self) -> String {
+        let mut report = "Merged targets: ".to_owned() + &self.targets.join(", ") + "\n";
+        let specific = self.target_specific();
+        report += &format!("Target-specific findings: {}\n", specific.len());
+        for (finding, targets) in specific {
+            let mut targets = targets.iter().map(|t| t.as_str()).collect::<Vec<_>>();
+            targets.sort();
+            report += &format!("{} #{}: only on {}\n", self.sites[finding], finding,
+                targets.join(", "));
+        }
+
+        report
+    }
+}
+
+/// Parse an access site file written with SANDBOX_STABLE_KEYS=1 into pairs of
+/// (stable key, access site). Fail on an access without a key, as it cannot be
+/// matched with the accesses of the other targets.
+pub fn parse_access_sites(content: &str) -> SandboxResult<Vec<(Finding, String)>> {
+    let mut findings = Vec::new();
+    for line in content.lines().filter(|line| !line.is_empty()) {
+        let Some((site, key)) = line.rsplit_once(" #") else {
+            return Err(SandboxError::MissingStableKey { line: line.to_owned() });
+        };
+        findings.push((key.to_owned(), site.to_owned()));
+    }
+
+    Ok(findings)
+}
+
+/// Merge the in-memory findings of multiple targets.
+///
+/// Inputs:
+/// @reports: Pairs of (target name, (stable key, access site) of each unsafe
+///           access found on the target).
+pub fn merge_targets(reports: Vec<(String, Vec<(Finding, String)>)>) -> MergedReport {
+    let mut merged = MergedReport {
+        targets: Vec::with_capacity(reports.len()),
+        findings: FxHashMap::default(),
+        sites: FxHashMap::default(),
+    };
+
+    for (target, findings) in reports {
+        for (finding, site) in findings {
+            merged.findings.entry(finding.clone()).or_default().insert(target.clone());
+            merged.sites.entry(finding).or_insert(site);
+        }
+        merged.targets.push(target);
+    }
+
+    merged
+}
+
+/// Entrance of this module. Read the access site file of each target and merge
+/// them.
+///
+/// Inputs:
+/// @reports: Pairs of (target name, path of the access site file of the
+///           target, written with SANDBOX_STABLE_KEYS=1).
+pub fn merge_target_reports(reports: &[(String, PathBuf)]) -> SandboxResult<MergedReport> {
+    let mut findings = Vec::with_capacity(reports.len());
+    for (target, path) in reports {
+        findings.push((target.clone(), parse_access_sites(&fs::read_to_string(path)?)?));
+    }
+
+    Ok(merge_targets(findings))
+}
+
+/// Union the in-memory WPA results of multiple binary crates, i.e., the unsafe
+/// sources of a function are those found in any of the results.
+pub fn union_wp_summaries(wp_summaries: Vec<WPSummary>) -> WPSummary {
+    let mut merged = WPSummary::default();
+    for wp_summary in wp_summaries {
+        for (fn_id, def_sites) in wp_summary {
+            merged.entry(fn_id).or_default().extend(def_sites);
+        }
+    }
+
+    merged
+}
+
+/// Read the WPA result files of multiple binary crates, e.g., those written
+/// with SANDBOX_WP_SUMMARY_PER_CRATE=1, and union them. A function is
+/// reachable if it is reachable in any of them.
+pub fn merge_wp_summaries(paths: &[PathBuf]) -> SandboxResult<(WPSummary, FxHashSet<FnID>)> {
+    let mut wp_summaries = Vec::with_capacity(paths.len());
+    let mut reachable = FxHashSet::<FnID>::default();
+    for path in paths {
+        let (wp_summary, wp_reachable) = read_wpa_file_with_reachable(path)?;
+        wp_summaries.push(wp_summary);
+        reachable.extend(wp_reachable);
+    }
+
+    Ok((union_wp_summaries(wp_summaries), reachable))
+}
+
+/// Merge the WPA result files of multiple binary crates and write the result
+/// to where the later compilation of each crate reads it from.
+pub fn write_merged_wp_summary(paths: &[PathBuf]) -> SandboxResult<()> {
+    let (wp_summary, reachable) = merge_wp_summaries(paths)?;
+    fs::write(get_wp_summary_path(), serialize_wp_summary(&wp_summary, &reachable)?)?;
+
+    Ok(())
+}
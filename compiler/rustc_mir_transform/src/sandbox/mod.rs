@@ -11,6 +11,14 @@
 pub mod summarize_fn;
 pub mod wpa;
 pub mod unsafe_access;
+pub mod merge;
+pub mod archive;
+pub mod context;
+pub mod error;
+pub mod stats;
 pub(crate) mod utils;
 pub(crate) mod debug;
 pub(crate) mod database;
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,113 @@
+//! Statistics of the analysis of each crate, written as JSON next to the
+//! whole-program summary so that the coverage of the analysis can be tracked
+//! across versions of it.
+//!
+//! The counts of functions and unsafe sources are filled by the WPA, and the
+//! number of unsafe dereferences of a crate is filled later when the crate is
+//! compiled again to find unsafe memory accesses. The numbers of functions
+//! selected with SANDBOX_UNSAFE_ROOTS_ONLY=1 are filled before the WPA, when
+//! each crate is summarized.
+
+use rustc_data_structures::flock::Lock;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+use super::error::SandboxResult;
+use super::utils::{get_local_crate_name, get_stats_path};
+
+/// Statistics of a crate.
+#[derive(Serialize, Deserialize, Default, Debug, Clone, PartialEq)]
+#[serde(default)]
+pub struct Stats {
+    /// Functions with a Summary.
+    pub fns_analyzed: usize,
+    /// Functions with any unsafe source.
+    pub unsafe_fns: usize,
+    /// Unsafe heap allocation sites.
+    pub heap_alloc_sites: usize,
+    /// Unsafe native calls.
+    pub native_calls: usize,
+    /// Unsafe calls to Rust functions that return an unsafe source.
+    pub other_call_sources: usize,
+    /// Unsafe function arguments.
+    pub arg_sources: usize,
+    /// Unsafe sources of the other kinds: uninitialized values, globals,
+    /// union reads, transmutes, raw slices and integer-to-pointer casts.
+    pub uninit_sources: usize,
+    pub global_sources: usize,
+    pub union_read_sources: usize,
+    pub transmute_sources: usize,
+    pub raw_slice_sources: usize,
+    pub int_to_ptr_sources: usize,
+    /// Def sites of pointers that are only read, and of those written to.
+    pub const_sources: usize,
+    pub mut_sources: usize,
+    /// Functions with inline assembly, and functions calling intrinsics.
+    pub inline_asm_fns: usize,
+    pub intrinsic_call_fns: usize,
+    /// Unsafe memory dereferences.
+    pub unsafe_derefs: u32,
+    /// Functions collected to be summarized, and those of them selected with
+    /// SANDBOX_UNSAFE_ROOTS_ONLY=1. Both are 0 if the option is off.
+    pub fns_collected: usize,
+    pub fns_selected: usize,
+}
+
+/// Statistics of each crate, by crate name.
+pub type CrateStats = BTreeMap<String, Stats>;
+
+/// Read the statistics file. It is empty if the file does not exist.
+pub(crate) fn read_stats(path: &str) -> SandboxResult<CrateStats> {
+    if !Path::new(path).exists() {
+        return Ok(CrateStats::default());
+    }
+
+    Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+}
+
+/// Update the statistics file. The rustc processes of a build may update it
+/// in parallel, so the update is done under a lock.
+pub(crate) fn update_stats(update: impl FnOnce(&mut CrateStats)) -> SandboxResult<()> {
+    let path = get_stats_path();
+    let _lock = Lock::new(Path::new(&(path.clone() + ".lock")), true, true, true)?;
+    let mut stats = read_stats(&path)?;
+    update(&mut stats);
+    fs::write(&path, serde_json::to_string_pretty(&stats)?)?;
+
+    Ok(())
+}
+
+/// Write the statistics of all the crates computed by the WPA. They replace
+/// those of an earlier build, as the WPA runs before unsafe dereferences are
+/// counted. The numbers of functions selected for each crate are kept, as they
+/// are written when the crate is summarized, before the WPA.
+pub(crate) fn write_wpa_stats(mut wpa_stats: CrateStats) -> SandboxResult<()> {
+    update_stats(|stats| {
+        for (crate_name, crate_stats) in wpa_stats.iter_mut() {
+            if let Some(old) = stats.get(crate_name) {
+                crate_stats.fns_collected = old.fns_collected;
+                crate_stats.fns_selected = old.fns_selected;
+            }
+        }
+        *stats = wpa_stats;
+    })
+}
+
+/// Record the number of functions of the current crate collected to be
+/// summarized, and the number of them selected with SANDBOX_UNSAFE_ROOTS_ONLY=1.
+pub fn write_selected_fns(fns_collected: usize, fns_selected: usize) -> SandboxResult<()> {
+    update_stats(|stats| {
+        let crate_stats = stats.entry(get_local_crate_name()).or_default();
+        crate_stats.fns_collected = fns_collected;
+        crate_stats.fns_selected = fns_selected;
+    })
+}
+
+/// Record the number of unsafe dereferences of the current crate.
+pub fn write_unsafe_derefs(unsafe_derefs: u32) -> SandboxResult<()> {
+    update_stats(|stats| {
+        stats.entry(get_local_crate_name()).or_default().unsafe_derefs = unsafe_derefs;
+    })
+}
@@ -2,20 +2,22 @@
 //! argument of each callee, and def site(s) for the return value.
 
 use rustc_middle::mir::*;
-use rustc_middle::ty::{self, TyCtxt, InstanceDef};
+use rustc_middle::ty::{self, Ty, TyCtxt, InstanceDef, VtblEntry, TypeVisitableExt};
+use rustc_middle::ty::adjustment::PointerCoercion;
 use rustc_hir::def_id::{DefId};
 use rustc_data_structures::fx::{FxHashSet, FxHashMap};
 
+use crate::sandbox::error::{SandboxError, SandboxResult};
 use crate::sandbox::utils::*;
-use super::{DefSite, Summary, Callee};
+use super::{DefSite, FnID, Summary, Callee};
 
 static _DEBUG: bool = false;
 
 impl Callee {
     /// Add a new pair of (bb, arg_defs) to a Calle's arg_defs.
-    fn add_arg_def_slot<'tcx>(&mut self, args: &Vec<Operand<'tcx>>, bb: u32) {
-        let mut arg_defs = Vec::with_capacity(args.len());
-        for _ in 0..args.len() {
+    fn add_arg_def_slot(&mut self, arg_num: usize, bb: u32) {
+        let mut arg_defs = Vec::with_capacity(arg_num);
+        for _ in 0..arg_num {
             arg_defs.push(FxHashSet::default());
         }
         self.arg_defs.insert(bb, arg_defs);
@@ -23,16 +25,16 @@ fn add_arg_def_slot<'tcx>(&mut self, args: &Vec<Operand<'tcx>>, bb: u32) {
 }
 
 impl Summary {
-    /// Get the target Callee by DefId from the vector of Callee used by a fn.
+    /// Get the target Callee by FnID from the vector of Callee used by a fn.
     ///
     /// This may not be that slow as it looks because a function usually only has
     /// a limited number of callees. We did not use a HashSet for Summary.callees
     /// because HashSet does not support get_mut(). We also did not use
     /// HashMap<DefId, Callee> because serializing it will generate illegal JSON
     /// ("key must be a string").
-    fn get_callee_local(&mut self, def_id: DefId) -> Option<&mut Callee> {
+    fn get_callee_local(&mut self, fn_id: FnID) -> Option<&mut Callee> {
         for callee in self.callees.iter_mut() {
-            if break_def_id(def_id) == callee.def_id {
+            if fn_id == callee.fn_id {
                 return Some(callee);
             }
         }
@@ -40,13 +42,60 @@ fn get_callee_local(&mut self, def_id: DefId) -> Option<&mut Callee> {
         None
     }
 
+    /// Record a method in a vtable created by this function.
+    fn add_vtable_fn<'tcx>(&mut self, tcx: TyCtxt<'tcx>, def_id: DefId) {
+        let fn_id = get_fn_fingerprint(tcx, def_id);
+        if self.vtable_fns.iter().any(|method| method.fn_id == fn_id) {
+            return;
+        }
+        self.vtable_fns.push(Callee {
+            fn_id: fn_id,
+            fn_name: get_fn_name(def_id),
+            crate_name: get_crate_name(def_id),
+            def_id: Some(break_def_id(def_id)),
+            arg_defs: FxHashMap::default()
+        });
+    }
+
+    /// Record a closure that is coerced to a Fn/FnMut/FnOnce trait object by
+    /// this function, with the number of arguments of the closure body.
+    fn add_dyn_fn_closure<'tcx>(&mut self, tcx: TyCtxt<'tcx>, def_id: DefId,
+                                arg_count: u32) {
+        let fn_id = get_fn_fingerprint(tcx, def_id);
+        if self.dyn_fn_closures.iter().any(|(closure, _)| closure.fn_id == fn_id) {
+            return;
+        }
+        self.dyn_fn_closures.push((Callee {
+            fn_id: fn_id,
+            fn_name: get_fn_name(def_id),
+            crate_name: get_crate_name(def_id),
+            def_id: Some(break_def_id(def_id)),
+            arg_defs: FxHashMap::default()
+        }, arg_count));
+    }
+
+    /// Record a function or closure coerced to a fn pointer by this function.
+    fn add_fn_ptr_fn<'tcx>(&mut self, tcx: TyCtxt<'tcx>, def_id: DefId) {
+        let fn_id = get_fn_fingerprint(tcx, def_id);
+        if self.fn_ptr_fns.iter().any(|f| f.fn_id == fn_id) {
+            return;
+        }
+        self.fn_ptr_fns.push(Callee {
+            fn_id: fn_id,
+            fn_name: get_fn_name(def_id),
+            crate_name: get_crate_name(def_id),
+            def_id: Some(break_def_id(def_id)),
+            arg_defs: FxHashMap::default()
+        });
+    }
+
     /// Update Callee.arg_defs by adding a new DefSite.
     ///
     /// Inputs:
-    /// @call: The (BasicBlock, DefId) of the target callee.
+    /// @call: The (BasicBlock, FnID) of the target callee.
     /// @index: Index of the argument in Callee.arg_defs.
     /// @site: A new DefSite
-    fn update_arg_defs(&mut self, call: (u32, DefId),
+    fn update_arg_defs(&mut self, call: (u32, FnID),
                        index: usize, site: DefSite) {
         let callee = self.get_callee_local(call.1).unwrap();
         // The next unwrap is safe as analyze_fn() processes each call.
@@ -56,13 +105,55 @@ fn update_arg_defs(&mut self, call: (u32, DefId),
 
 }
 
-/// Get the Local of the Place of the return value of a function call, if it
-/// does not return an empty tuple or divert.
-fn get_non_empty_ret<'tcx>(ret: Place<'tcx>, body: &Body<'tcx>) -> Option<Local> {
-    if is_empty_ty(body.local_decls[ret.local].ty) {
-        return None;
-    } else {
-        return Some(ret.local);
+/// Replace the return Local of a call in @places by the Local used in the
+/// call's arguments. The arguments are collected before the return Local is
+/// removed, so for a call like "_5 = foo(_5, _6)", _5 is kept to find its def
+/// sites before the call.
+///
+/// Inputs:
+/// @args: Arguments of the call.
+/// @ret: The Local that the call's return value is assigned to.
+/// @places: Place that contribute to the target Place.
+fn replace_ret_with_args<'tcx>(args: &[Operand<'tcx>], ret: Local,
+                               places: &mut UnsafePlaces) {
+    let mut arg_locals = FxHashSet::<Local>::default();
+    get_local_in_args(args, &mut arg_locals);
+    if !arg_locals.contains(&ret) {
+        places.remove_local(&ret);
+    }
+    places.insert_locals(arg_locals);
+}
+
+/// Add the Place used by @rvalue to @places. If only some fields (@part) of
+/// the Local that @rvalue defines contribute to the target Place and @rvalue
+/// builds a struct or tuple, only the operands of those fields are added, as
+/// in find_unsafe_def_core(); see get_field_operands().
+fn add_rvalue_places<'tcx>(rvalue: &Rvalue<'tcx>, part: Option<&LocalPart>,
+                           places: &mut UnsafePlaces) {
+    let mut rvalue_places = Vec::new();
+    match (part, get_field_operands(rvalue)) {
+        (Some(LocalPart::Fields(fields)), Some(operands)) => {
+            for (field, operand) in operands.iter_enumerated() {
+                if fields.contains(&field.as_u32()) {
+                    get_place_in_operand(operand, &mut rvalue_places);
+                }
+            }
+        },
+        _ => {
+            get_place_in_rvalue(rvalue, &mut rvalue_places);
+        }
+    }
+    for place in rvalue_places {
+        places.insert_place(&place);
+    }
+}
+
+/// Check if a write to @place defines none of @part, i.e., it writes a field
+/// of a Local of which only other fields contribute to the target Place.
+fn is_untracked_field<'tcx>(place: &Place<'tcx>, part: Option<&LocalPart>) -> bool {
+    match (PlaceKey::of(place).field, part) {
+        (Some(field), Some(LocalPart::Fields(fields))) => !fields.contains(&field),
+        _ => false
     }
 }
 
@@ -73,13 +164,14 @@ fn get_non_empty_ret<'tcx>(ret: Place<'tcx>, body: &Body<'tcx>) -> Option<Local>
 /// Inputs:
 /// @bb: Currently processed BasicBlock.
 /// @body: Body of the processed function.
-/// @call: (BasicBlock, DefId) of the currently processed call of a callee.
-/// @locals: Local (Place) that contributes to the arguments of the call.
+/// @call: (BasicBlock, FnID) of the currently processed call of a callee.
+/// @locals: Place (a Local or a field of it) that contribute to each argument
+///          of the call.
 /// @visited: Already processed BB.
 /// @summary: Summary of the target function.
 fn find_arg_def<'tcx>(bb: BasicBlock, body: &Body<'tcx>,
-                      call: (u32, DefId),
-                      locals: &mut Vec<FxHashSet<Local>>,
+                      call: (u32, FnID),
+                      locals: &mut Vec<UnsafePlaces>,
                       visited: &mut FxHashSet<BasicBlock>,
                       summary: &mut Summary) {
     if !visited.insert(bb) || locals.is_empty() { return; }
@@ -87,46 +179,96 @@ fn find_arg_def<'tcx>(bb: BasicBlock, body: &Body<'tcx>,
     let bbd = &body.basic_blocks()[bb];
     let bb_index = bb.as_u32();
     // Process Terminator
-    if let TerminatorKind::Call{func: Operand::Constant(f), args, destination,
-        ..} = &bbd.terminator().kind {
-        if let Some(call_ret) = get_non_empty_ret(*destination, body) {
+    if bb_index == call.0 {
+        // The first BB is the one of the call itself. Its destination is
+        // written after its arguments are read, so the call is never a def
+        // site of its own arguments, e.g., _5 in "_5 = foo(_5, _6)".
+    } else if let TerminatorKind::Call{func: Operand::Constant(f), args, ..} =
+        &bbd.terminator().kind
+        && let Some(def_site) = def_site_from_call(f, bb_index) {
+        if let Some(call_ret) = call_destination_local(bbd.terminator(), body) {
             // Found a potential definition site from a function call.
             for i in 0..locals.len() {
                 let arg_locals = &mut locals[i];
-                if arg_locals.contains(&call_ret) {
-                    arg_locals.remove(&call_ret);
-                    let def_site = def_site_from_call(f, bb_index);
+                if arg_locals.contains_local(&call_ret) {
                     match def_site {
-                        DefSite::HeapAlloc(_) => {
+                        DefSite::HeapAlloc(_) | DefSite::Uninit(_) => {
+                            arg_locals.remove_local(&call_ret);
                             summary.update_arg_defs(call, i, def_site);
                         },
                         DefSite::NativeCall(_) => {
-                            get_local_in_args(args, arg_locals);
+                            replace_ret_with_args(args, call_ret, arg_locals);
                         },
-                        DefSite::OtherCall(_) => {
-                            get_local_in_args(args, arg_locals);
+                        DefSite::OtherCall(_) | DefSite::Transmute(_) |
+                            DefSite::RawSlice(_) => {
+                            replace_ret_with_args(def_site.tainting_args(args), call_ret,
+                                                  arg_locals);
                             summary.update_arg_defs(call, i, def_site);
                         },
-                        _ => {}
+                        _ => {
+                            arg_locals.remove_local(&call_ret);
+                        }
                     }
                 }
             }
         }
+    } else if let TerminatorKind::Call{args, ..} = &bbd.terminator().kind {
+        if let Some(call_ret) = call_destination_local(bbd.terminator(), body) {
+            // A call through a function pointer, which may be a constant. Its
+            // callee may be unknown, so conservatively assume that all its
+            // arguments contribute to the return value, as for a native call.
+            // The return value of a resolved callee is traced in WPA, as for
+            // a normal call.
+            for i in 0..locals.len() {
+                if locals[i].contains_local(&call_ret) {
+                    replace_ret_with_args(args, call_ret, &mut locals[i]);
+                    summary.update_arg_defs(call, i, DefSite::OtherCall(bb_index));
+                }
+            }
+        }
     }
 
     // Process each Statement backward.
-    for i in (0..bbd.statements.len()).rev() {
-        let stmt = &bbd.statements[i];
+    for stmt in bbd.statements.iter().rev() {
         match &stmt.kind {
             StatementKind::Assign(box (place, rvalue)) => {
-                for arg_locals in locals.iter_mut() {
+                let def_site = def_site_from_rvalue(rvalue, body, bb_index);
+                for i in 0..locals.len() {
+                    let arg_locals = &mut locals[i];
                     let local = place.local;
-                    if arg_locals.contains(&local) {
-                        arg_locals.remove(&local);
-                        get_local_in_rvalue(rvalue, arg_locals);
+                    let part = arg_locals.get(&local).cloned();
+                    if part.is_none() || is_untracked_field(place, part.as_ref()) {
+                        continue;
+                    }
+                    // A write to a field of an enum variant only defines part
+                    // of the enum; see find_ret_def(). A write to a field that
+                    // is tracked by itself only defines that field.
+                    if !is_variant_field(place) {
+                        match (PlaceKey::of(place).field, &part) {
+                            (Some(field), Some(LocalPart::Fields(_))) => {
+                                arg_locals.remove_field(&local, field);
+                            },
+                            _ => {
+                                arg_locals.remove_local(&local);
+                            }
+                        }
+                    }
+                    // A Box allocated in place, a global, a union read, or a
+                    // transmute or an integer-to-pointer cast, which also
+                    // carries the taint of its operand.
+                    if let Some(def_site) = def_site {
+                        summary.update_arg_defs(call, i, def_site);
+                    }
+                    if def_site.map_or(true, |def_site| def_site.inherits_taint()) {
+                        add_rvalue_places(rvalue,
+                            part.as_ref().filter(|_| place.projection.is_empty()), arg_locals);
                     }
                 }
             },
+            StatementKind::SetDiscriminant { .. } | StatementKind::Deinit(_) => {
+                // Parts of constructing an enum; see find_unsafe_def_core().
+                // The enum is still tracked for the writes to its fields.
+            },
             _ => {
                 // Any other cases to handle?
             }
@@ -150,8 +292,7 @@ fn find_arg_def<'tcx>(bb: BasicBlock, body: &Body<'tcx>,
         for i in 0..locals.len() {
             let arg_locals = &mut locals[i];
             for arg in body.args_iter() {
-                if arg_locals.contains(&arg) {
-                    arg_locals.remove(&arg);
+                if arg_locals.remove_local(&arg) {
                     summary.update_arg_defs(call, i, DefSite::Arg(arg.as_u32()));
                 }
             }
@@ -163,16 +304,15 @@ fn find_arg_def<'tcx>(bb: BasicBlock, body: &Body<'tcx>,
 ///
 /// Inputs:
 /// @loc: Location of the Statement/Terminator from which to iterate backward.
-/// @locals: Local of Place that contribute to the target return value.
+/// @locals: Place (a Local or a field of it) that contribute to the target
+///          return value.
 /// @body: Body of the target function.
 /// @visited: Processed BasicBlock.
 /// @summary: Summary.
 ///
-/// TODO: We assume that there is no MIR code like _5 = foo(_5, ..), i.e.,
-/// the return of a call is assigned to a Place that is also used as one of the
-/// arguments. We should add assert for this. We would otherwise run the risk
-/// of missing the def sites for such Place.
-fn find_ret_def<'tcx>(loc: &Location, locals: &mut FxHashSet<Local>,
+/// A call like "_5 = foo(_5, ..)" assigns its return to a Place that is also
+/// one of its arguments; see replace_ret_with_args() for how it is handled.
+fn find_ret_def<'tcx>(loc: &Location, locals: &mut UnsafePlaces,
                       body: &Body<'tcx>, visited: &mut FxHashSet<BasicBlock>,
                       summary: &mut Summary) {
     let bb = loc.block;
@@ -184,28 +324,39 @@ fn find_ret_def<'tcx>(loc: &Location, locals: &mut FxHashSet<Local>,
     let stmt_num = bbd.statements.len();
     if start_index == stmt_num {
         // Examine the BB starting from the Terminator.
-        if let TerminatorKind::Call{func: Operand::Constant(f), args,
-            destination, ..} = &bbd.terminator().kind {
-            if let Some(local) = get_non_empty_ret(*destination, body) {
-                if locals.contains(&local) {
-                    locals.remove(&local);
-                    let def_site = def_site_from_call(f, bb.as_u32());
+        if let TerminatorKind::Call{func: Operand::Constant(f), args, ..} =
+            &bbd.terminator().kind
+            && let Some(def_site) = def_site_from_call(f, bb.as_u32()) {
+            if let Some(local) = call_destination_local(bbd.terminator(), body) {
+                if locals.contains_local(&local) {
                     match def_site {
-                        DefSite::HeapAlloc(_) => {
+                        DefSite::HeapAlloc(_) | DefSite::Uninit(_) => {
+                            locals.remove_local(&local);
                             summary.ret_defs.0.insert(def_site);
                         },
                         DefSite::NativeCall(_) => {
-                            get_local_in_args(args, locals);
+                            replace_ret_with_args(args, local, locals);
                             // Should def_site be put to summary.ret_defs?
                         },
-                        DefSite::OtherCall(_) => {
-                            get_local_in_args(args, locals);
+                        DefSite::OtherCall(_) | DefSite::Transmute(_) |
+                            DefSite::RawSlice(_) => {
+                            replace_ret_with_args(def_site.tainting_args(args), local, locals);
                             summary.ret_defs.0.insert(def_site);
                         }
-                        _ => {}
+                        _ => {
+                            locals.remove_local(&local);
+                        }
                     }
                 }
             }
+        } else if let TerminatorKind::Call{args, ..} = &bbd.terminator().kind {
+            // A call through a function pointer; see find_arg_def().
+            if let Some(local) = call_destination_local(bbd.terminator(), body) {
+                if locals.contains_local(&local) {
+                    replace_ret_with_args(args, local, locals);
+                    summary.ret_defs.0.insert(DefSite::OtherCall(bb.as_u32()));
+                }
+            }
         }
     } else {
         // This means we need to examine starting from a Statement.
@@ -217,11 +368,35 @@ fn find_ret_def<'tcx>(loc: &Location, locals: &mut FxHashSet<Local>,
         match &bbd.statements[i].kind {
             StatementKind::Assign(box (place, rvalue)) => {
                 let local = place.local;
-                if local.as_u32() == 0 || locals.contains(&local) {
-                    locals.remove(&local);
-                    get_local_in_rvalue(rvalue, locals);
+                let part = locals.get(&local).cloned();
+                if (local == RETURN_PLACE || part.is_some()) &&
+                    !is_untracked_field(place, part.as_ref()) {
+                    // A write to a field (e.g., "(_5.1: *mut u8) = ...") only
+                    // defines part of the Local. Keep tracking the Local for
+                    // the writes to its other fields.
+                    if place.projection.is_empty() {
+                        locals.remove_local(&local);
+                    }
+                    let def_site = def_site_from_rvalue(rvalue, body, bb.as_u32());
+                    if let Some(def_site) = def_site {
+                        // A Box allocated in place is a heap allocation
+                        // site, like a call to Box::new(); the address of a
+                        // global, a union field read, a transmute and an
+                        // integer-to-pointer cast are sources by themselves.
+                        summary.ret_defs.0.insert(def_site);
+                    }
+                    if def_site.map_or(true, |def_site| def_site.inherits_taint()) {
+                        add_rvalue_places(rvalue,
+                            part.as_ref().filter(|_| place.projection.is_empty()), locals);
+                    }
                 }
             },
+            StatementKind::Deinit(_) | StatementKind::SetDiscriminant { .. } => {
+                // Deinit marks a Place uninitialized before it is initialized,
+                // often field by field, and SetDiscriminant then selects the
+                // variant of an enum. They define nothing, so the Place is
+                // still tracked for the writes before them, if any.
+            },
             _ => {}
         }
     }
@@ -241,7 +416,7 @@ fn find_ret_def<'tcx>(loc: &Location, locals: &mut FxHashSet<Local>,
     // Check if any argument contributes to the return value.
     if bb.index() == 0 && !locals.is_empty() {
         body.args_iter().for_each(|arg|
-            if locals.contains(&arg) {
+            if locals.contains_local(&arg) {
                 summary.ret_defs.1.push(DefSite::Arg(arg.as_u32()));
             });
     }
@@ -265,17 +440,29 @@ fn find_ret_def<'tcx>(loc: &Location, locals: &mut FxHashSet<Local>,
 /// it would know the cause could be this.
 /// Jie Zhou: It is not clear to me why some impl disappear. A guess: the
 /// compiler may decide that such impl are dead code.
+/// Note that this is not because the impl are not found: the impl of a trait
+/// are collected from all the dependency crates, not just the crate of the
+/// trait or of the caller.
+///
+/// Shims that cannot be resolved to a more specific function, e.g., the drop
+/// glue of a type without a Drop impl, are resolved to the callee itself,
+/// which def_site_from_call() then classifies like any other std function.
 ///
-/// Note that there are unhandled cases of InstanceDef. It is fine now leaving
-/// them unhandled as none of the test program triggered the panic.
-fn resolve_callee<'tcx>(tcx: TyCtxt<'tcx>, callee: &Constant<'tcx>)
-    -> FxHashSet<DefId> {
+/// A constant function pointer is resolved to the function it points to, if
+/// the constant can be evaluated (see resolve_const_fn_ptr()). It returns None
+/// if the callee is neither a function item nor such a function pointer.
+pub(super) fn resolve_callee<'tcx>(tcx: TyCtxt<'tcx>, callee: &Constant<'tcx>)
+    -> Option<FxHashSet<DefId>> {
     let mut resolved_ids = FxHashSet::<DefId>::default();
+    if let Some(fn_id) = resolve_const_fn_ptr(tcx, callee) {
+        resolved_ids.insert(fn_id);
+        return Some(resolved_ids);
+    }
     if let ty::FnDef(callee_id, substs) = *callee.literal.ty().kind() {
         if tcx.trait_of_item(callee_id).is_none() {
             // Not a trait fn.
             resolved_ids.insert(callee_id);
-            return resolved_ids;
+            return Some(resolved_ids);
         }
 
         // Resolving a trait function.
@@ -300,49 +487,77 @@ fn resolve_callee<'tcx>(tcx: TyCtxt<'tcx>, callee: &Constant<'tcx>)
                     InstanceDef::Item(_) => {
                         // Should be from calling a default trait fn.
                         resolved_ids.insert(callee_id);
-                        return resolved_ids;
+                        return Some(resolved_ids);
                     },
                     InstanceDef::Virtual(..) => {
                         // Dynamic dispatch (dyn Trait). Handle this case below.
                     },
-                    InstanceDef::VtableShim(_) |
-                    InstanceDef::ReifyShim(_) |
-                    InstanceDef::FnPtrShim(..) => {
+                    InstanceDef::ClosureOnceShim{..} => {
+                        // A Fn/FnMut closure called via FnOnce::call_once. The
+                        // shim calls the closure, whose DefId is also the
+                        // DefId of its MIR body.
+                        if let ty::Closure(closure_id, _) = substs.type_at(0).kind() {
+                            resolved_ids.insert(*closure_id);
+                        } else {
+                            resolved_ids.insert(callee_id);
+                        }
+                        return Some(resolved_ids);
+                    },
+                    InstanceDef::FnPtrShim(_, ty) => {
+                        // A fn item or a fn pointer called via a Fn trait. The
+                        // shim calls the fn item, if it is one.
+                        if let ty::FnDef(item_id, _) = ty.kind() {
+                            resolved_ids.insert(*item_id);
+                            return Some(resolved_ids);
+                        }
+                        // A fn pointer. Handle it the same as Virtual.
+                    },
+                    InstanceDef::VTableShim(_) |
+                    InstanceDef::ReifyShim(_) => {
                         // Is it correct to handle those the same as Virtual?
                     },
                     InstanceDef::CloneShim(..) => {
                         // Compiler-generated <T as Clone>::clone(). Do we need
                         // to resolve all the implementors of it?
                         resolved_ids.insert(callee_id);
-                        return resolved_ids;
+                        return Some(resolved_ids);
+                    },
+                    InstanceDef::DropGlue(_, ty) => {
+                        // Resolve to the Drop impl of the type, if it has one.
+                        let drop_impl = ty.and_then(|ty| ty.ty_adt_def())
+                            .and_then(|adt_def| adt_def.destructor(tcx));
+                        match drop_impl {
+                            Some(destructor) => resolved_ids.insert(destructor.did),
+                            None => resolved_ids.insert(callee_id),
+                        };
+                        return Some(resolved_ids);
                     },
                     InstanceDef::Intrinsic(_) |
-                    InstanceDef::ClosureOnceShim{..} |
-                    InstanceDef::DropGlue(..) => {
-                        // TODO: Do we need to handle them specicially?
-                        panic!("Unhanndled InstanceDef");
+                    InstanceDef::ThreadLocalShim(_) |
+                    InstanceDef::FnPtrAddrShim(..) => {
+                        // E.g., copy_nonoverlapping or write_bytes. They are
+                        // in core, so they are native calls.
+                        resolved_ids.insert(callee_id);
+                        return Some(resolved_ids);
                     }
                 }
             } else {
                 // Successfully resolved the exact trait fn.
                 resolved_ids.insert(instance_id);
-                return resolved_ids;
+                return Some(resolved_ids);
             }
         }
 
         // Processing an unresolved case or resolved call by dyn trait.
         let trait_id = tcx.trait_of_item(callee_id).expect("DefId of Trait");
-        let mut impl_ids = Vec::<DefId>::new();
-        let impls = tcx.trait_impls_of(trait_id);
-        // Collect all the impl of this trait.
-        for impl_id in impls.blanket_impls() {
-            impl_ids.push(*impl_id);
-        }
-        for impl_id_v in impls.non_blanket_impls().values() {
-            for impl_id in impl_id_v {
-                impl_ids.push(*impl_id);
-            }
-        }
+        // Collect all the impl of this trait, both blanket and non-blanket
+        // ones, in this crate and in all its dependency crates; trait_impls_of
+        // queries implementations_of_trait of each upstream crate. The impl in
+        // downstream crates cannot be known here. Dedupe them so that each
+        // impl is examined once.
+        let mut impl_ids = tcx.all_impls(trait_id).collect::<Vec<DefId>>();
+        impl_ids.sort_by_key(|impl_id| tcx.def_path_hash(*impl_id));
+        impl_ids.dedup();
 
         // Find all implemented functions for callee_id.
         for impl_id in impl_ids {
@@ -368,35 +583,249 @@ fn resolve_callee<'tcx>(tcx: TyCtxt<'tcx>, callee: &Constant<'tcx>)
             }
         }
 
-        return resolved_ids;
+        return Some(resolved_ids);
+    }
+
+    None
+}
+
+/// Check if a value of a type cannot carry taint, so that the def site
+/// analysis of a call argument or the return value of that type can be
+/// skipped: the type is a bool, a char, "()", "!", a fieldless enum (e.g.,
+/// Ordering), or a tuple or array of such types. Integers are excluded as an
+/// integer may hold an address that is cast back to a pointer (see
+/// DefSite::IntToPtr).
+///
+/// The fast path is off if SANDBOX_NO_FAST_PATH is set to 1, e.g., to time it
+/// or to check that it does not change any summary.
+fn can_skip_def_analysis<'tcx>(ty: Ty<'tcx>) -> bool {
+    if fast_path_disabled() {
+        return false;
+    }
+
+    is_pointer_free_ty(ty)
+}
+
+/// Helper function of can_skip_def_analysis().
+fn is_pointer_free_ty<'tcx>(ty: Ty<'tcx>) -> bool {
+    match ty.kind() {
+        ty::Bool | ty::Char | ty::Never => true,
+        ty::Tuple(tys) => tys.iter().all(is_pointer_free_ty),
+        ty::Array(elem_ty, _) => is_pointer_free_ty(*elem_ty),
+        ty::Adt(adt_def, _) => {
+            adt_def.is_enum() && adt_def.all_fields().next().is_none()
+        },
+        _ => false,
+    }
+}
+
+/// Get the Local that are a mutable reference or raw pointer to the return
+/// place, e.g., _5 in "_5 = &mut _0".
+///
+/// MIR is independent of the ABI: even if a large aggregate is returned via
+/// a hidden pointer to the caller's return slot (PassMode::Indirect), MIR
+/// still writes the return value to _0, either directly ("_0 = ...") or field
+/// by field ("(_0.1: *mut u8) = ..."), or via a pointer to _0. The former two
+/// cases are writes to a Place whose Local is _0. This function collects the
+/// pointers for the third case.
+fn get_ret_aliases<'tcx>(body: &Body<'tcx>) -> FxHashSet<Local> {
+    let mut aliases = FxHashSet::default();
+    for bbd in body.basic_blocks().iter() {
+        for stmt in &bbd.statements {
+            if let StatementKind::Assign(box (place, rvalue)) = &stmt.kind {
+                match rvalue {
+                    Rvalue::Ref(_, BorrowKind::Mut{..}, borrowed) |
+                    Rvalue::AddressOf(Mutability::Mut, borrowed) => {
+                        if borrowed.local == RETURN_PLACE &&
+                            borrowed.projection.is_empty() &&
+                            place.projection.is_empty() {
+                            aliases.insert(place.local);
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    aliases
+}
+
+/// Check if a Place is (part of) the return value, i.e., it is _0 or a
+/// projection of it, or a dereference of a pointer to _0.
+fn is_ret_place<'tcx>(place: &Place<'tcx>, ret_aliases: &FxHashSet<Local>) -> bool {
+    if place.local == RETURN_PLACE {
+        return true;
+    }
+
+    ret_aliases.contains(&place.local) &&
+        matches!(place.projection.first(), Some(ProjectionElem::Deref))
+}
+
+/// Get the pointee type of a reference, raw pointer, or Box.
+fn get_pointee_ty<'tcx>(ty: Ty<'tcx>) -> Option<Ty<'tcx>> {
+    if ty.is_box() {
+        return Some(ty.boxed_ty());
+    }
+    ty.builtin_deref(true).map(|type_and_mut| type_and_mut.ty)
+}
+
+/// Get the methods in the vtable created by an unsizing coercion to a trait
+/// object, e.g., "_3 = move _4 as Box<dyn Trait> (PointerCoercion(Unsize))".
+///
+/// A method that is only called via trait objects may never show up as a
+/// resolved callee of any call, so we record the methods where their vtable
+/// is created to make sure they are summarized and linked to the call graph.
+fn get_vtable_methods<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>,
+                            rvalue: &Rvalue<'tcx>) -> Vec<DefId> {
+    let mut methods = Vec::new();
+    if let Rvalue::Cast(CastKind::PointerCoercion(PointerCoercion::Unsize),
+        operand, target_ty) = rvalue {
+        let source_ty = operand.ty(body, tcx);
+        let (Some(source_pointee), Some(target_pointee)) =
+            (get_pointee_ty(source_ty), get_pointee_ty(*target_ty)) else {
+            return methods;
+        };
+        // The vtable of a generic type is unknown until monomorphization.
+        if source_pointee.has_param() { return methods; }
+
+        if let ty::Dynamic(preds, ..) = target_pointee.kind() {
+            if let Some(principal) = preds.principal() {
+                let trait_ref = principal.with_self_ty(tcx, source_pointee);
+                for entry in tcx.vtable_entries(trait_ref) {
+                    if let VtblEntry::Method(instance) = entry {
+                        methods.push(instance.def_id());
+                    }
+                }
+            }
+        }
+    }
+
+    methods
+}
+
+/// Get the closure coerced to a Fn/FnMut/FnOnce trait object by an unsizing
+/// coercion, e.g., "_3 = move _4 as Box<dyn Fn()> (PointerCoercion(Unsize))"
+/// where _4 is a Box of a closure. Return the DefId of the closure and the
+/// number of arguments of its body, i.e., the closure itself (the captured
+/// environment) followed by each argument of the closure untupled.
+fn get_dyn_fn_closure<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>,
+                            rvalue: &Rvalue<'tcx>) -> Option<(DefId, u32)> {
+    let Rvalue::Cast(CastKind::PointerCoercion(PointerCoercion::Unsize),
+        operand, target_ty) = rvalue else {
+        return None;
+    };
+    let source_pointee = get_pointee_ty(operand.ty(body, tcx))?;
+    let target_pointee = get_pointee_ty(*target_ty)?;
+    let (ty::Closure(closure_id, substs), ty::Dynamic(preds, ..)) =
+        (source_pointee.kind(), target_pointee.kind()) else {
+        return None;
+    };
+    if !tcx.is_fn_trait(preds.principal_def_id()?) {
+        return None;
+    }
+
+    let inputs = substs.as_closure().sig().inputs().skip_binder()[0];
+    Some((*closure_id, 1 + inputs.tuple_fields().len() as u32))
+}
+
+/// Get the function or closure coerced to a fn pointer, e.g.,
+/// "_2 = write as fn(*mut u8) (PointerCoercion(ReifyFnPointer))".
+///
+/// Like a method only called via trait objects, such a function may never
+/// show up as a resolved callee, but it is reachable from where its pointer
+/// is created.
+fn get_fn_ptr_target<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>,
+                           rvalue: &Rvalue<'tcx>) -> Option<DefId> {
+    let Rvalue::Cast(CastKind::PointerCoercion(
+        PointerCoercion::ReifyFnPointer | PointerCoercion::ClosureFnPointer(_)),
+        operand, _) = rvalue else {
+        return None;
+    };
+    match *operand.ty(body, tcx).kind() {
+        ty::FnDef(def_id, _) | ty::Closure(def_id, _) => Some(def_id),
+        _ => None
     }
+}
+
+/// Check if a call is to a method of Fn/FnMut/FnOnce on a trait object, e.g.,
+/// "f()" where f is a Box<dyn Fn()> or a &dyn Fn(). resolve_callee() cannot
+/// find the concrete closure of such a call; WPA resolves it to the closures
+/// coerced to a Fn trait object in the whole program.
+fn is_dyn_fn_call<'tcx>(tcx: TyCtxt<'tcx>, callee: &Constant<'tcx>) -> bool {
+    if let ty::FnDef(callee_id, substs) = *callee.literal.ty().kind() {
+        if let Some(trait_id) = tcx.trait_of_item(callee_id) {
+            if tcx.is_fn_trait(trait_id) {
+                let mut self_ty = substs.type_at(0);
+                while let Some(pointee) = get_pointee_ty(self_ty) {
+                    self_ty = pointee;
+                }
+                return self_ty.is_trait();
+            }
+        }
+    }
+
+    false
+}
 
-    panic!("Not a function");
+/// Get the Drop impl run by a Drop terminator and the Local of the dropped
+/// Place. There is one only if the dropped type is an ADT that
+/// implements Drop. The drop glue of its fields is not followed.
+pub(super) fn get_drop_impl<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>,
+                                  terminator: &Terminator<'tcx>) -> Option<(DefId, Local)> {
+    let place = match &terminator.kind {
+        TerminatorKind::Drop{place, ..} => place,
+        _ => return None,
+    };
+    let adt = place.ty(body, tcx).ty.ty_adt_def()?;
+    let destructor = tcx.adt_destructor(adt.did())?;
+
+    Some((destructor.did, place.local))
 }
 
 /// Analyze a function to find:
 /// 1. Its callees and the definition sites of the arguments of each callee.
+///    A drop of a value whose type implements Drop is a call to its drop().
 /// 2. The definition sites for its return value, if there is one.
+/// 3. The methods in the vtables it creates, and the closures it coerces to
+///    Fn trait objects.
+/// 4. Whether it contains inline assembly or calls to intrinsics.
+/// 5. The type allocated at each heap allocation site, if known.
 pub(super)
-fn analyze_fn<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, summary: &mut Summary) {
+fn analyze_fn<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, summary: &mut Summary)
+    -> SandboxResult<()> {
     // BB that end with a call.
     let mut bb_with_calls = Vec::new();
+    // BB that end with a drop running a Drop impl, and the dropped Local.
+    let mut bb_with_drops = Vec::new();
     // Location of return value's def stmt and Local that contribute to it.
-    let mut ret_defs = FxHashMap::<Location, FxHashSet::<Local>>::default();
-    // Cache of a BB and the DefId of its resolved callee(s).
-    let mut callee_def_ids = FxHashMap::<u32, Vec<DefId>>::default();
+    let mut ret_defs = FxHashMap::<Location, UnsafePlaces>::default();
+    // Fast path for a return value that cannot carry taint.
+    let skip_ret_defs = can_skip_def_analysis(body.local_decls[RETURN_PLACE].ty);
+    // Cache of a BB and the FnID of its resolved callee(s).
+    let mut callee_ids = FxHashMap::<u32, Vec<FnID>>::default();
+    // Local that point to the return place.
+    let ret_aliases = get_ret_aliases(body);
     // Prepare data:
     // 1. BB with a call.
     // 2. BB with return value definition.
     for (bb, bbd) in body.basic_blocks().iter_enumerated() {
         let terminator = &bbd.terminator();
         let bb_index = bb.as_u32();
-        if let TerminatorKind::Call{func: Operand::Constant(callee), args,
-            destination, ..} = &terminator.kind {
+        if let TerminatorKind::Call{func: Operand::Constant(callee), args, ..} =
+            &terminator.kind
+            && let Some(resolved_callees) = resolve_callee(tcx, callee) {
             bb_with_calls.push(bb);
+            if let Some(alloc_type) = alloc_type_from_call(tcx, callee, bb_index) {
+                summary.alloc_types.push((DefSite::HeapAlloc(bb_index), alloc_type));
+            }
+            if is_dyn_fn_call(tcx, callee) {
+                summary.dyn_fn_calls.push(bb_index);
+            }
             // Prepare arg_defs of Callee.
-            let resolved_callees = resolve_callee(tcx, callee);
-            assert!(resolved_callees.len() > 0, "Failed to resolve calls");
+            if resolved_callees.is_empty() {
+                return Err(SandboxError::UnresolvedCall { bb: bb_index, caller: summary.name() });
+            }
 
             // Record callees that cannot be resolved statically. See the
             // comment of resolve_callee() for why we need this.
@@ -407,13 +836,14 @@ fn analyze_fn<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, summary: &mut Summary)
             }
 
             for callee_id in resolved_callees {
-                if !callee_def_ids.contains_key(&bb_index) {
-                    callee_def_ids.insert(bb_index, Vec::new());
-                }
-                callee_def_ids.get_mut(&bb_index).unwrap().push(callee_id);
                 let callee_fn_id = get_fn_fingerprint(tcx, callee_id);
+                callee_ids.entry(bb_index).or_default().push(callee_fn_id);
 
-                if tcx.is_foreign_item(callee_id) {
+                if tcx.is_intrinsic(callee_id) {
+                    summary.has_intrinsic_call = true;
+                }
+
+                if is_ffi_decl(tcx, callee_id) {
                     // The Callee is a foreign item. The later WPA will ignore
                     // foreign functions. Another implementation option is to
                     // not add such a Callee to Summary. However, we add it
@@ -421,49 +851,131 @@ fn analyze_fn<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, summary: &mut Summary)
                     summary.foreign_callees.insert(callee_fn_id);
                 }
 
-                if let Some(callee) = summary.get_callee_local(callee_id) {
+                if is_free_fn(tcx, callee_id) {
+                    summary.free_callees.insert(callee_fn_id);
+                }
+
+                if let Some(callee) = summary.get_callee_local(callee_fn_id) {
                     // Has seen a call to this callee before.
-                    callee.add_arg_def_slot(args, bb_index);
+                    callee.add_arg_def_slot(args.len(), bb_index);
                 } else {
                     let mut callee = Callee {
                         fn_id: callee_fn_id,
                         fn_name: get_fn_name(callee_id),
                         crate_name: get_crate_name(callee_id),
-                        def_id: break_def_id(callee_id),
+                        def_id: Some(break_def_id(callee_id)),
                         arg_defs: FxHashMap::default()
                     };
-                    callee.add_arg_def_slot(args, bb_index);
+                    callee.add_arg_def_slot(args.len(), bb_index);
                     summary.callees.push(callee);
                 }
             }
 
             // Prepare for return value.
-            if !is_empty_ty(body.return_ty()) && destination.local.index() == 0 {
+            if !skip_ret_defs &&
+                call_destination_local(terminator, body) == Some(RETURN_PLACE) {
                 let loc = Location {
                     block: bb, statement_index: bbd.statements.len()
                 };
                 let mut locals = FxHashSet::<Local>::default();
                 get_local_in_args(args, &mut locals);
-                ret_defs.insert(loc, locals);
+                let mut places = UnsafePlaces::default();
+                places.insert_locals(locals);
+                ret_defs.insert(loc, places);
+            }
+        } else if let TerminatorKind::Call{func, args, ..} = &terminator.kind {
+            // A call through a function pointer that is not a constant one to
+            // a known function cannot be resolved statically. Record it as a
+            // dyn Callee of its own so that the call graph and the def sites
+            // of its arguments are still complete. It has no DefId.
+            bb_with_calls.push(bb);
+            let callee_fn_id = get_fn_ptr_callee_id(summary.fn_id, bb_index);
+            callee_ids.insert(bb_index, vec![callee_fn_id]);
+            summary.dyn_callees.insert(callee_fn_id);
+            let mut callee = Callee {
+                fn_id: callee_fn_id,
+                fn_name: format!("<{}>", short_type_name(tcx, func.ty(body, tcx),
+                                                         MAX_TYPE_NAME_DEPTH)),
+                crate_name: get_local_crate_name(),
+                def_id: None,
+                arg_defs: FxHashMap::default()
+            };
+            callee.add_arg_def_slot(args.len(), bb_index);
+            summary.callees.push(callee);
+
+            if !skip_ret_defs &&
+                call_destination_local(terminator, body) == Some(RETURN_PLACE) {
+                let loc = Location {
+                    block: bb, statement_index: bbd.statements.len()
+                };
+                let mut locals = FxHashSet::<Local>::default();
+                get_local_in_args(args, &mut locals);
+                let mut places = UnsafePlaces::default();
+                places.insert_locals(locals);
+                ret_defs.insert(loc, places);
+            }
+        } else if let Some((drop_id, dropped)) = get_drop_impl(tcx, body, terminator) {
+            // Drop::drop(&mut self) takes a mutable borrow of the dropped
+            // value as its only argument. The dropped Local itself is
+            // deliberately recorded as Arg 1: a borrow has the same def sites
+            // as the borrowed Place (see get_source_place_in_rvalue()), so
+            // the def sites of the value are those of "&mut value".
+            bb_with_drops.push((bb, dropped));
+            let callee_fn_id = get_fn_fingerprint(tcx, drop_id);
+            callee_ids.insert(bb_index, vec![callee_fn_id]);
+            if let Some(callee) = summary.get_callee_local(callee_fn_id) {
+                callee.add_arg_def_slot(1, bb_index);
+            } else {
+                let mut callee = Callee {
+                    fn_id: callee_fn_id,
+                    fn_name: get_fn_name(drop_id),
+                    crate_name: get_crate_name(drop_id),
+                    def_id: Some(break_def_id(drop_id)),
+                    arg_defs: FxHashMap::default()
+                };
+                callee.add_arg_def_slot(1, bb_index);
+                summary.callees.push(callee);
             }
-            continue;
+        }
+
+        if let TerminatorKind::InlineAsm{..} = &terminator.kind {
+            summary.has_inline_asm = true;
         }
 
         // Continue to prepare for return value.
         for i in 0..bbd.statements.len() {
             match &bbd.statements[i].kind {
                 StatementKind::Assign(box (place, rvalue)) => {
-                    if place.local.as_u32() == 0 {
-                        // Found a def site for the return.
+                    if let Rvalue::ShallowInitBox(_, t) = rvalue {
+                        // An inlined Box::new(); see def_site_from_rvalue().
+                        summary.alloc_types.push((DefSite::BoxAlloc(bb_index),
+                            short_type_name(tcx, *t, MAX_TYPE_NAME_DEPTH)));
+                    }
+                    for method_id in get_vtable_methods(tcx, body, rvalue) {
+                        summary.add_vtable_fn(tcx, method_id);
+                    }
+                    if let Some((closure_id, arg_count)) =
+                        get_dyn_fn_closure(tcx, body, rvalue) {
+                        summary.add_dyn_fn_closure(tcx, closure_id, arg_count);
+                    }
+                    if let Some(fn_ptr_id) = get_fn_ptr_target(tcx, body, rvalue) {
+                        summary.add_fn_ptr_fn(tcx, fn_ptr_id);
+                    }
+                    if !skip_ret_defs && is_ret_place(place, &ret_aliases) {
+                        // Found a def site for the return. Note that there
+                        // may be multiple of them in a BB when the return
+                        // value is initialized field by field.
                         let loc = Location { block: bb, statement_index: i };
-                        let mut locals = FxHashSet::<Local>::default();
-                        get_local_in_rvalue(rvalue, &mut locals);
-                        ret_defs.insert(loc, locals);
-                        // There should be at most one ret def ("_0 = ...")
-                        // in a BB so it should be safe to break from here.
-                        break;
+                        let mut places = UnsafePlaces::default();
+                        add_rvalue_places(rvalue, None, &mut places);
+                        ret_defs.insert(loc, places);
                     }
                 },
+                StatementKind::Intrinsic(_) => {
+                    // An intrinsic call lowered to a statement, e.g., assume
+                    // or copy_nonoverlapping.
+                    summary.has_intrinsic_call = true;
+                },
                 _ => {}
             }
         }
@@ -476,17 +988,27 @@ fn analyze_fn<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, summary: &mut Summary)
             // Recorded visited BB to prevent infite recursions due to loops.
             let mut visited = FxHashSet::<BasicBlock>::default();
             // Local of the Place that contribute to function call arguments.
-            let mut locals = Vec::<FxHashSet::<Local>>::with_capacity(args.len());
-            // Collect the initial Local for each argument.
+            let mut locals = Vec::<UnsafePlaces>::with_capacity(args.len());
+            // Collect the initial Place for each argument.
             for arg in args {
                 let mut places = Vec::new();
-                let mut arg_locals = FxHashSet::default();
-                get_place_in_operand(arg, &mut places);
-                for place in places { arg_locals.insert(place.local); }
-                locals.push(arg_locals);
+                let mut arg_places = UnsafePlaces::default();
+                // Fast path for an argument that cannot carry taint.
+                if !can_skip_def_analysis(arg.ty(body, tcx)) {
+                    get_place_in_operand(arg, &mut places);
+                }
+                for place in places { arg_places.insert_place(&place); }
+                locals.push(arg_places);
+            }
+            if locals.iter().all(|arg_places| arg_places.is_empty()) {
+                if _DEBUG {
+                    println!("[calls::analyze_fn]: Skip def site analysis of the args of {:?} in {}",
+                        bb, summary.name());
+                }
+                continue;
             }
             // Enter the core procedure of finding def sites for fn args.
-            for callee_id in callee_def_ids.get(&bb.as_u32()).unwrap() {
+            for callee_id in callee_ids.get(&bb.as_u32()).unwrap() {
                 find_arg_def(bb, body, (bb.as_u32(), *callee_id), &mut locals,
                     &mut visited, summary);
             }
@@ -495,9 +1017,27 @@ fn analyze_fn<'tcx>(tcx: TyCtxt<'tcx>, body: &Body<'tcx>, summary: &mut Summary)
         }
     }
 
+    // Process each drop of a value with a Drop impl to find the def sites of
+    // the value.
+    for (bb, dropped) in bb_with_drops {
+        let mut visited = FxHashSet::<BasicBlock>::default();
+        let mut dropped_places = UnsafePlaces::default();
+        dropped_places.insert(PlaceKey::whole(dropped));
+        let mut locals = vec![dropped_places];
+        for callee_id in callee_ids.get(&bb.as_u32()).unwrap() {
+            find_arg_def(bb, body, (bb.as_u32(), *callee_id), &mut locals,
+                &mut visited, summary);
+        }
+    }
+
     // Process the return value to find its def sites.
     for (loc, mut locals) in ret_defs {
         let mut visited = FxHashSet::<BasicBlock>::default();
         find_ret_def(&loc, &mut locals, body, &mut visited, summary);
     }
+
+    // A Box allocated in place is found after the calls to allocate.
+    summary.alloc_types.sort();
+
+    Ok(())
 }
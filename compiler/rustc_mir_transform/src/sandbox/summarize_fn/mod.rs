@@ -9,19 +9,49 @@
 //! generates a final summary for the whole program, which will then be used
 //! to do memory isolation.
 pub(crate) mod calls;
+pub(crate) mod self_ref;
 pub(crate) mod unsafe_def;
 
 use rustc_middle::ty::{TyCtxt};
-use rustc_hir::def_id::{DefId, LOCAL_CRATE};
+use rustc_middle::mir::{Body, Operand, TerminatorKind};
+use rustc_hir::def_id::{CrateNum, DefId, DefIndex, DefPathHash, LOCAL_CRATE};
 use rustc_data_structures::fx::{FxHashSet, FxHashMap};
-use serde::{Deserialize, Serialize};
+use rustc_data_structures::fingerprint::Fingerprint;
+use rustc_data_structures::flock::Lock;
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use std::cmp::Ordering;
+use std::collections::BTreeMap;
 use std::fmt;
 use std::fs;
+use std::hash::Hash;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering as AtomicOrdering};
 
+use super::context::{AnalysisCtxt, AnalysisFn};
+use super::error::{SandboxError, SandboxResult};
 use super::utils::*;
 
 static _DEBUG: bool = false;
 
+/// Name of the lock file in a summary directory. Each rustc process holds it
+/// while writing the summary files of its crate, and WPA holds it shared while
+/// reading them.
+pub(crate) static SUMMARY_LOCK: &str = ".lock";
+
+/// Extension of a summary file that is being written. It is renamed to the
+/// final name once complete, so a reader never sees a partially written one.
+pub(crate) static SUMMARY_TMP_EXT: &str = "tmp";
+
+/// Name of the marker file written to a summary directory that is kept after
+/// WPA (SANDBOX_KEEP_SUMMARIES). It holds the generation of the directory,
+/// which is bumped each time the directory is kept. Summary files written in
+/// an earlier generation are left over from an earlier build and are ignored.
+pub(crate) static KEEP_MARKER: &str = ".keep";
+
+/// Number of temporary summary files created by this process, to give each a
+/// unique name.
+static TMP_FILE_NUM: AtomicUsize = AtomicUsize::new(0);
+
 /// Definition site of a Place can be one of the following cases:
 ///
 /// 1. Global variable
@@ -29,8 +59,9 @@
 /// 3. Return value of call, including heap allocation and other function call
 /// 4. Function argument, which could originally come from 1, 2, or 3
 ///
-/// Currently we only aim to isolate unsafe heap memory, so we only handle
-/// case 3 and 4.
+/// Currently we only aim to isolate unsafe heap memory, so we mainly handle
+/// case 3 and 4. Case 1 is recorded as a terminal source (DefSite::Global) so
+/// that unsafe statics and thread-locals are not silently dropped.
 ///
 /// We distinguish the types of calls. This is necessary in later WPA.
 /// Specifically, if we see a Place e.g., _10, is used by unsafe code, and
@@ -53,6 +84,44 @@ pub enum DefSite {
     OtherCall(u32),
     /// Local of an argument
     Arg(u32),
+    /// Location of a call to core::ptr::drop_in_place, which runs the drop
+    /// glue (e.g., frees the memory) of the pointee of its argument. It is
+    /// not a def site but is recorded with the def sites of the pointer so
+    /// that it can be paired with the allocation sites of the pointer.
+    DropInPlace(u32),
+    /// Location of a call that creates a possibly invalid value, i.e.,
+    /// mem::uninitialized, mem::zeroed, or MaybeUninit::assume_init. Like a
+    /// heap allocation, it is where an unsafe value originates.
+    Uninit(u32),
+    /// A static or thread-local variable, identified by its DefId. Like a heap
+    /// allocation, it is where an unsafe value originates, but it is never
+    /// freed.
+    Global(SerDefId),
+    /// Location (BB) of a read of a union field, e.g., "_3 = (_1.1: *mut u8)"
+    /// where _1 is a union. The read reinterprets the bytes of the union as
+    /// the type of the field, so like an uninitialized value, the value read
+    /// may be invalid.
+    UnionRead(u32),
+    /// Location (BB) of a transmute, either a call to mem::transmute_copy or
+    /// a Transmute cast, e.g., "_2 = move _1 as *mut u8 (Transmute)". The
+    /// result is a new unsafe value, but unlike other sources it also carries
+    /// the taint of the transmuted value.
+    Transmute(u32),
+    /// Location (BB) of a call to slice::from_raw_parts(_mut), which builds a
+    /// slice from a raw pointer and a length that are not checked. Like a
+    /// transmute, the result carries the taint of the pointer, but not of the
+    /// length.
+    RawSlice(u32),
+    /// Location (BB) of a cast from an integer to a pointer, e.g.,
+    /// "_2 = move _1 as *mut u8 (PointerFromExposedAddress)", which forges a
+    /// pointer out of an address. Like a transmute, the result also carries
+    /// the taint of the integer, which may be the address of a pointer.
+    IntToPtr(u32),
+    /// Location (BB) of a Box allocated in place, i.e., a ShallowInitBox,
+    /// which is what Box::new() becomes once it is inlined. It is a heap
+    /// allocation like HeapAlloc, but a kind of its own so that it does not
+    /// collide with a call to an allocation function that ends the same BB.
+    BoxAlloc(u32),
 }
 
 impl PartialEq for DefSite {
@@ -62,32 +131,159 @@ fn eq(&self, other: &DefSite) -> bool {
             (DefSite::NativeCall(nc), DefSite::NativeCall(nc1)) => nc == nc1,
             (DefSite::OtherCall(oc), DefSite::OtherCall(oc1)) => oc == oc1,
             (DefSite::Arg(arg), DefSite::Arg(arg1)) => arg == arg1,
+            (DefSite::DropInPlace(dp), DefSite::DropInPlace(dp1)) => dp == dp1,
+            (DefSite::Uninit(ui), DefSite::Uninit(ui1)) => ui == ui1,
+            (DefSite::Global(gl), DefSite::Global(gl1)) => gl == gl1,
+            (DefSite::UnionRead(ur), DefSite::UnionRead(ur1)) => ur == ur1,
+            (DefSite::Transmute(tm), DefSite::Transmute(tm1)) => tm == tm1,
+            (DefSite::RawSlice(rs), DefSite::RawSlice(rs1)) => rs == rs1,
+            (DefSite::IntToPtr(ip), DefSite::IntToPtr(ip1)) => ip == ip1,
+            (DefSite::BoxAlloc(ba), DefSite::BoxAlloc(ba1)) => ba == ba1,
             _ => false
         }
     }
 }
 
+impl DefSite {
+    /// Get the kind of a DefSite, in declaration order, and its location, by
+    /// which DefSite are ordered.
+    fn sort_key(&self) -> (u8, (u32, u32)) {
+        match self {
+            DefSite::HeapAlloc(loc) => (0, (*loc, 0)),
+            DefSite::NativeCall(loc) => (1, (*loc, 0)),
+            DefSite::OtherCall(loc) => (2, (*loc, 0)),
+            DefSite::Arg(arg) => (3, (*arg, 0)),
+            DefSite::DropInPlace(loc) => (4, (*loc, 0)),
+            DefSite::Uninit(loc) => (5, (*loc, 0)),
+            DefSite::Global(def_id) => (6, (def_id.0.krate.as_u32(), def_id.0.index.as_u32())),
+            DefSite::UnionRead(loc) => (7, (*loc, 0)),
+            DefSite::Transmute(loc) => (8, (*loc, 0)),
+            DefSite::RawSlice(loc) => (9, (*loc, 0)),
+            DefSite::IntToPtr(loc) => (10, (*loc, 0)),
+            DefSite::BoxAlloc(loc) => (11, (*loc, 0)),
+        }
+    }
+
+    /// Check if the value defined at this def site inherits the taint of the
+    /// operands (arguments) it is defined from, i.e., the def site is not
+    /// where the value wholly originates.
+    pub fn inherits_taint(&self) -> bool {
+        matches!(self, DefSite::Transmute(_) | DefSite::RawSlice(_) | DefSite::IntToPtr(_))
+    }
+
+    /// Check if this def site is a heap allocation, either a call to an
+    /// allocation function or a Box allocated in place.
+    pub fn is_heap_alloc(&self) -> bool {
+        matches!(self, DefSite::HeapAlloc(_) | DefSite::BoxAlloc(_))
+    }
+
+    /// Check if this def site is where an unsafe value originates as far as
+    /// the whole-program analysis is concerned, i.e., tracing its def sites
+    /// stops here. An OtherCall and an Arg are traced further into the callee
+    /// and the callers, and a DropInPlace is not a def site at all.
+    pub fn is_terminal_source(&self) -> bool {
+        !matches!(self, DefSite::OtherCall(_) | DefSite::Arg(_) | DefSite::DropInPlace(_))
+    }
+
+    /// Get the BB of the call that defines the value at this def site, or
+    /// None if the value is not defined by a call.
+    pub fn call_bb(&self) -> Option<u32> {
+        match self {
+            DefSite::HeapAlloc(bb) | DefSite::NativeCall(bb) | DefSite::Uninit(bb) |
+                DefSite::OtherCall(bb) | DefSite::Transmute(bb) |
+                DefSite::RawSlice(bb) => Some(*bb),
+            _ => None
+        }
+    }
+
+    /// Get the arguments of a call at this def site whose taint the result
+    /// inherits. A raw slice only inherits the taint of its pointer, not of
+    /// its length; other calls inherit the taint of all their arguments.
+    pub fn tainting_args<'a, T>(&self, args: &'a [T]) -> &'a [T] {
+        match self {
+            DefSite::RawSlice(_) => &args[..args.len().min(1)],
+            _ => args
+        }
+    }
+}
+
+impl Ord for DefSite {
+    fn cmp(&self, other: &DefSite) -> Ordering {
+        return self.sort_key().cmp(&other.sort_key());
+    }
+}
+
+impl PartialOrd for DefSite {
+    fn partial_cmp(&self, other: &DefSite) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
 impl fmt::Debug for DefSite {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let (message, loc) = match self {
             DefSite::HeapAlloc(loc) | DefSite::NativeCall(loc) |
-                DefSite::OtherCall(loc) => ("BB", loc),
-            DefSite::Arg(arg) => ("Arg", arg)
+                DefSite::OtherCall(loc) | DefSite::DropInPlace(loc) |
+                DefSite::Uninit(loc) | DefSite::UnionRead(loc) |
+                DefSite::Transmute(loc) | DefSite::RawSlice(loc) |
+                DefSite::IntToPtr(loc) | DefSite::BoxAlloc(loc) => ("BB", loc),
+            DefSite::Arg(arg) => ("Arg", arg),
+            DefSite::Global(def_id) => {
+                return write!(f, "Global: {:?}", def_id);
+            }
         };
         write!(f, "{}: {}", message, loc)
     }
 }
 
-/// The def_id::DefPathHash, i.e., Fingerprint, of a function.
-///
-/// This is not as beautiful as it should be: Ideally we should directly use
-/// DefPathHash instead of the inner representation. However, we would then
-/// have to implement Serialize & Deserialize for DefPathHash. Serialize is
-/// usually easy to implement but Deserialize is more complex. This is similar
-/// to why we use u32 a lot to represent BasicBlock and def_id's components.
-/// We should fix these issues later.
-#[derive(Serialize, Deserialize, Hash, Eq, Copy, Clone)]
-pub struct FnID(pub(crate) (u64, u64));
+/// Mutability of the pointer defined at an unsafe def site. A mutable pointer
+/// enables writes and thus aliasing bugs, so it is more interesting than a
+/// const one.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Copy, Clone, Debug)]
+pub enum PtrMutability {
+    /// *const T, &T, or a shared smart pointer such as Rc<T>.
+    Const,
+    /// *mut T, &mut T, or a uniquely owned one such as Box<T>.
+    Mut,
+}
+
+/// The def_id::DefPathHash, i.e., Fingerprint, of a function. It is unique
+/// and stable across compilation sessions, and is serialized as the two u64
+/// halves of the Fingerprint, e.g., [1234, 5678].
+#[derive(Hash, Eq, Copy, Clone)]
+pub struct FnID(pub(crate) DefPathHash);
+
+impl FnID {
+    /// Create a FnID from the two halves of a DefPathHash.
+    pub(crate) fn new(hi: u64, lo: u64) -> FnID {
+        FnID(DefPathHash(Fingerprint::new(hi, lo)))
+    }
+
+    /// Get the two halves of the DefPathHash.
+    pub(crate) fn as_value(&self) -> (u64, u64) {
+        self.0.0.as_value()
+    }
+
+    /// Get the hex string of the DefPathHash, e.g., for keying the findings of
+    /// a function across compilation sessions.
+    pub(crate) fn to_hex(&self) -> String {
+        let (hi, lo) = self.as_value();
+        format!("{:016x}{:016x}", hi, lo)
+    }
+}
+
+impl Serialize for FnID {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.as_value().serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for FnID {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<FnID, D::Error> {
+        let (hi, lo) = <(u64, u64)>::deserialize(deserializer)?;
+        Ok(FnID::new(hi, lo))
+    }
+}
 
 impl PartialEq for FnID {
     fn eq(&self, other: &FnID) -> bool {
@@ -95,27 +291,125 @@ fn eq(&self, other: &FnID) -> bool {
     }
 }
 
+impl Ord for FnID {
+    fn cmp(&self, other: &FnID) -> Ordering {
+        return self.0.cmp(&other.0);
+    }
+}
+
+impl PartialOrd for FnID {
+    fn partial_cmp(&self, other: &FnID) -> Option<Ordering> {
+        return Some(self.cmp(other));
+    }
+}
+
+/// Serialize a set of FnID in ascending order so that the output is stable
+/// across compilation sessions.
+fn serialize_sorted_fn_ids<S: Serializer>(fn_ids: &FxHashSet<FnID>, serializer: S)
+    -> Result<S::Ok, S::Error> {
+    let mut sorted = fn_ids.iter().collect::<Vec<_>>();
+    sorted.sort();
+    sorted.serialize(serializer)
+}
+
+/// Get a set of DefSite sorted by the order of DefSite.
+fn sorted_def_sites(def_sites: &FxHashSet<DefSite>) -> Vec<&DefSite> {
+    let mut sorted = def_sites.iter().collect::<Vec<_>>();
+    sorted.sort();
+    sorted
+}
+
+/// Serialize the def sites of the return value, with the set of call def
+/// sites in ascending order so that the output is stable across compilation
+/// sessions.
+fn serialize_sorted_ret_defs<S: Serializer>(ret_defs: &(FxHashSet<DefSite>, Vec<DefSite>),
+                                            serializer: S) -> Result<S::Ok, S::Error> {
+    (sorted_def_sites(&ret_defs.0), &ret_defs.1).serialize(serializer)
+}
+
+/// Serialize an optional set of DefSite in ascending order.
+fn serialize_sorted_unsafe_defs<S: Serializer>(def_sites: &Option<FxHashSet<DefSite>>,
+                                               serializer: S) -> Result<S::Ok, S::Error> {
+    def_sites.as_ref().map(sorted_def_sites).serialize(serializer)
+}
+
+/// Serialize the def sites of each dropped Place with each set in ascending
+/// order. The BB are sorted already.
+fn serialize_sorted_drop_defs<S: Serializer>(drop_defs: &[(u32, FxHashSet<DefSite>)],
+                                             serializer: S) -> Result<S::Ok, S::Error> {
+    drop_defs.iter().map(|(bb, def_sites)| (bb, sorted_def_sites(def_sites)))
+        .collect::<Vec<_>>().serialize(serializer)
+}
+
+/// Serialize the def sites of the arguments of each call by the BB of the
+/// call in ascending order, with each set of def sites in ascending order.
+fn serialize_sorted_arg_defs<S: Serializer>(arg_defs: &FxHashMap<u32, Vec<FxHashSet<DefSite>>>,
+                                            serializer: S) -> Result<S::Ok, S::Error> {
+    arg_defs.iter()
+        .map(|(bb, args)| (bb, args.iter().map(sorted_def_sites).collect::<Vec<_>>()))
+        .collect::<BTreeMap<_, _>>().serialize(serializer)
+}
+
 impl fmt::Debug for FnID {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}::{}", self.0.0, self.0.1)
+        let (hi, lo) = self.as_value();
+        write!(f, "{}::{}", hi, lo)
+    }
+}
+
+/// The DefId of a function or a global, serialized as its DefIndex and
+/// CrateNum, e.g., [5, 0]. Unlike FnID, it is only valid in the compilation
+/// session that creates it. Deserializing an index or a crate number out of
+/// the range of DefIndex or CrateNum is an error instead of a panic.
+#[derive(Hash, PartialEq, Eq, Copy, Clone)]
+pub struct SerDefId(pub(crate) DefId);
+
+impl Serialize for SerDefId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.0.index.as_u32(), self.0.krate.as_u32()).serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for SerDefId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<SerDefId, D::Error> {
+        let (index, krate) = <(u32, u32)>::deserialize(deserializer)?;
+        if index > DefIndex::MAX_AS_U32 || krate > CrateNum::MAX_AS_U32 {
+            return Err(de::Error::custom(format!("invalid DefId: ({}, {})", index, krate)));
+        }
+        Ok(SerDefId(DefId {
+            index: DefIndex::from_u32(index),
+            krate: CrateNum::from_u32(krate)
+        }))
+    }
+}
+
+impl fmt::Debug for SerDefId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}:{}", self.0.krate.as_u32(), self.0.index.as_u32())
     }
 }
 
 /// Information of a callee used by a function. Speficially, we collect the
 /// definition sites for all the arguments of a call of the Callee.
-#[derive(Serialize, Deserialize)]
+#[derive(Serialize, Deserialize, Clone)]
 pub(crate) struct Callee {
     /// Unique ID of a function that is stable across compilation sessions.
     pub(crate) fn_id: FnID,
     pub fn_name: String,
     pub crate_name: String,
-    /// DefId (DefIndex, CrateNum)
-    pub(crate) def_id: (u32, u32),
+    /// DefId (DefIndex, CrateNum), or None for the unknown callee of a call
+    /// through a function pointer.
+    pub(crate) def_id: Option<SerDefId>,
     /// The basic block of a call and def sites for each argument. For example,
     /// (bb3, [[bb0, bb1], [bb2, _2]]) means the callee is called at BB3, and
     /// the call has two arguments, and the first argument is computed from the
     /// Terminator of BB0 and BB1, and the second is from the Terminator of bb2
     /// and argument _2.
+    ///
+    /// Each call has its own number of arguments, which may differ between
+    /// the calls of a variadic foreign function such as printf, so the
+    /// arguments of a call must be looked up by its BB; see get_arg_defs().
+    #[serde(serialize_with = "serialize_sorted_arg_defs")]
     pub(crate) arg_defs: FxHashMap<u32, Vec<FxHashSet<DefSite>>>,
 }
 
@@ -127,13 +421,16 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 }
 
 impl Callee {
-    /// Get the set of DefSite for a certain argument of a call.
+    /// Get the set of DefSite for a certain argument of a call. It returns
+    /// None if there is no call at @bb or no such argument, e.g., for Arg(0),
+    /// which is the return value instead of an argument.
     ///
     /// Inputs:
     /// @bb: Where the call is.
-    /// @arg: Argument number.
-    pub(crate) fn get_arg_defs(&self, bb: u32, arg: u32) -> &FxHashSet<DefSite> {
-        return &self.arg_defs[&bb][(arg - 1) as usize];
+    /// @arg: Argument number, starting from 1.
+    pub(crate) fn get_arg_defs(&self, bb: u32, arg: u32) -> Option<&FxHashSet<DefSite>> {
+        let index = arg.checked_sub(1)? as usize;
+        return self.arg_defs.get(&bb)?.get(index);
     }
 
     // Return "crate_name::fn_name" of the callee. This is for debugging.
@@ -150,17 +447,78 @@ pub struct Summary {
     pub fn_name: String,
     pub crate_name: String,
     /// DefId
-    def_id: (u32, u32),
+    def_id: SerDefId,
     /// Callees used in this function. Key is DefId.
     pub(crate) callees: Vec<Callee>,
     /// DefSite of Place in return value (FxHashSet<CallSite>, Vec<Arg>)
+    #[serde(serialize_with = "serialize_sorted_ret_defs")]
     pub(crate) ret_defs: (FxHashSet<DefSite>, Vec::<DefSite>),
     /// DefSite of Place in unsafe code
+    #[serde(serialize_with = "serialize_sorted_unsafe_defs")]
     pub(crate) unsafe_defs: Option<FxHashSet<DefSite>>,
-    /// A set of Callee that are foreign items, usually declared in extern "C".
+    /// A set of Callee that are FFI declarations of any ABI, usually declared
+    /// in extern "C"; see is_ffi_decl().
+    #[serde(serialize_with = "serialize_sorted_fn_ids")]
     pub(crate) foreign_callees: FxHashSet<FnID>,
+    /// A set of Callee that free the memory their first argument points to;
+    /// see is_free_fn().
+    #[serde(default, serialize_with = "serialize_sorted_fn_ids")]
+    pub(crate) free_callees: FxHashSet<FnID>,
     /// Callee that cannot be resolved at compile time.
+    #[serde(serialize_with = "serialize_sorted_fn_ids")]
     pub(crate) dyn_callees: FxHashSet<FnID>,
+    /// Methods in the vtables created by this function, i.e., functions that
+    /// may be called via trait objects. Their arg_defs are always empty.
+    #[serde(default)]
+    pub(crate) vtable_fns: Vec<Callee>,
+    /// Heap allocation sites (BB) whose pointer may be stored into a field of
+    /// the allocation itself, sorted.
+    #[serde(default)]
+    pub(crate) self_ref_allocs: Vec<u32>,
+    /// DefSite of the Place dropped by each Drop terminator or freed by each
+    /// call to a deallocation function of the global allocator API, by BB,
+    /// sorted, i.e., where the memory freed at each BB may come from.
+    #[serde(default, serialize_with = "serialize_sorted_drop_defs")]
+    pub(crate) drop_defs: Vec<(u32, FxHashSet<DefSite>)>,
+    /// Calls (BB) to a Fn/FnMut/FnOnce method on a trait object, e.g., calling
+    /// a Box<dyn Fn()>. They are resolved to closures by WPA.
+    #[serde(default)]
+    pub(crate) dyn_fn_calls: Vec<u32>,
+    /// Closures coerced to Fn/FnMut/FnOnce trait objects by this function,
+    /// each with the number of arguments of the closure body.
+    #[serde(default)]
+    pub(crate) dyn_fn_closures: Vec<(Callee, u32)>,
+    /// Functions and closures coerced to fn pointers by this function, i.e.,
+    /// functions that may be called via fn pointers. Like vtable_fns, their
+    /// arg_defs are always empty.
+    #[serde(default)]
+    pub(crate) fn_ptr_fns: Vec<Callee>,
+    /// Whether this function contains inline assembly.
+    #[serde(default)]
+    pub(crate) has_inline_asm: bool,
+    /// Whether this function calls a compiler intrinsic.
+    #[serde(default)]
+    pub(crate) has_intrinsic_call: bool,
+    /// Mutability of the pointer defined at each of unsafe_defs, if the def
+    /// site defines a pointer, sorted by DefSite.
+    #[serde(default)]
+    pub(crate) source_mutability: Vec<(DefSite, PtrMutability)>,
+    /// Type allocated at each heap allocation site, if known, sorted by
+    /// DefSite, e.g., "u64" for Vec::<u64>::with_capacity().
+    #[serde(default)]
+    pub(crate) alloc_types: Vec<(DefSite, String)>,
+    /// Whether this function is the entry point of the program, i.e., main().
+    #[serde(default)]
+    pub(crate) is_entry: bool,
+    /// Whether this function is reachable from other crates, e.g., a pub fn
+    /// of a library crate.
+    #[serde(default)]
+    pub(crate) is_exported: bool,
+    /// Whether this function is unsafe as a whole, i.e., an unsafe fn or a
+    /// closure in an unsafe context, so that its unsafe_defs are all its
+    /// arguments and calls.
+    #[serde(default)]
+    pub(crate) is_unsafe_fn: bool,
 }
 
 impl Summary {
@@ -174,7 +532,8 @@ pub(crate) fn get_callee_global(&self, fn_id: &FnID) -> &Callee {
         panic!("Cannot find the target callee");
     }
 
-    /// Get all the Callee of a call by BB.
+    /// Get all the Callee of a call by BB. It is empty if no callee is
+    /// recorded for the BB, e.g., when the callee has been filtered out.
     pub(crate) fn get_callee_bb(&self, bb: u32) -> Vec::<&Callee> {
         let mut callees = Vec::new();
         for callee in &self.callees {
@@ -182,7 +541,6 @@ pub(crate) fn get_callee_bb(&self, bb: u32) -> Vec::<&Callee> {
                 callees.push(callee);
             }
         }
-        assert!(callees.len() > 0, "Cannot find any callee");
 
         return callees;
     }
@@ -198,6 +556,11 @@ pub(crate) fn is_foreign_callee(&self, callee_fn_id: &FnID) -> bool {
         return self.foreign_callees.contains(callee_fn_id);
     }
 
+    /// Check if a Callee is a deallocation function.
+    pub(crate) fn is_free_callee(&self, callee_fn_id: &FnID) -> bool {
+        return self.free_callees.contains(callee_fn_id);
+    }
+
     pub(crate) fn is_dyn_callee(&self, callee_fn_id: &FnID) -> bool {
         return self.dyn_callees.contains(callee_fn_id);
     }
@@ -207,6 +570,13 @@ pub fn name(&self) -> String {
         return (self.crate_name.to_owned() + "::" + &self.fn_name).to_owned();
     }
 
+    /// Get the DefId of the methods in the vtables created by this function.
+    pub fn vtable_fn_def_ids(&self) -> Vec<DefId> {
+        self.vtable_fns.iter()
+            .filter_map(|method| method.def_id.map(assemble_def_id))
+            .collect()
+    }
+
     #[allow(dead_code)]
     pub(crate) fn def_id(&self) -> DefId {
         assemble_def_id(self.def_id)
@@ -220,21 +590,159 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
     }
 }
 
+/// Configuration of the compilation that generates a summary file.
+///
+/// A single compilation only sees the code of one cfg set. Recording the cfg
+/// set allows a later tool to tell which findings come from which
+/// configuration, and to flag functions whose unsafe behavior depends on it.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SummaryHeader {
+    /// Target triple.
+    pub target: String,
+    /// Active cfg options (including features) in the form of `name` or
+    /// `name="value"`, sorted.
+    pub cfg: Vec<String>,
+    /// Generation of the summary directory when the file was written; see
+    /// KEEP_MARKER.
+    #[serde(default)]
+    pub generation: u64,
+}
+
+impl SummaryHeader {
+    /// Get the configuration of the current compilation.
+    pub(crate) fn new<'tcx>(tcx: TyCtxt<'tcx>) -> SummaryHeader {
+        let mut cfg = Vec::new();
+        for (name, value) in &tcx.sess.parse_sess.config {
+            match value {
+                Some(value) => cfg.push(format!("{}=\"{}\"", name, value)),
+                None => cfg.push(name.to_string()),
+            }
+        }
+        cfg.sort();
+
+        SummaryHeader {
+            target: tcx.sess.opts.target_triple.triple().to_owned(),
+            cfg: cfg,
+            generation: 0,
+        }
+    }
+}
+
+/// Content of a summary file: the header and the summaries of a crate.
+/// S is &[Summary] for serialization and Vec<Summary> for deserialization.
+#[derive(Serialize, Deserialize)]
+pub(crate) struct SummaryFile<S> {
+    pub(crate) header: SummaryHeader,
+    pub(crate) summaries: S,
+}
+
 /// Entrance of this module.
-pub fn summarize<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId,
-                       summaries: &mut Vec::<Summary>) {
-    // Filter out uninterested functions.
-    if ignore_fn(tcx, def_id) { return; }
+pub fn summarize<'tcx>(cx: &mut AnalysisCtxt<'tcx>, def_id: DefId,
+                       summaries: &mut Vec::<Summary>) -> SandboxResult<()> {
+    // Filter out uninterested functions and those without MIR.
+    let Some(func) = cx.get(def_id) else { return Ok(()); };
+
+    summarize_body(cx.tcx(), def_id, func.fn_id, func.body, summaries)
+}
+
+/// Summarize the initializer of a static, e.g., "static FOO: T = unsafe {..};".
+/// Statics are often used as FFI globals, and their initializers may contain
+/// unsafe code. The Summary is recorded under the FnID of the static itself.
+pub fn summarize_static<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId,
+                              summaries: &mut Vec::<Summary>) -> SandboxResult<()> {
+    if ignore_fn(tcx, def_id) { return Ok(()); }
+
+    // The initializer of a static is only available as CTFE MIR.
+    summarize_body(tcx, def_id, get_fn_fingerprint(tcx, def_id), tcx.mir_for_ctfe(def_id),
+                   summaries)
+}
+
+/// Check if only the functions with unsafe code, and their transitive callers
+/// and callees, are analyzed (SANDBOX_UNSAFE_ROOTS_ONLY=1).
+pub fn unsafe_roots_only() -> bool {
+    return std::env::var("SANDBOX_UNSAFE_ROOTS_ONLY").map_or(false, |v| v == "1");
+}
+
+/// Select the functions to summarize when only unsafe code and what it
+/// interacts with are of interest (SANDBOX_UNSAFE_ROOTS_ONLY=1).
+///
+/// It first cheaply finds the functions that are unsafe or contain unsafe
+/// blocks. Using them as roots, it then selects among @def_ids, i.e., the
+/// functions of the current crate, the roots and their transitive callers, and
+/// the transitive callees of all of them; see select_rooted().
+///
+/// Only the calls within the current crate are followed. A function of another
+/// crate is selected only if it is rooted in its own crate, so the WPA treats
+/// a callee without a summary as unknown in this mode instead of failing.
+pub fn select_unsafe_rooted<'tcx>(cx: &mut AnalysisCtxt<'tcx>, def_ids: &FxHashSet<DefId>)
+    -> FxHashSet<DefId> {
+    let tcx = cx.tcx();
+    let mut callees = FxHashMap::<DefId, Vec<DefId>>::default();
+    let mut callers = FxHashMap::<DefId, Vec<DefId>>::default();
+    let mut roots = Vec::new();
+    for def_id in def_ids {
+        let Some(AnalysisFn { body, .. }) = cx.get(*def_id) else { continue; };
+        if unsafe_def::has_unsafe_code(body) {
+            roots.push(*def_id);
+        }
+        for bbd in body.basic_blocks().iter() {
+            let terminator = bbd.terminator();
+            let callee_ids = match &terminator.kind {
+                TerminatorKind::Call{func: Operand::Constant(f), ..} => {
+                    calls::resolve_callee(tcx, f).unwrap_or_default()
+                },
+                _ => calls::get_drop_impl(tcx, body, terminator)
+                        .map(|(drop_id, _)| drop_id).into_iter().collect(),
+            };
+            for callee_id in callee_ids {
+                if def_ids.contains(&callee_id) {
+                    callees.entry(*def_id).or_default().push(callee_id);
+                    callers.entry(callee_id).or_default().push(*def_id);
+                }
+            }
+        }
+    }
+
+    select_rooted(&roots, &callees, &callers)
+}
+
+/// Select the roots and their transitive callers, and then the transitive
+/// callees of all of them. The callees of a caller are selected as well, e.g.,
+/// a safe helper that a caller of an unsafe fn calls, as the WPA needs the
+/// summary of every callee it traces a return value into.
+pub(crate) fn select_rooted<T: Copy + Eq + Hash>(roots: &[T], callees: &FxHashMap<T, Vec<T>>,
+                                                 callers: &FxHashMap<T, Vec<T>>) -> FxHashSet<T> {
+    let reach = |from: Vec<T>, edges: &FxHashMap<T, Vec<T>>| {
+        let mut visited = from.iter().copied().collect::<FxHashSet<_>>();
+        let mut to_process = from;
+        while let Some(node) = to_process.pop() {
+            for next in edges.get(&node).into_iter().flatten() {
+                if visited.insert(*next) {
+                    to_process.push(*next);
+                }
+            }
+        }
+        visited
+    };
+
+    let with_callers = reach(roots.to_vec(), callers);
+    reach(with_callers.into_iter().collect(), callees)
+}
+
+/// Summarize a function or static initializer of the given MIR body.
+fn summarize_body<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId, fn_id: FnID,
+                        body: &'tcx Body<'tcx>, summaries: &mut Vec::<Summary>)
+    -> SandboxResult<()> {
 
     // Init a summary.
     let crate_name = get_crate_name(def_id);
-    let fn_name = tcx.opt_item_name(def_id).unwrap().to_ident_string();
+    let fn_name = get_fn_name(def_id);
     if _DEBUG {
         println!("[summarize_fn::calls]: Processing fn {}", tcx.def_path_debug_str(def_id));
     }
 
     let mut summary = Summary {
-        fn_id: get_fn_fingerprint(tcx, def_id),
+        fn_id: fn_id,
         fn_name: fn_name,
         crate_name: crate_name,
         def_id: break_def_id(def_id),
@@ -242,23 +750,65 @@ pub fn summarize<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId,
         ret_defs: (FxHashSet::default(), Vec::new()),
         unsafe_defs: None,
         foreign_callees: FxHashSet::default(),
+        free_callees: FxHashSet::default(),
         dyn_callees: FxHashSet::default(),
+        vtable_fns: Vec::new(),
+        self_ref_allocs: Vec::new(),
+        drop_defs: Vec::new(),
+        dyn_fn_calls: Vec::new(),
+        dyn_fn_closures: Vec::new(),
+        fn_ptr_fns: Vec::new(),
+        has_inline_asm: false,
+        has_intrinsic_call: false,
+        source_mutability: Vec::new(),
+        alloc_types: Vec::new(),
+        is_entry: tcx.entry_fn(()).map_or(false, |(entry, _)| entry == def_id),
+        is_exported: def_id.as_local().map_or(false, |local_def_id|
+            tcx.effective_visibilities(()).is_exported(local_def_id)),
+        is_unsafe_fn: false,
     };
 
-    let body = tcx.optimized_mir(def_id);
-
     // Analyze calls and return values.
-    calls::analyze_fn(tcx, body, &mut summary);
+    calls::analyze_fn(tcx, body, &mut summary)?;
+
+    // A foreign item is never a candidate of a dynamic call.
+    debug_assert!(summary.foreign_callees.is_disjoint(&summary.dyn_callees),
+        "{} has a callee that is both foreign and dyn", summary.name());
+    summary.dyn_callees.retain(|fn_id| !summary.foreign_callees.contains(fn_id));
 
     // Find the def sites of Place used in unsafe code.
     unsafe_def::analyze_fn(body, &mut summary);
+    if let Some(unsafe_defs) = &summary.unsafe_defs {
+        summary.source_mutability =
+            unsafe_def::find_source_mutability(tcx, body, unsafe_defs);
+    }
+
+    // Find the def sites of dropped Place for pairing allocs with frees.
+    summary.drop_defs = unsafe_def::find_drop_defs(body);
+
+    // Find heap allocations that may point to themselves.
+    self_ref::analyze_fn(body, &mut summary);
 
     summaries.push(summary);
+
+    Ok(())
+}
+
+/// Check if the summaries of a crate have a main() fn, i.e., it is the main
+/// crate on which the WPA is run.
+pub fn has_main<'tcx>(tcx: TyCtxt<'tcx>, summaries: &[Summary]) -> SandboxResult<bool> {
+    let count = summaries.iter().filter(|summary| is_main(tcx, summary)).count();
+    if count > 1 {
+        return Err(SandboxError::MultipleMains { count });
+    }
+
+    Ok(count == 1)
 }
 
 /// Check if a Summary is for the main() fn.
 pub fn is_main<'tcx>(tcx: TyCtxt<'tcx>, summary: &Summary) -> bool {
     if summary.fn_name != "main" { return false; }
+    if tcx.is_static(assemble_def_id(summary.def_id)) { return false; }
 
     // Check signature. There might be other main fn which have different
     // signatures than the main() in the application itself.
@@ -279,25 +829,149 @@ pub fn is_main<'tcx>(tcx: TyCtxt<'tcx>, summary: &Summary) -> bool {
 // created and those directories contain files named probe{1,2,3..}.
 // Some probe* files are empty. Don't know why they are generated and
 // what they are exactly.
-pub fn write_summaries_to_file<'tcx>(tcx: TyCtxt<'tcx>, summaries: &Vec<Summary>) {
+pub fn write_summaries_to_file<'tcx>(tcx: TyCtxt<'tcx>, summaries: &Vec<Summary>)
+    -> SandboxResult<()> {
     let local_crate_name = get_local_crate_name();
     if ignore_build_crate(&local_crate_name) {
-        return;
+        return Ok(());
     }
 
-    let dir = get_summary_dir();
+    let file_name = local_crate_name + "-" +
+        &tcx.stable_crate_id(LOCAL_CRATE).to_u64().to_string();
+    write_summary_files(&get_summary_dir(), &file_name, &SummaryHeader::new(tcx),
+                        summaries, get_max_summary_bytes()?)?;
+
+    Ok(())
+}
+
+/// Write the summaries of a crate to a summary directory shared by all the
+/// rustc processes of a build, which run in parallel under cargo. The files
+/// are written while holding the lock file of the directory, so that the
+/// chunks of two processes compiling the same crate are not interleaved.
+/// The files of an earlier compile of the crate are removed first; see
+/// remove_summary_files(). The header of each file records the current
+/// generation of the directory. It returns the number of files written.
+///
+/// Inputs:
+/// @dir: The summary directory, created if it does not exist.
+/// @file_name: Name of the summary file of the crate.
+/// @header, @summaries, @max_bytes: See write_summary_chunks().
+pub(crate) fn write_summary_files(dir: &str, file_name: &str, header: &SummaryHeader,
+                                  summaries: &[Summary], max_bytes: Option<usize>)
+    -> SandboxResult<usize> {
     // Create the directory for the summary files of all dependent crates.
-    // No need to sync. It is harmless to fail for "File exists".
-    let _ = fs::create_dir(&dir);
+    // It is harmless to fail for "File exists" as creating it is atomic.
+    let _ = fs::create_dir(dir);
+
+    let _lock = Lock::new(&Path::new(dir).join(SUMMARY_LOCK), true, true, true)?;
+    remove_summary_files(dir, file_name)?;
+    let header = SummaryHeader { generation: summary_generation(dir), ..header.clone() };
+    let output_file = Path::new(dir).join(file_name);
+    write_summary_chunks(&header, summaries, &output_file.to_string_lossy(), max_bytes)
+}
 
-    // Serialize summaries to a string and write the string to a file.
-    let serialized = serde_json::to_string(&summaries).unwrap();
-    let output_file = dir + "/" + &local_crate_name + "-" +
-        &tcx.stable_crate_id(LOCAL_CRATE).to_u64().to_string();
-    fs::write(output_file.as_str(), &serialized).
-        expect("Failed to write summaries");
+/// Get the generation of a summary directory, i.e., the number in its
+/// KEEP_MARKER, or 0 if it has never been kept.
+pub(crate) fn summary_generation(dir: &str) -> u64 {
+    return fs::read_to_string(Path::new(dir).join(KEEP_MARKER)).ok()
+        .and_then(|generation| generation.trim().parse().ok()).unwrap_or(0);
+}
+
+/// Keep a summary directory after WPA by bumping its generation, so that the
+/// summary files written so far are ignored by a later build that reuses it.
+pub(crate) fn keep_summary_dir(dir: &str) -> SandboxResult<()> {
+    let _lock = Lock::new(&Path::new(dir).join(SUMMARY_LOCK), true, true, true)?;
+    fs::write(Path::new(dir).join(KEEP_MARKER), format!("{}\n", summary_generation(dir) + 1))?;
+
+    Ok(())
+}
+
+/// Remove the summary files of a crate written by an earlier compile of it,
+/// i.e., "{file_name}" and its chunks "{file_name}.{i}". Otherwise the reader
+/// would still collect the summaries of the functions that have since been
+/// removed or renamed, or the chunks that the new files do not overwrite. It
+/// must be called with the lock of the summary directory held.
+fn remove_summary_files(dir: &str, file_name: &str) -> SandboxResult<()> {
+    for entry in fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name();
+        let Some(name) = name.to_str() else { continue; };
+        let is_chunk = name.strip_prefix(file_name)
+            .and_then(|suffix| suffix.strip_prefix('.'))
+            .is_some_and(|i| !i.is_empty() && i.bytes().all(|b| b.is_ascii_digit()));
+        if name == file_name || is_chunk {
+            fs::remove_file(entry.path())?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Write a file atomically, i.e., write to a temporary file in the same
+/// directory and then rename it to the target path.
+fn write_file_atomic(path: &str, contents: &str) -> SandboxResult<()> {
+    let tmp_path = format!("{}.{}.{}.{}", path, std::process::id(),
+        TMP_FILE_NUM.fetch_add(1, AtomicOrdering::Relaxed), SUMMARY_TMP_EXT);
+    fs::write(&tmp_path, contents)?;
+    fs::rename(&tmp_path, path)?;
+
+    Ok(())
+}
+
+/// Serialize summaries and write them to one or more files.
+///
+/// The summaries are sorted by FnID so that the same summaries are always
+/// written as the same bytes, no matter in which order they are collected.
+/// If the serialized summaries exceed max_bytes, they are split into chunks
+/// that are each written to "{output_file}.{i}" as a complete SummaryFile, so
+/// that the reader simply collects the summaries of all files in the summary
+/// directory. A single Summary larger than max_bytes is put in its own chunk.
+/// It returns the number of files written.
+///
+/// Inputs:
+/// @header: Header of every file.
+/// @summaries: All the summaries of a crate.
+/// @output_file: Path of the summary file of a crate.
+/// @max_bytes: Soft limit of the size of each file, or None for no limit.
+pub(crate) fn write_summary_chunks(header: &SummaryHeader, summaries: &[Summary],
+                                   output_file: &str, max_bytes: Option<usize>)
+    -> SandboxResult<usize> {
+    let mut summaries = summaries.iter().collect::<Vec<&Summary>>();
+    summaries.sort_by_key(|summary| summary.fn_id);
+
+    // Split the summaries by their serialized sizes.
+    let mut chunks = Vec::<&[&Summary]>::new();
+    let mut start = 0;
+    let mut chunk_bytes = 0;
+    if let Some(max_bytes) = max_bytes {
+        for (i, summary) in summaries.iter().enumerate() {
+            let bytes = serde_json::to_vec(summary)?.len();
+            if i > start && chunk_bytes + bytes > max_bytes {
+                chunks.push(&summaries[start..i]);
+                start = i;
+                chunk_bytes = 0;
+            }
+            chunk_bytes += bytes;
+        }
+    }
+    chunks.push(&summaries[start..]);
+
+    for (i, chunk) in chunks.iter().enumerate() {
+        let summary_file = SummaryFile {
+            header: header.clone(),
+            summaries: *chunk,
+        };
+        let serialized = serde_json::to_string(&summary_file)?;
+        if chunks.len() == 1 {
+            write_file_atomic(output_file, &serialized)?;
+        } else {
+            write_file_atomic(&format!("{}.{}", output_file, i), &serialized)?;
+        }
+
+        if _DEBUG {
+            println!("\nSerialized Summaries: {:?}", serialized);
+        }
+    }
 
-     if _DEBUG {
-         println!("\nSerialized Summaries: {:?}", serialized);
-     }
+    Ok(chunks.len())
 }
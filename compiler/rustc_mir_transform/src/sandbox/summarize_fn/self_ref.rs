@@ -0,0 +1,153 @@
+//! Find heap allocations that may be self-referential, i.e., a pointer to an
+//! allocation is stored back into the allocation itself, such as a node of an
+//! intrusive linked list that points to itself. Such structures are prone to
+//! bugs when the allocation is moved or freed while the pointer is still used.
+
+use rustc_middle::mir::*;
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+
+use crate::sandbox::utils::*;
+use super::{DefSite, Summary};
+
+// For debugging purpose.
+static _DEBUG: bool = false;
+
+/// Add the heap allocation sites of the Local in @from to the Local @to.
+/// Return true if the allocation sites of @to change.
+fn add_allocs(allocs: &mut FxHashMap<Local, FxHashSet<u32>>, to: Local,
+              from: &FxHashSet<Local>) -> bool {
+    let mut sites = FxHashSet::<u32>::default();
+    for local in from {
+        if let Some(local_sites) = allocs.get(local) {
+            sites.extend(local_sites.iter().copied());
+        }
+    }
+    if sites.is_empty() { return false; }
+
+    let to_sites = allocs.entry(to).or_default();
+    let old_len = to_sites.len();
+    to_sites.extend(sites);
+    to_sites.len() != old_len
+}
+
+/// Get the Local of each operand of an Rvalue that may pass on a pointer as
+/// is: a copy, move or cast of a Place not read through a dereference, or a
+/// reborrow into the pointee, e.g., "&mut (*p).f". A value loaded through a
+/// pointer, e.g., "(*p).f", is the content of the allocation rather than a
+/// pointer to it.
+fn get_pointer_locals<'tcx>(rvalue: &Rvalue<'tcx>, locals: &mut FxHashSet<Local>) {
+    match rvalue {
+        Rvalue::Use(operand) | Rvalue::Cast(_, operand, _) => {
+            if let Some(place) = operand.place() && !place.is_indirect() {
+                locals.insert(place.local);
+            }
+        },
+        Rvalue::Aggregate(_, operands) => {
+            for operand in operands {
+                if let Some(place) = operand.place() && !place.is_indirect() {
+                    locals.insert(place.local);
+                }
+            }
+        },
+        Rvalue::Ref(_, _, place) | Rvalue::AddressOf(_, place) => {
+            let derefs = place.projection.iter()
+                .filter(|elem| *elem == ProjectionElem::Deref).count();
+            if derefs == 1 && place.projection[0] == ProjectionElem::Deref {
+                locals.insert(place.local);
+            }
+        },
+        _ => {}
+    }
+}
+
+/// Map each Local to the heap allocation sites (BB of the allocation call)
+/// whose pointer may flow to it. It is a flow-insensitive analysis: a
+/// pointer-typed Local gets the allocation sites of the pointers copied,
+/// cast or reborrowed to define it (see get_pointer_locals()), and the
+/// return value of a native call gets those of the call's arguments. Repeat
+/// until no new allocation site is added to any Local.
+fn collect_alloc_sites<'tcx>(body: &'tcx Body<'tcx>)
+    -> FxHashMap<Local, FxHashSet<u32>> {
+    let mut allocs = FxHashMap::<Local, FxHashSet<u32>>::default();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for (bb, bbd) in body.basic_blocks().iter_enumerated() {
+            for stmt in &bbd.statements {
+                if let StatementKind::Assign(box (place, rvalue)) = &stmt.kind {
+                    // A write through a pointer or to a field does not make
+                    // the whole Local point to the allocation, and neither
+                    // does a write to a Local that cannot hold a pointer.
+                    if !place.projection.is_empty() { continue; }
+                    let ty = body.local_decls[place.local].ty;
+                    if !(ty.is_any_ptr() || ty.is_box()) { continue; }
+                    let mut locals = FxHashSet::<Local>::default();
+                    get_pointer_locals(rvalue, &mut locals);
+                    changed |= add_allocs(&mut allocs, place.local, &locals);
+                }
+            }
+
+            if let TerminatorKind::Call{func: Operand::Constant(f), args, ..} =
+                &bbd.terminator().kind {
+                let ret = call_destination_local(bbd.terminator(), body);
+                if ret.is_none() { continue; }
+                match def_site_from_call(f, bb.as_u32()) {
+                    Some(DefSite::HeapAlloc(_)) => {
+                        changed |= allocs.entry(ret.unwrap()).or_default()
+                            .insert(bb.as_u32());
+                    },
+                    Some(def_site @ (DefSite::NativeCall(_) | DefSite::Transmute(_) |
+                                     DefSite::RawSlice(_))) => {
+                        // E.g., "p = Box::into_raw(b)".
+                        let mut locals = FxHashSet::<Local>::default();
+                        get_local_in_args(def_site.tainting_args(args), &mut locals);
+                        changed |= add_allocs(&mut allocs, ret.unwrap(), &locals);
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    allocs
+}
+
+/// Entrance of this module.
+///
+/// It records in the Summary each heap allocation site that may have its
+/// pointer stored into a field of itself, i.e., a write "(*p).f = q" or
+/// "(*p) = Node { .., q, .. }" where p and q may point to the same allocation.
+pub(super) fn analyze_fn<'tcx>(body: &'tcx Body<'tcx>, summary: &mut Summary) {
+    let allocs = collect_alloc_sites(body);
+    if allocs.is_empty() { return; }
+
+    let mut self_ref_allocs = FxHashSet::<u32>::default();
+    for bbd in body.basic_blocks().iter() {
+        for stmt in &bbd.statements {
+            if let StatementKind::Assign(box (place, rvalue)) = &stmt.kind {
+                if !place.projection.iter().any(|elem| elem == ProjectionElem::Deref) {
+                    continue;
+                }
+                let target_sites = match allocs.get(&place.local) {
+                    Some(sites) => sites,
+                    None => continue
+                };
+                let mut locals = FxHashSet::<Local>::default();
+                get_pointer_locals(rvalue, &mut locals);
+                for local in locals {
+                    if let Some(value_sites) = allocs.get(&local) {
+                        self_ref_allocs.extend(target_sites.intersection(value_sites));
+                    }
+                }
+            }
+        }
+    }
+
+    if _DEBUG && !self_ref_allocs.is_empty() {
+        println!("[self_ref]: {} may have self-referential allocations at BB {:?}",
+            summary.name(), self_ref_allocs);
+    }
+
+    summary.self_ref_allocs = self_ref_allocs.into_iter().collect();
+    summary.self_ref_allocs.sort();
+}
@@ -2,11 +2,12 @@
 //! unsafe code.
 
 use rustc_middle::mir::*;
+use rustc_middle::ty::TyCtxt;
 use rustc_data_structures::fx::{FxHashSet,FxHashMap};
 
 use crate::sandbox::utils::*;
 use crate::sandbox::debug::*;
-use super::{DefSite,Summary};
+use super::{DefSite,PtrMutability,Summary};
 
 // For debugging purpose.
 static _DEBUG: bool = false;
@@ -21,22 +22,52 @@ struct UnsafeOp <'tcx> {
 }
 
 /// Check if a fn is unsafe, or if a statement/terminator in an unsafe block.
-fn is_unsafe<'tcx>(body: &Body<'tcx>, scope: SourceScope) -> bool {
-    match body.source_scopes[scope].local_data.as_ref() {
-        ClearCrossCrate::Clear => false,
-        ClearCrossCrate::Set(v) => {
-            match v.safety {
-                Safety::ExplicitUnsafe(_) | Safety::FnUnsafe => true,
-                // TODO?: Handle BuiltinUnsafe
-                _ => false
+///
+/// The safety of a scope may be cleared, e.g., for a scope inlined from a
+/// closure or from another crate. The safety of such a scope is that of its
+/// closest ancestor scope whose safety is set.
+pub(super) fn is_unsafe<'tcx>(body: &Body<'tcx>, scope: SourceScope) -> bool {
+    let mut scope = Some(scope);
+    while let Some(s) = scope {
+        let scope_data = &body.source_scopes[s];
+        match scope_data.local_data.as_ref() {
+            ClearCrossCrate::Clear => scope = scope_data.parent_scope,
+            ClearCrossCrate::Set(v) => {
+                return match v.safety {
+                    Safety::ExplicitUnsafe(_) | Safety::FnUnsafe => true,
+                    // TODO?: Handle BuiltinUnsafe
+                    _ => false
+                };
             }
         }
     }
+
+    false
+}
+
+/// Check if a whole function is unsafe, i.e., an unsafe fn or a closure in an
+/// unsafe context (see is_closure_in_unsafe()).
+fn is_unsafe_fn<'tcx>(body: &Body<'tcx>) -> bool {
+    is_unsafe(body, SourceInfo::outermost(body.span).scope) ||
+        is_closure_in_unsafe(body.source.def_id())
+}
+
+/// Check if a function contains any unsafe code.
+pub(super) fn has_unsafe_code<'tcx>(body: &Body<'tcx>) -> bool {
+    if is_unsafe_fn(body) {
+        return true;
+    }
+    body.basic_blocks().iter().any(|bbd| {
+        bbd.statements.iter().any(|stmt| is_unsafe(body, stmt.source_info.scope)) ||
+            is_unsafe(body, bbd.terminator().source_info.scope)
+    })
 }
 
 /// Collect unsafe allocation sites of an unsafe function. It does not need to
 /// analyze the data flow of the function; instead, it only needs to collect all
-/// fn arguments and return values of function calls.
+/// fn arguments, return values of function calls, and Box allocated in place.
+/// Like in safe functions, a native call is only collected if it originates
+/// the pointer it returns.
 ///
 /// Inputs:
 /// @body: The body of the target function.
@@ -48,9 +79,19 @@ fn find_unsafe_fn_def<'tcx>(body: &'tcx Body<'tcx>,
     }
 
     for (bb, bbd) in body.basic_blocks().iter_enumerated() {
+        for stmt in &bbd.statements {
+            if let StatementKind::Assign(box (_, rvalue)) = &stmt.kind {
+                results.extend(def_site_from_rvalue(rvalue, body, bb.as_u32()));
+            }
+        }
         match &bbd.terminator().kind {
-            TerminatorKind::Call{func: Operand::Constant(f), ..} => {
-                results.insert(def_site_from_call(f, bb.as_u32()));
+            TerminatorKind::Call{func: Operand::Constant(f), args, ..} => {
+                // A native call is a source only if it originates the pointer
+                // it returns, e.g., not len() or wrapping_add().
+                let ret = call_destination_local(bbd.terminator(), body);
+                results.extend(def_site_from_call(f, bb.as_u32()).filter(|def_site|
+                    !matches!(def_site, DefSite::NativeCall(_)) ||
+                        ret.is_some_and(|ret| is_native_ptr_origin(body, args, ret))));
             },
             _ => {}
         }
@@ -64,43 +105,140 @@ fn find_unsafe_fn_def<'tcx>(body: &'tcx Body<'tcx>,
 /// is an def site for _2, and _3 is a contributor to _2 and thus will be put
 /// the unsafe Place set.
 ///
+/// The traversal is depth-first over the predecessors. A BB with a single
+/// predecessor passes on its unsafe Place, and a BB with multiple ones passes
+/// a copy to each of them in case of branches. It uses an explicit stack of
+/// the branches rather than recursion, as a function with a large CFG, e.g., a
+/// generated parser, would overflow the stack.
+///
 /// Inputs:
-/// @place_locals: The Local of all the Place used directly or indirectly (e.g.,
-///                by assignment) by unsafe code.
-/// @bb: The currently processed BasicBlock.
-/// @unsafe_op: The last unsafe operation in a BB, or None.
+/// @place_locals: All the Place used directly or indirectly (e.g., by
+///                assignment) by unsafe code, each as a Local or a field of it.
+/// @bb: The BasicBlock to start from.
+/// @unsafe_op: The last unsafe operation in @bb, or None.
 /// @visited: Already processed BasicBlock.
 /// @body: The function body of the current BB.
+/// @mut_borrows: Local that mutably borrow another Local; see get_mut_borrows().
 /// @results: Unsafe def sites.
-fn find_unsafe_def_core<'tcx>(place_locals: &mut FxHashSet<Local>,
+fn find_unsafe_def_core<'tcx>(place_locals: &mut UnsafePlaces,
                               bb: BasicBlock,
                               unsafe_op: Option<&UnsafeOp<'tcx>>,
                               visited: &mut FxHashSet<BasicBlock>,
                               body: &'tcx Body<'tcx>,
+                              mut_borrows: &FxHashMap<Local, Local>,
                               results: &mut FxHashSet::<DefSite>) {
-    // Prevent infinite recursions caused by loops.
-    if !visited.insert(bb) { return; }
+    // Branches yet to traverse, each with its own copy of the unsafe Place,
+    // the next one on the top.
+    let mut branches = Vec::<(BasicBlock, UnsafePlaces)>::new();
+    find_unsafe_def_in_chain(place_locals, bb, unsafe_op, visited, body, mut_borrows,
+                             results, &mut branches);
+    while let Some((pbb, mut branch_locals)) = branches.pop() {
+        find_unsafe_def_in_chain(&mut branch_locals, pbb, None, visited, body, mut_borrows,
+                                 results, &mut branches);
+    }
+}
 
-    // Has handled all target Place.
-    if place_locals.is_empty() { return; }
+/// Traverse backward from a BB through the BB that have a single predecessor,
+/// sharing @place_locals, until a BB that has none or multiple predecessors.
+/// The predecessors of the latter are pushed to @branches, in the order they
+/// should be traversed, each with a copy of @place_locals.
+///
+/// See find_unsafe_def_core() for the other inputs.
+fn find_unsafe_def_in_chain<'tcx>(place_locals: &mut UnsafePlaces,
+                                  mut bb: BasicBlock,
+                                  mut unsafe_op: Option<&UnsafeOp<'tcx>>,
+                                  visited: &mut FxHashSet<BasicBlock>,
+                                  body: &'tcx Body<'tcx>,
+                                  mut_borrows: &FxHashMap<Local, Local>,
+                                  results: &mut FxHashSet::<DefSite>,
+                                  branches: &mut Vec<(BasicBlock, UnsafePlaces)>) {
+    let mut reached_entry = false;
+    while find_unsafe_def_in_bb(place_locals, bb, unsafe_op, visited, body, mut_borrows,
+                                results) {
+        reached_entry |= bb == START_BLOCK;
+        let preds = &body.predecessors()[bb];
+        if preds.len() == 1 {
+            // There is only one predecessor. Just pass the original place_locals.
+            bb = preds[0];
+            unsafe_op = None;
+        } else {
+            // Pass a clone of place_locals in case of branches. The first
+            // predecessor is traversed first.
+            branches.extend(preds.iter().rev().map(|pbb| (*pbb, place_locals.clone())));
+            break;
+        }
+    }
 
+    // After examing the entry BB and its single-predecessor chain, if any,
+    // check if there are any unsafe Place from the function's arguments.
+    if reached_entry && !place_locals.is_empty() {
+       for arg in body.args_iter() {
+           if place_locals.remove_local(&arg) {
+               results.insert(DefSite::Arg(arg.as_u32()));
+           }
+       }
+    }
+}
+
+/// Examine a BB backward for def sites of @place_locals, starting from
+/// @unsafe_op if any or from the Terminator otherwise. It returns whether
+/// the traversal should go on to the predecessors of the BB, i.e., the BB has
+/// not been visited and there are still unsafe Place to find def sites for.
+///
+/// See find_unsafe_def_core() for the inputs.
+fn find_unsafe_def_in_bb<'tcx>(place_locals: &mut UnsafePlaces,
+                               bb: BasicBlock,
+                               unsafe_op: Option<&UnsafeOp<'tcx>>,
+                               visited: &mut FxHashSet<BasicBlock>,
+                               body: &'tcx Body<'tcx>,
+                               mut_borrows: &FxHashMap<Local, Local>,
+                               results: &mut FxHashSet::<DefSite>) -> bool {
     let bbd = &body.basic_blocks()[bb];
+
+    // Prevent infinite traversals caused by loops. Starting from an unsafe
+    // Statement only examines the part of the BB before it, so the BB is not
+    // marked as visited; if it is in a loop, the rest of it, e.g., the update
+    // of a loop-carried pointer after the unsafe Statement, is examined when
+    // the traversal reaches it again via the back edge.
     let stmt_num = bbd.statements.len();
+    let partial = unsafe_op.is_some_and(|op| op.location.statement_index < stmt_num);
+    if !partial && !visited.insert(bb) { return false; }
+
+    // Has handled all target Place.
+    if place_locals.is_empty() { return false; }
+
     let location = match unsafe_op {
         Some(op) => op.location,
         None => Location { block: bb, statement_index: stmt_num }
     };
-    let mut stmt_index = location.statement_index;
+    // Index of the statement after the last one to examine. The unsafe
+    // statement itself is examined as it may define an unsafe Place.
+    let mut stmt_end = location.statement_index + 1;
     if location.statement_index == stmt_num {
         // Examine a terminator.
-        if let TerminatorKind::Call{func: Operand::Constant(f), args,
-                                    destination, ..} = &bbd.terminator().kind {
-            if place_locals.contains(&destination.local) {
+        if let TerminatorKind::Call{func: Operand::Constant(f), args, ..} =
+            &bbd.terminator().kind
+            && let Some(def_site) = def_site_from_call(f, bb.as_u32()) {
+            // ptr::drop_in_place is unsafe, so a call to it is always an
+            // unsafe operation. Record it; its pointer argument is tracked
+            // like other unsafe Place to find where the pointee is allocated.
+            if matches!(def_site, DefSite::DropInPlace(_)) {
+                results.insert(def_site);
+            }
+            let ret = call_destination_local(bbd.terminator(), body)
+                .filter(|ret| place_locals.contains_local(ret));
+            if let Some(ret) = ret {
                 // Found a definition site for an unsafe Place.
-                place_locals.remove(&destination.local);
-                let def_site = def_site_from_call(f, bb.as_u32());
+                place_locals.remove_local(&ret);
                 match def_site {
-                    DefSite::HeapAlloc(_) => {
+                    DefSite::HeapAlloc(_) | DefSite::Uninit(_) => {
+                        // An allocation returns a pointer to new memory,
+                        // which cannot be one of its own arguments.
+                        debug_assert!({
+                            let mut arg_locals = FxHashSet::<Local>::default();
+                            get_local_in_args(args, &mut arg_locals);
+                            !arg_locals.contains(&ret)
+                        }, "Allocation call defines its own argument {:?}", ret);
                         results.insert(def_site);
                         // Question: Do we need to handle argument(s) to a
                         // heap allocation, e.g., Vec::from_raw_parts()?
@@ -109,10 +247,25 @@ fn find_unsafe_def_core<'tcx>(place_locals: &mut FxHashSet<Local>,
                         // Since we do not analyze native functions, we need
                         // conservatively assume that all arguments to such
                         // a function contribute to the return value.
-                        get_local_in_args(args, place_locals);
-                        // No need to add this def_site to results. Or we can
-                        // add only the def_site without adding args, and wait
-                        // for WPA to process args.
+                        let mut arg_locals = FxHashSet::<Local>::default();
+                        get_local_in_args(args, &mut arg_locals);
+                        place_locals.insert_locals(arg_locals);
+                        // No need to add this def_site to results, unless
+                        // the call originates the pointer it returns, e.g.,
+                        // CString::into_raw(). Then there is no argument to
+                        // trace the pointer back to.
+                        if is_native_ptr_origin(body, args, ret) {
+                            results.insert(def_site);
+                        }
+                    },
+                    DefSite::Transmute(_) | DefSite::RawSlice(_) => {
+                        // A transmute is an unsafe source, and its result
+                        // also carries the taint of the transmuted value. So
+                        // is a raw slice, but only of its pointer.
+                        let mut arg_locals = FxHashSet::<Local>::default();
+                        get_local_in_args(def_site.tainting_args(args), &mut arg_locals);
+                        place_locals.insert_locals(arg_locals);
+                        results.insert(def_site);
                     },
                     DefSite::OtherCall(_) => {
                         // For a normal call, we only need to track args that
@@ -123,57 +276,111 @@ fn find_unsafe_def_core<'tcx>(place_locals: &mut FxHashSet<Local>,
                     },
                     _ => {}
                 }
+            } else if matches!(def_site, DefSite::NativeCall(_)) &&
+                get_mut_borrowed_args(args, mut_borrows).iter()
+                    .any(|local| place_locals.contains_local(local)) {
+                // A native call like "v.push(p)" may store its arguments to
+                // an unsafe Place borrowed by the receiver, which is still
+                // an unsafe Place as the call only partially defines it.
+                let mut arg_locals = FxHashSet::<Local>::default();
+                get_local_in_args(args, &mut arg_locals);
+                place_locals.insert_locals(arg_locals);
+            }
+        } else if let TerminatorKind::Call{args, ..} = &bbd.terminator().kind {
+            // A call through a function pointer, which may be a constant.
+            // Like a native call, all its arguments may contribute to the
+            // return value. Like a normal call, so may the return value of
+            // its callee if it is resolved (see resolve_callee()).
+            let ret = call_destination_local(bbd.terminator(), body);
+            if ret.is_some_and(|ret| place_locals.remove_local(&ret)) {
+                let mut arg_locals = FxHashSet::<Local>::default();
+                get_local_in_args(args, &mut arg_locals);
+                place_locals.insert_locals(arg_locals);
+                results.insert(DefSite::OtherCall(bb.as_u32()));
             }
         }
-        stmt_index -= 1;
+        stmt_end = stmt_num;
     }
 
-    if stmt_num != 0 {
-        // Examine each statement in the current BB backward.
-        for i in (0..=stmt_index).rev() {
-            let stmt = &bbd.statements[i];
-            match &stmt.kind {
-                StatementKind::Assign(box (place, rvalue)) => {
-                    if place_locals.contains(&place.local) {
-                        place_locals.remove(&place.local);
-                        // Put the Place in rvalue to the unsafe Place set.
-                        let mut place_in_rvalue = Vec::<Place<'tcx>>::new();
-                        get_place_in_rvalue(&rvalue, &mut place_in_rvalue);
-                        for place in place_in_rvalue {
-                            place_locals.insert(place.local);
+    // Examine each statement in the current BB backward. Note that a BB may
+    // have no statement at all, e.g., an edge block inserted by coverage
+    // instrumentation may only have a Goto.
+    for i in (0..stmt_end).rev() {
+        let stmt = &bbd.statements[i];
+        match &stmt.kind {
+            StatementKind::Assign(box (place, rvalue)) => {
+                let Some(part) = place_locals.get(&place.local).cloned() else {
+                    continue;
+                };
+                let mut place_in_rvalue = Vec::<Place<'tcx>>::new();
+                match (PlaceKey::of(place).field, part) {
+                    (Some(field), LocalPart::Fields(fields)) => {
+                        // A write to a field only defines that field.
+                        if !fields.contains(&field) { continue; }
+                        if place.projection.len() == 1 {
+                            place_locals.remove_field(&place.local, field);
+                        }
+                        get_source_place_in_rvalue(&rvalue, &mut place_in_rvalue);
+                    },
+                    (Some(_), LocalPart::Whole) => {
+                        // The field write contributes to the unsafe Local,
+                        // whose other fields are defined elsewhere.
+                        get_source_place_in_rvalue(&rvalue, &mut place_in_rvalue);
+                    },
+                    (None, _) if is_variant_field(place) => {
+                        // Likewise, a write to a field of an enum variant
+                        // contributes to the unsafe enum.
+                        get_source_place_in_rvalue(&rvalue, &mut place_in_rvalue);
+                    },
+                    (None, part) => {
+                        place_locals.remove_local(&place.local);
+                        if let Some(def_site) = def_site_from_rvalue(rvalue, body, bb.as_u32()) {
+                            // A Box allocated in place, the address of a
+                            // global, or a union field read. Each is where
+                            // the value originates, so stop here. A transmute
+                            // and an integer-to-pointer cast also carry the
+                            // taint of their operand.
+                            results.insert(def_site);
+                            if !def_site.inherits_taint() { continue; }
                         }
-                    }
-                },
-                _  => {
-                    // Any other cases to handle?
+                        match (part, get_field_operands(rvalue)) {
+                            (LocalPart::Fields(fields), Some(operands))
+                                if place.projection.is_empty() => {
+                                // Only the operands of the unsafe fields.
+                                for (field, operand) in operands.iter_enumerated() {
+                                    if fields.contains(&field.as_u32()) {
+                                        get_place_in_operand(operand, &mut place_in_rvalue);
+                                    }
+                                }
+                            },
+                            _ => {
+                                get_source_place_in_rvalue(&rvalue, &mut place_in_rvalue);
+                            }
+                        }
+                    },
+                }
+                // Put the Place in rvalue to the unsafe Place set.
+                for place in place_in_rvalue {
+                    place_locals.insert_place(&place);
                 }
+            },
+            StatementKind::SetDiscriminant { .. } | StatementKind::Deinit(_) => {
+                // An enum is constructed by a Deinit, writes to the fields of
+                // a variant, and then a SetDiscriminant. Neither of the two
+                // defines a pointer, so an unsafe enum is still tracked for
+                // the writes to its fields before them (see above).
+            },
+            StatementKind::Coverage(_) => {
+                // Coverage counters (-C instrument-coverage) do not affect
+                // any Place.
+            },
+            _  => {
+                // Any other cases to handle?
             }
         }
     }
 
-    // Recursively traverse backward to the current BB's predecessors.
-    let pbb_num = body.predecessors()[bb].len();
-    for pbb in &body.predecessors()[bb] {
-        if pbb_num > 1 {
-            // Pass a clone of place_locals in case of branches.
-            find_unsafe_def_core(&mut place_locals.clone(), *pbb, None,
-                                 visited, body, results);
-        } else {
-            // There is only one predecessor. Just pass the original place_locals.
-            find_unsafe_def_core(place_locals, *pbb, None, visited, body, results);
-        }
-    }
-
-    // After examing the entry BB, check if there are any unsafe Place from
-    // the function's arguments.
-    if bb.index() == 0  && !place_locals.is_empty() {
-       for arg in body.args_iter() {
-           if place_locals.contains(&arg) {
-               results.insert(DefSite::Arg(arg.as_u32()));
-               place_locals.remove(&arg);
-           }
-       }
-    }
+    true
 }
 
 /// Find unsafe definition sites within a non-unsafe function.
@@ -185,9 +392,12 @@ fn find_unsafe_def_core<'tcx>(place_locals: &mut FxHashSet<Local>,
 fn find_unsafe_def<'tcx>(body: &'tcx Body<'tcx>, results: &mut FxHashSet<DefSite>) {
     // Collect operations in unsafe blocks.
     let mut unsafe_ops = Vec::new();
+    // Arguments of unsafe calls whose taint the result does not inherit.
+    let mut untainted_args = FxHashSet::<Local>::default();
     for (bb, bbd) in body.basic_blocks().iter_enumerated() {
         for (i, stmt) in bbd.statements.iter().enumerate() {
-            if !is_unsafe(body, stmt.source_info.scope) {
+            if matches!(stmt.kind, StatementKind::Coverage(_)) ||
+                !is_unsafe(body, stmt.source_info.scope) {
                 continue;
             }
 
@@ -215,6 +425,20 @@ fn find_unsafe_def<'tcx>(body: &'tcx Body<'tcx>, results: &mut FxHashSet<DefSite
                 block: bb, statement_index: bbd.statements.len()
             }};
         get_place_in_terminator(body, &terminator, &mut unsafe_op.places);
+        if let TerminatorKind::Call{func: Operand::Constant(f), args, ..} = &terminator.kind
+            && let Some(def_site) = def_site_from_call(f, bb.as_u32()) {
+            // The result of the call only inherits the taint of some of its
+            // arguments (see DefSite::tainting_args()), e.g., not of the
+            // length passed to slice::from_raw_parts(), so the others are
+            // not used as unsafe Place.
+            let mut untainted = Vec::new();
+            for arg in &args[def_site.tainting_args(args).len()..] {
+                get_place_in_operand(arg, &mut untainted);
+            }
+            unsafe_op.places.retain(|place| !untainted.contains(place));
+            untainted_args.extend(untainted.iter()
+                .filter(|place| place.projection.is_empty()).map(|place| place.local));
+        }
         if !unsafe_op.places.is_empty() {
             unsafe_ops.push(unsafe_op);
         }
@@ -223,6 +447,15 @@ fn find_unsafe_def<'tcx>(body: &'tcx Body<'tcx>, results: &mut FxHashSet<DefSite
         }
     }
 
+    // Neither is the unsafe Statement that only prepares such an argument,
+    // e.g., "_4 = _2" for the length.
+    unsafe_ops.retain(|op| {
+        let stmts = &body.basic_blocks()[op.location.block].statements;
+        !matches!(stmts.get(op.location.statement_index).map(|stmt| &stmt.kind),
+            Some(StatementKind::Assign(box (lhs, _)))
+                if lhs.projection.is_empty() && untainted_args.contains(&lhs.local))
+    });
+
     if unsafe_ops.is_empty() {
         return;
     }
@@ -233,27 +466,65 @@ fn find_unsafe_def<'tcx>(body: &'tcx Body<'tcx>, results: &mut FxHashSet<DefSite
 
     // Map each BasicBlock to the last unsafe operation in it.
     let mut bb_unsafe_ops = FxHashMap::<BasicBlock, UnsafeOp<'tcx>>::default();
-    let mut place_locals = FxHashSet::<Local>::default();
+    let mut place_locals = UnsafePlaces::default();
     for unsafe_op in unsafe_ops {
-        // Collect all interested Place as its Local.
+        // Collect all interested Place as its Local or a field of it.
         for place in &unsafe_op.places {
-            place_locals.insert(place.local);
+            place_locals.insert_place(place);
         }
         // Collect the last unsafe statement/terminator in a block.
         bb_unsafe_ops.insert(unsafe_op.location.block, unsafe_op);
     }
 
     // Examine each BB that contains unsafe operation(s).
+    let mut_borrows = get_mut_borrows(body);
     for (bb, unsafe_op) in bb_unsafe_ops {
         // Record visited BasicBlock to avoid infinite cycles due to loop.
         let mut visited = FxHashSet::<BasicBlock>::default();
         find_unsafe_def_core(&mut place_locals, bb, Some(&unsafe_op),
-                             &mut visited, body, results);
+                             &mut visited, body, &mut_borrows, results);
     }
 
     if _DEBUG { print_unsafe_def(&results); }
 }
 
+/// Find the def sites of the Place dropped by Drop terminators or passed to a
+/// deallocation function of the global allocator API, e.g., std::alloc::dealloc,
+/// i.e., where the memory released may be allocated. It reuses the core
+/// procedure of finding unsafe def sites, starting from each such Terminator.
+/// The def sites are grouped by the BB of the Terminator, sorted.
+pub(super) fn find_drop_defs<'tcx>(body: &'tcx Body<'tcx>)
+    -> Vec<(u32, FxHashSet<DefSite>)> {
+    let mut results = Vec::new();
+    let mut_borrows = get_mut_borrows(body);
+    for (bb, bbd) in body.basic_blocks().iter_enumerated() {
+        let freed = match &bbd.terminator().kind {
+            TerminatorKind::Drop{place, ..} => Some(place.local),
+            TerminatorKind::Call{func: Operand::Constant(f), args, ..}
+                if is_global_dealloc(f) => {
+                match args.first() {
+                    Some(Operand::Move(ptr) | Operand::Copy(ptr)) => Some(ptr.local),
+                    _ => None
+                }
+            },
+            _ => None
+        };
+        if let Some(freed) = freed {
+            let mut place_locals = UnsafePlaces::default();
+            place_locals.insert(PlaceKey::whole(freed));
+            let mut visited = FxHashSet::<BasicBlock>::default();
+            let mut def_sites = FxHashSet::<DefSite>::default();
+            find_unsafe_def_core(&mut place_locals, bb, None, &mut visited,
+                                 body, &mut_borrows, &mut def_sites);
+            if !def_sites.is_empty() {
+                results.push((bb.as_u32(), def_sites));
+            }
+        }
+    }
+
+    results
+}
+
 /// Entrance of this module.
 pub(super) fn analyze_fn<'tcx>(body: &'tcx Body<'tcx>, summary: &mut Summary) {
     if _DEBUG {
@@ -263,8 +534,9 @@ pub(super) fn analyze_fn<'tcx>(body: &'tcx Body<'tcx>, summary: &mut Summary) {
     }
 
     let mut results = FxHashSet::<DefSite>::default();
-    if is_unsafe(body, SourceInfo::outermost(body.span).scope) {
+    if is_unsafe_fn(body) {
         // Process an unsafe function.
+        summary.is_unsafe_fn = true;
         find_unsafe_fn_def(&body, &mut results);
 
         if _DEBUG { print_unsafe_def(&results); }
@@ -277,3 +549,51 @@ pub(super) fn analyze_fn<'tcx>(body: &'tcx Body<'tcx>, summary: &mut Summary) {
         summary.unsafe_defs = Some(results);
     }
 }
+
+/// Find the mutability of the pointer defined at each unsafe def site by the
+/// type of the Local it defines, i.e., an argument or the destination of a
+/// call. For an allocation site, it is the mutability of the returned smart
+/// pointer or raw pointer. For a global, it is the mutability of the global.
+/// Def sites that do not define a pointer are skipped.
+pub(super) fn find_source_mutability<'tcx>(tcx: TyCtxt<'tcx>, body: &'tcx Body<'tcx>,
+                                           unsafe_defs: &FxHashSet<DefSite>)
+    -> Vec<(DefSite, PtrMutability)> {
+    let mut results = Vec::new();
+    for def_site in unsafe_defs {
+        let local = match def_site {
+            DefSite::Arg(arg) => Some(Local::from_u32(*arg)),
+            DefSite::BoxAlloc(bb) => {
+                box_alloc_local(&body.basic_blocks()[BasicBlock::from_u32(*bb)])
+            },
+            DefSite::HeapAlloc(bb) | DefSite::NativeCall(bb) | DefSite::OtherCall(bb) |
+                DefSite::Uninit(bb) | DefSite::RawSlice(bb) => {
+                let terminator = body.basic_blocks()[BasicBlock::from_u32(*bb)].terminator();
+                call_destination_local(terminator, body)
+            },
+            DefSite::Transmute(bb) => {
+                transmute_local(&body.basic_blocks()[BasicBlock::from_u32(*bb)], body)
+            },
+            DefSite::IntToPtr(bb) => {
+                int_to_ptr_local(&body.basic_blocks()[BasicBlock::from_u32(*bb)])
+            },
+            DefSite::DropInPlace(_) | DefSite::UnionRead(_) => None,
+            DefSite::Global(def_id) => {
+                // The pointer to a global is mutable iff the global is.
+                let mutability = match tcx.static_mutability(assemble_def_id(*def_id)) {
+                    Some(Mutability::Mut) => PtrMutability::Mut,
+                    _ => PtrMutability::Const,
+                };
+                results.push((*def_site, mutability));
+                continue;
+            },
+        };
+        let mutability = local.and_then(|local|
+            get_ptr_mutability(tcx, body.local_decls[local].ty));
+        if let Some(mutability) = mutability {
+            results.push((*def_site, mutability));
+        }
+    }
+    results.sort_by_key(|(def_site, _)| *def_site);
+
+    results
+}
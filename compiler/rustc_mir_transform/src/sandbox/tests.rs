@@ -0,0 +1,1505 @@
+//! Unit tests for the parts of the sandboxing module that do not need a
+//! TyCtxt, e.g., the whole-program analysis on summaries and the handling of
+//! analysis results.
+//!
+//! ```shell
+//! ./x.py test compiler/rustc_mir_transform --test-args sandbox
+//! ```
+
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_hir::def_id::{CrateNum, DefId, DefIndex};
+use rustc_middle::mir::{FakeReadCause, Local, Place, SourceInfo, Statement, StatementKind};
+use rustc_span::DUMMY_SP;
+use serde_json::{json, Value};
+use std::fs;
+use std::path::Path;
+
+use super::archive;
+use super::database::{read_extra_database, ExtraDatabase};
+use super::debug::parse_fn_list;
+use super::error::SandboxError;
+use super::merge;
+use super::stats::Stats;
+use super::utils::{get_place_in_stmt, get_summary_dir, get_wp_summary_path, ignore_crate_name, ignore_fn_name_in, parse_max_summary_bytes};
+use super::summarize_fn::{keep_summary_dir, select_rooted, summary_generation, write_summary_chunks, write_summary_files, Callee, DefSite, FnID, PtrMutability, SerDefId, Summary, SummaryHeader};
+use super::wpa::{build_call_graph, find_all_by_name, find_by_name, format_site_report, format_wp_summary, read_summary_dir, read_summary_dirs, resolve_dyn_fn_calls, serialize_wp_summary, wpa_in_memory, DeallocSite, UnsafeSites, WPSummary, WpaResult};
+
+/// Create a WPSummary from a list of (fn, def sites).
+fn wp_summary(sources: Vec<(FnID, Vec<DefSite>)>) -> WPSummary {
+    let mut wp_summary = WPSummary::default();
+    for (fn_id, def_sites) in sources {
+        wp_summary.insert(fn_id, def_sites.into_iter().collect::<FxHashSet<_>>());
+    }
+    wp_summary
+}
+
+/// Create a SerDefId from a DefIndex and a CrateNum.
+fn def_id(index: u32, krate: u32) -> SerDefId {
+    SerDefId(DefId { index: DefIndex::from_u32(index), krate: CrateNum::from_u32(krate) })
+}
+
+/// Create the JSON of a Callee that is called at the given BB with the def
+/// sites of each argument.
+fn callee(fn_id: u64, calls: Vec<(u32, Vec<Vec<DefSite>>)>) -> Value {
+    let mut arg_defs = serde_json::Map::new();
+    for (bb, args) in calls {
+        arg_defs.insert(bb.to_string(), json!(args));
+    }
+    json!({
+        "fn_id": [fn_id, 0],
+        "fn_name": format!("f{}", fn_id),
+        "crate_name": "krate",
+        "def_id": [fn_id, 0],
+        "arg_defs": arg_defs,
+    })
+}
+
+/// Create a Summary. The fields that are not specified are empty.
+///
+/// Inputs:
+/// @fn_id: The first half of the FnID, also used as the fn name "f{fn_id}".
+/// @callees: JSON of the callees created by callee().
+/// @ret_defs: Call and Arg def sites of the return value.
+/// @unsafe_defs: Def sites of Place used in unsafe code.
+fn summary(fn_id: u64, callees: Vec<Value>, ret_defs: (Vec<DefSite>, Vec<DefSite>),
+           unsafe_defs: Option<Vec<DefSite>>) -> Summary {
+    let summary = json!({
+        "fn_id": [fn_id, 0],
+        "fn_name": format!("f{}", fn_id),
+        "crate_name": "krate",
+        "def_id": [fn_id, 0],
+        "callees": callees,
+        "ret_defs": [ret_defs.0, ret_defs.1],
+        "unsafe_defs": unsafe_defs,
+        "foreign_callees": [],
+        "free_callees": [],
+        "dyn_callees": [],
+        "vtable_fns": [],
+        "self_ref_allocs": [],
+        "drop_defs": [],
+        "dyn_fn_calls": [],
+        "dyn_fn_closures": [],
+        "fn_ptr_fns": [],
+        "has_inline_asm": false,
+        "has_intrinsic_call": false,
+        "source_mutability": [],
+        "alloc_types": [],
+        "is_entry": false,
+        "is_exported": false,
+        "is_unsafe_fn": false,
+    });
+    serde_json::from_value::<Summary>(summary).unwrap()
+}
+
+/// Create a map from FnID to Summary.
+fn summaries(summaries: Vec<Summary>) -> FxHashMap<FnID, Summary> {
+    summaries.into_iter().map(|summary| (summary.fn_id, summary)).collect()
+}
+
+/// Create a Summary with the given foreign and dyn callees from JSON.
+fn summary_with_callees(foreign: &str, dyn_callees: &str) -> Summary {
+    let mut summary = serde_json::to_value(
+        summary(1, Vec::new(), (Vec::new(), Vec::new()), None)).unwrap();
+    summary["foreign_callees"] = serde_json::from_str(foreign).unwrap();
+    summary["dyn_callees"] = serde_json::from_str(dyn_callees).unwrap();
+    serde_json::from_value::<Summary>(summary).unwrap()
+}
+
+#[test]
+fn test_sorted_foreign_dyn_callees() {
+    let summary = summary_with_callees("[[3,0],[1,0],[2,1],[2,0]]", "[[9,9],[5,5],[7,7]]");
+    let serialized = serde_json::to_string(&summary).unwrap();
+    assert!(serialized.contains("\"foreign_callees\":[[1,0],[2,0],[2,1],[3,0]]"));
+    assert!(serialized.contains("\"dyn_callees\":[[5,5],[7,7],[9,9]]"));
+
+    // Round trip.
+    let summary = serde_json::from_str::<Summary>(&serialized).unwrap();
+    assert_eq!(serde_json::to_string(&summary).unwrap(), serialized);
+}
+
+#[test]
+fn test_summary_defaults() {
+    // A summary written before the optional fields were added still reads.
+    let summary = serde_json::from_value::<Summary>(json!({
+        "fn_id": [1, 0],
+        "fn_name": "f1",
+        "crate_name": "krate",
+        "def_id": [1, 0],
+        "callees": [],
+        "ret_defs": [[], []],
+        "unsafe_defs": null,
+        "foreign_callees": [],
+        "dyn_callees": [],
+    })).unwrap();
+    assert!(summary.free_callees.is_empty() && summary.fn_ptr_fns.is_empty());
+    assert!(!summary.is_entry && !summary.is_exported && !summary.is_unsafe_fn);
+}
+
+#[test]
+fn test_get_arg_defs() {
+    let callee: Callee = serde_json::from_value(
+        callee(2, vec![(3, vec![vec![DefSite::HeapAlloc(0)]])])).unwrap();
+    assert_eq!(callee.get_arg_defs(3, 1), Some(&[DefSite::HeapAlloc(0)].into_iter().collect()));
+    // Arg(0) is not an argument, and there is no second argument or call at bb0.
+    assert_eq!(callee.get_arg_defs(3, 0), None);
+    assert_eq!(callee.get_arg_defs(3, 2), None);
+    assert_eq!(callee.get_arg_defs(0, 1), None);
+}
+
+#[test]
+fn test_id_round_trip() {
+    let fn_id = FnID::new(0x0123_4567_89ab_cdef, u64::MAX);
+    let serialized = serde_json::to_string(&fn_id).unwrap();
+    assert_eq!(serialized, format!("[{},{}]", 0x0123_4567_89ab_cdef_u64, u64::MAX));
+    assert_eq!(serde_json::from_str::<FnID>(&serialized).unwrap(), fn_id);
+    assert_eq!(fn_id.to_hex(), "0123456789abcdefffffffffffffffff");
+
+    let global = DefSite::Global(def_id(5, 3));
+    let serialized = serde_json::to_string(&global).unwrap();
+    assert_eq!(serialized, r#"{"Global":[5,3]}"#);
+    assert_eq!(serde_json::from_str::<DefSite>(&serialized).unwrap(), global);
+
+    // Out of the range of DefIndex.
+    assert!(serde_json::from_str::<SerDefId>("[4294967295,0]").is_err());
+}
+
+#[test]
+fn test_find_by_name() {
+    // f1 calls f2 and f3. f2 and f3 are both renamed to "new", like methods
+    // of two different types.
+    let rename = |summary: Summary| {
+        let mut summary = serde_json::to_value(summary).unwrap();
+        summary["fn_name"] = json!("new");
+        serde_json::from_value::<Summary>(summary).unwrap()
+    };
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(0, vec![])]), callee(3, vec![(1, vec![])])],
+                (vec![], vec![]), None),
+        rename(summary(3, vec![], (vec![], vec![]), None)),
+        rename(summary(2, vec![], (vec![], vec![]), None)),
+    ]);
+
+    let f1 = find_by_name(&summaries, "krate", "f1").unwrap();
+    assert_eq!(f1.callees.len(), 2);
+    assert!(find_by_name(&summaries, "other_krate", "f1").is_none());
+
+    // "new" is ambiguous.
+    assert!(find_by_name(&summaries, "krate", "new").is_none());
+    let fn_ids = find_all_by_name(&summaries, "krate", "new").iter()
+        .map(|summary| summary.fn_id).collect::<Vec<_>>();
+    assert_eq!(fn_ids, vec![FnID::new(2, 0), FnID::new(3, 0)]);
+}
+
+#[test]
+fn test_unsafe_sites() {
+    let wp_summary = wp_summary(vec![
+        (FnID::new(2, 0), vec![DefSite::Arg(1), DefSite::HeapAlloc(3), DefSite::OtherCall(4)]),
+        (FnID::new(1, 0), vec![DefSite::HeapAlloc(0), DefSite::Uninit(1), DefSite::Arg(2)]),
+        (FnID::new(3, 0), vec![DefSite::NativeCall(5), DefSite::Global(def_id(7, 0))]),
+    ]);
+    assert_eq!(wp_summary.count_by_kind(), (2, 1, 1, 2));
+
+    let sites = wp_summary.iter_unsafe_sites().collect::<Vec<_>>();
+    assert_eq!(sites.len(), 8);
+    assert_eq!(sites[..3], [
+        (FnID::new(1, 0), DefSite::HeapAlloc(0)),
+        (FnID::new(1, 0), DefSite::Arg(2)),
+        (FnID::new(1, 0), DefSite::Uninit(1)),
+    ]);
+}
+
+#[test]
+fn test_merge_targets() {
+    // The same access is in different BBs on the two targets, e.g., because of
+    // a cfg-gated statement before it, but has the same stable key.
+    let linux = merge::parse_access_sites(concat!(
+        "foo: src/lib.rs:3:14: 3:16 #00ab:0000000000000001\n",
+        "foo: src/lib.rs:9:14: 9:16 (write) #00ab:0000000000000002\n",
+    )).unwrap();
+    let windows = merge::parse_access_sites(
+        "foo: src/lib.rs:4:14: 4:16 #00ab:0000000000000001\n").unwrap();
+
+    let merged = merge::merge_targets(vec![
+        ("x86_64-unknown-linux-gnu".to_owned(), linux),
+        ("x86_64-pc-windows-msvc".to_owned(), windows),
+    ]);
+    assert_eq!(merged.targets.len(), 2);
+    assert_eq!(merged.findings.len(), 2);
+
+    let specific = merged.target_specific();
+    assert_eq!(specific.len(), 1);
+    let (finding, targets) = specific[0];
+    assert_eq!(finding, "00ab:0000000000000002");
+    assert!(targets.len() == 1 && targets.contains("x86_64-unknown-linux-gnu"));
+    assert!(merged.format().ends_with(
+        "foo: src/lib.rs:9:14: 9:16 (write) #00ab:0000000000000002: only on x86_64-unknown-linux-gnu\n"));
+}
+
+#[test]
+fn test_merge_targets_no_key() {
+    // Accesses written without SANDBOX_STABLE_KEYS cannot be merged.
+    let err = merge::parse_access_sites("foo: src/lib.rs:3:14: 3:16\n").unwrap_err();
+    assert!(matches!(err, SandboxError::MissingStableKey { .. }));
+}
+
+#[test]
+fn test_merge_targets_sorted() {
+    // The same findings merged in a different order are reported in the same
+    // order, sorted by their keys.
+    let findings = (0..20).map(|i| (format!("{:04x}:{:016x}", i % 4, i), format!("site {}", i)))
+        .collect::<Vec<_>>();
+    let merge = |findings: Vec<(String, String)>| {
+        merge::merge_targets(vec![
+            ("x86_64-unknown-linux-gnu".to_owned(), findings),
+            ("x86_64-pc-windows-msvc".to_owned(), Vec::new()),
+        ])
+    };
+    let merged = merge(findings.clone());
+    let reversed = merge(findings.iter().rev().cloned().collect());
+
+    assert_eq!(merged.format(), reversed.format());
+    let specific = merged.target_specific().into_iter().map(|(finding, _)| finding.clone())
+        .collect::<Vec<_>>();
+    let mut sorted = findings.into_iter().map(|(finding, _)| finding).collect::<Vec<_>>();
+    sorted.sort();
+    assert_eq!(specific, sorted);
+}
+
+#[test]
+fn test_merge_wp_summaries() {
+    let dir = std::env::temp_dir().join(format!("sandbox-merge-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+
+    // foo is in both results with different sources; bar and baz are each in
+    // one of them. foo is only reachable in app1, and baz in app2.
+    let (foo, bar, baz) = (FnID::new(1, 0), FnID::new(2, 0), FnID::new(3, 0));
+    let app1 = wp_summary(vec![
+        (foo, vec![DefSite::HeapAlloc(1)]),
+        (bar, vec![DefSite::Arg(1)]),
+    ]);
+    let app2 = wp_summary(vec![
+        (foo, vec![DefSite::HeapAlloc(1), DefSite::Arg(2)]),
+        (baz, vec![DefSite::NativeCall(3)]),
+    ]);
+    let paths = [dir.join("app1"), dir.join("app2")];
+    let reachable1 = [foo].into_iter().collect::<FxHashSet<_>>();
+    let reachable2 = [baz].into_iter().collect::<FxHashSet<_>>();
+    fs::write(&paths[0], serialize_wp_summary(&app1, &reachable1).unwrap()).unwrap();
+    fs::write(&paths[1], serialize_wp_summary(&app2, &reachable2).unwrap()).unwrap();
+
+    let (merged, reachable) = merge::merge_wp_summaries(&paths).unwrap();
+    assert_eq!(merged, wp_summary(vec![
+        (foo, vec![DefSite::HeapAlloc(1), DefSite::Arg(2)]),
+        (bar, vec![DefSite::Arg(1)]),
+        (baz, vec![DefSite::NativeCall(3)]),
+    ]));
+    assert_eq!(reachable, [foo, baz].into_iter().collect::<FxHashSet<_>>());
+
+    let _ = fs::remove_dir_all(&dir);
+}
+
+#[test]
+fn test_blast_radius() {
+    // f1 calls f2 at bb1 and f5 at bb2; f2 calls f3 at bb3 and returns its
+    // return value; f4 calls f3 at bb4 but does not return it.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(1, vec![])]), callee(5, vec![(2, vec![])])],
+                (vec![], vec![]), None),
+        summary(2, vec![callee(3, vec![(3, vec![])])],
+                (vec![DefSite::OtherCall(3)], vec![]), None),
+        summary(3, vec![], (vec![], vec![]), None),
+        summary(4, vec![callee(3, vec![(4, vec![])])], (vec![], vec![]), None),
+        summary(5, vec![], (vec![], vec![]), None),
+    ]);
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert!(wpa_result.wp_summary.is_empty());
+
+    let radius = wpa_result.blast_radius(&FnID::new(3, 0));
+    let expected = [FnID::new(1, 0), FnID::new(2, 0), FnID::new(4, 0)];
+    assert_eq!(radius, expected.into_iter().collect::<FxHashSet<_>>());
+
+    assert!(wpa_result.blast_radius(&FnID::new(1, 0)).is_empty());
+}
+
+#[test]
+fn test_unsafe_fn_calls() {
+    // Safe f1 calls unsafe f2 at bb1 and passes its return value to f3 at bb2.
+    let mut f2 = serde_json::to_value(
+        summary(2, vec![], (vec![], vec![DefSite::Arg(1)]), Some(vec![DefSite::Arg(1)]))).unwrap();
+    f2["is_unsafe_fn"] = json!(true);
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(1, vec![vec![]])]),
+                        callee(3, vec![(2, vec![vec![DefSite::OtherCall(1)]])])],
+                (vec![], vec![]), None),
+        serde_json::from_value::<Summary>(f2).unwrap(),
+        summary(3, vec![], (vec![], vec![]), None),
+    ]);
+
+    // By default, calling an unsafe fn is not an unsafe source by itself.
+    let wpa_result = WpaResult::with_unsafe_fn_calls(&summaries, false).unwrap();
+    assert!(wpa_result.wp_summary.is_empty());
+
+    let wpa_result = WpaResult::with_unsafe_fn_calls(&summaries, true).unwrap();
+    let mut expected = WPSummary::default();
+    expected.insert(FnID::new(1, 0), [DefSite::OtherCall(1)].into_iter().collect());
+    expected.insert(FnID::new(3, 0), [DefSite::Arg(1)].into_iter().collect());
+    assert_eq!(wpa_result.wp_summary, expected);
+}
+
+#[test]
+fn test_summary_chunks() {
+    let dir = std::env::temp_dir().join(format!("sandbox-chunks-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+
+    let header = SummaryHeader { target: "x86_64-unknown-linux-gnu".to_string(), cfg: Vec::new(), generation: 0 };
+    let all = (1..=10)
+        .map(|i| {
+            summary(
+                i,
+                vec![callee(i + 10, vec![(0, vec![vec![DefSite::HeapAlloc(0)]])])],
+                (vec![DefSite::OtherCall(0)], Vec::new()),
+                Some(vec![DefSite::Arg(1)]),
+            )
+        })
+        .collect::<Vec<_>>();
+
+    // Each chunk holds about three summaries.
+    let max_bytes = serde_json::to_vec(&all[0]).unwrap().len() * 3;
+    let output_file = dir.join("krate-1");
+    let chunk_num =
+        write_summary_chunks(&header, &all, output_file.to_str().unwrap(), Some(max_bytes))
+            .unwrap();
+    assert!(chunk_num > 1);
+    assert_eq!(fs::read_dir(&dir).unwrap().count(), chunk_num);
+
+    let read_back = read_summary_dir(dir.to_str().unwrap()).unwrap();
+    assert_eq!(read_back.len(), all.len());
+    for summary in &all {
+        assert_eq!(
+            serde_json::to_value(summary).unwrap(),
+            serde_json::to_value(&read_back[&summary.fn_id]).unwrap()
+        );
+    }
+
+    // Compiling the crate again with fewer functions removes all the chunks
+    // of the earlier compile, but not the files of other crates.
+    let dir_str = dir.to_str().unwrap();
+    write_summary_files(dir_str, "krate-10", &header, &all[1..2], None).unwrap();
+    write_summary_files(dir_str, "krate-1", &header, &all[..1], None).unwrap();
+    let read_back = read_summary_dir(dir_str).unwrap();
+    assert_eq!(read_back.len(), 2);
+    assert!(dir.join("krate-1").exists() && dir.join("krate-10").exists());
+    assert!(!dir.join("krate-1.0").exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_parse_max_summary_bytes() {
+    assert_eq!(parse_max_summary_bytes(None).unwrap(), None);
+    assert_eq!(parse_max_summary_bytes(Some("4096".to_string())).unwrap(), Some(4096));
+    match parse_max_summary_bytes(Some("4k".to_string())) {
+        Err(SandboxError::InvalidEnv { name, value }) => {
+            assert_eq!(name, "SANDBOX_MAX_SUMMARY_BYTES");
+            assert_eq!(value, "4k");
+        },
+        result => panic!("Expected InvalidEnv, got {:?}", result),
+    }
+}
+
+#[test]
+fn test_deterministic_serialization() {
+    let dir = std::env::temp_dir().join(format!("sandbox-deterministic-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+
+    // The same summaries collected in two different orders.
+    let header = SummaryHeader { target: "x86_64-unknown-linux-gnu".to_string(), cfg: Vec::new(), generation: 0 };
+    let all = (1..=10).map(|i| summary(i, vec![], (vec![], vec![]), None)).collect::<Vec<_>>();
+    let reversed = (1..=10).rev().map(|i| summary(i, vec![], (vec![], vec![]), None))
+        .collect::<Vec<_>>();
+    let (file1, file2) = (dir.join("krate-1"), dir.join("krate-2"));
+    write_summary_chunks(&header, &all, file1.to_str().unwrap(), None).unwrap();
+    write_summary_chunks(&header, &reversed, file2.to_str().unwrap(), None).unwrap();
+    let (bytes1, bytes2) = (fs::read(&file1).unwrap(), fs::read(&file2).unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(bytes1, bytes2);
+
+    // The sets and maps of def sites in a summary built in two different
+    // orders, into sets of different capacities.
+    let def_sites = [DefSite::Arg(10), DefSite::OtherCall(2), DefSite::Arg(1),
+                     DefSite::HeapAlloc(3), DefSite::Arg(2)];
+    let build = |capacity: usize, rev: bool| {
+        let set = || {
+            let mut set = FxHashSet::<DefSite>::with_capacity_and_hasher(capacity, Default::default());
+            if rev {
+                set.extend(def_sites.iter().rev());
+            } else {
+                set.extend(def_sites.iter());
+            }
+            set
+        };
+        let mut built = summary(1, vec![callee(2, vec![])], (vec![], vec![]), None);
+        built.ret_defs.0 = set();
+        built.unsafe_defs = Some(set());
+        built.drop_defs = vec![(4, set())];
+        let mut arg_defs = FxHashMap::with_capacity_and_hasher(capacity, Default::default());
+        if rev {
+            arg_defs.extend((0..8).rev().map(|bb| (bb, vec![set(), set()])));
+        } else {
+            arg_defs.extend((0..8).map(|bb| (bb, vec![set(), set()])));
+        }
+        built.callees[0].arg_defs = arg_defs;
+        serde_json::to_string(&built).unwrap()
+    };
+    let serialized = build(0, false);
+    assert_eq!(serialized, build(1024, true));
+    let sorted = r#"[{"HeapAlloc":3},{"OtherCall":2},{"Arg":1},{"Arg":2},{"Arg":10}]"#;
+    assert!(serialized.contains(&format!(r#""unsafe_defs":{}"#, sorted)));
+    assert!(serialized.contains(&format!(r#""drop_defs":[[4,{}]]"#, sorted)));
+    assert!(serialized.contains(&format!(r#""arg_defs":{{"0":[{},{}],"1":"#, sorted, sorted)));
+
+    // The same whole-program summary built in two different orders.
+    let sources = vec![
+        (FnID::new(2, 0), vec![DefSite::Arg(1), DefSite::HeapAlloc(3), DefSite::Global(def_id(1, 0))]),
+        (FnID::new(1, 0), vec![DefSite::OtherCall(2), DefSite::HeapAlloc(5)]),
+    ];
+    let reachable = [FnID::new(1, 0)].into_iter().collect::<FxHashSet<_>>();
+    let serialized = serialize_wp_summary(&wp_summary(sources.clone()), &reachable).unwrap();
+    let reversed = sources.into_iter().rev()
+        .map(|(fn_id, def_sites)| (fn_id, def_sites.into_iter().rev().collect()))
+        .collect::<Vec<_>>();
+    assert_eq!(serialized, serialize_wp_summary(&wp_summary(reversed), &reachable).unwrap());
+    assert_eq!(serialized, concat!(r#"[[[1,0],[{"HeapAlloc":5},{"OtherCall":2}],true],"#,
+        r#"[[2,0],[{"HeapAlloc":3},{"Arg":1},{"Global":[1,0]}],false]]"#));
+}
+
+#[test]
+fn test_read_summary_dirs() {
+    let base = std::env::temp_dir().join(format!("sandbox-dirs-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&base);
+    let header = SummaryHeader { target: "x86_64-unknown-linux-gnu".to_string(), cfg: Vec::new(), generation: 0 };
+
+    // A precomputed dependency (f1, f2) and the current build (f2, f3). The
+    // current build has a newer summary of f2, which returns an allocation.
+    let saved = vec![
+        summary(1, Vec::new(), (Vec::new(), Vec::new()), None),
+        summary(2, Vec::new(), (Vec::new(), Vec::new()), None),
+    ];
+    let current = vec![
+        summary(2, Vec::new(), (vec![DefSite::HeapAlloc(0)], Vec::new()), None),
+        summary(3, Vec::new(), (Vec::new(), Vec::new()), None),
+    ];
+    let mut dirs = Vec::new();
+    for (name, summaries) in [("saved", &saved), ("current", &current)] {
+        let dir = base.join(name);
+        fs::create_dir_all(&dir).unwrap();
+        write_summary_chunks(&header, summaries, dir.join("krate-1").to_str().unwrap(), None)
+            .unwrap();
+        dirs.push(dir.to_str().unwrap().to_owned());
+    }
+
+    let read_back = read_summary_dirs(&dirs).unwrap();
+    assert_eq!(read_back.len(), 3);
+    assert!(read_back[&FnID::new(2, 0)].ret_defs_contains(&DefSite::HeapAlloc(0)));
+
+    fs::remove_dir_all(&base).unwrap();
+}
+
+#[test]
+fn test_kept_summaries() {
+    let dir = std::env::temp_dir().join(format!("sandbox-keep-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    let header = SummaryHeader { target: "x86_64-unknown-linux-gnu".to_string(), cfg: Vec::new(), generation: 0 };
+    let dir_str = dir.to_str().unwrap();
+    let write = |fn_id: u64, file: &str| {
+        let summaries = vec![summary(fn_id, Vec::new(), (Vec::new(), Vec::new()), None)];
+        write_summary_files(dir_str, file, &header, &summaries, None).unwrap();
+    };
+
+    // krate-1 is from the build that kept the directory, and krate-2 is from
+    // a later build.
+    write(1, "krate-1");
+    assert_eq!(summary_generation(dir_str), 0);
+    keep_summary_dir(dir_str).unwrap();
+    assert_eq!(summary_generation(dir_str), 1);
+    write(2, "krate-2");
+
+    let read_back = read_summary_dir(dir_str).unwrap();
+    assert_eq!(read_back.keys().copied().collect::<Vec<_>>(), vec![FnID::new(2, 0)]);
+
+    // The later build keeps the directory again, so krate-2 is stale too.
+    keep_summary_dir(dir_str).unwrap();
+    write(3, "krate-3");
+
+    let read_back = read_summary_dir(dir_str).unwrap();
+    assert_eq!(read_back.keys().copied().collect::<Vec<_>>(), vec![FnID::new(3, 0)]);
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn test_call_graph_dot() {
+    // f1 calls f2, which returns a pointer allocated at bb0 and read by f1 in
+    // unsafe code, and calls f3 that has no unsafe sources. f1 is named with
+    // a quote.
+    let mut f1 = serde_json::to_value(summary(
+        1, vec![callee(2, vec![(0, vec![])]), callee(3, vec![(1, vec![])])], (vec![], vec![]),
+        Some(vec![DefSite::OtherCall(0)]))).unwrap();
+    f1["fn_name"] = json!("f\"1");
+    let summaries = summaries(vec![
+        serde_json::from_value::<Summary>(f1).unwrap(),
+        summary(2, vec![], (vec![DefSite::HeapAlloc(0)], vec![]), None),
+        summary(3, vec![], (vec![], vec![]), None),
+    ]);
+
+    let dot = WpaResult::new(&summaries).unwrap().call_graph_dot();
+    let (f1, f2, f3) = (FnID::new(1, 0).to_hex(), FnID::new(2, 0).to_hex(), FnID::new(3, 0).to_hex());
+    assert!(dot.starts_with("digraph call_graph {\n") && dot.ends_with("}\n"));
+    assert!(dot.contains(&format!(
+        "    \"{}\" [label=\"krate::f\\\"1\", style=filled, fillcolor=salmon];\n", f1)));
+    assert!(dot.contains(&format!(
+        "    \"{}\" [label=\"krate::f2\", style=filled, fillcolor=salmon];\n", f2)));
+    assert!(dot.contains(&format!("    \"{}\" [label=\"krate::f3\"];\n", f3)));
+    assert!(dot.contains(&format!("    \"{}\" -> \"{}\";\n", f1, f2)));
+    assert!(dot.contains(&format!("    \"{}\" -> \"{}\";\n", f1, f3)));
+}
+
+#[test]
+fn test_format_wp_summary() {
+    // f1 allocates at bb0 by calling exchange_malloc, and passes the pointer
+    // to f2 at bb1.
+    let mut malloc = callee(9, vec![(0, vec![])]);
+    malloc["fn_name"] = json!("exchange_malloc");
+    let summaries = summaries(vec![
+        summary(1, vec![malloc, callee(2, vec![(1, vec![vec![DefSite::HeapAlloc(0)]])])],
+                (vec![], vec![]), Some(vec![DefSite::HeapAlloc(0)])),
+        summary(2, vec![], (vec![], vec![]), None),
+    ]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(format_wp_summary(&wpa_result.wp_summary, &summaries, &wpa_result.reachable),
+        "krate::f1\n    HeapAlloc at BB0: krate::exchange_malloc\nkrate::f2\n    Arg 1\n");
+}
+
+#[test]
+fn test_self_referential_allocs() {
+    // f1 has an unsafe self-referential allocation at bb0 and a safe one at
+    // bb2; f2's self-referential allocation is not unsafe. f1 also passes the
+    // allocation to the foreign function 9 at bb1, which has an unsafe Arg
+    // but no Summary.
+    let mut f1 = serde_json::to_value(summary(1,
+        vec![callee(9, vec![(1, vec![vec![DefSite::HeapAlloc(0)]])])],
+        (vec![], vec![]), Some(vec![DefSite::HeapAlloc(0)]))).unwrap();
+    f1["self_ref_allocs"] = json!([0, 2]);
+    f1["foreign_callees"] = json!([[9, 0]]);
+    let mut f2 = serde_json::to_value(summary(2, vec![], (vec![], vec![]), None)).unwrap();
+    f2["self_ref_allocs"] = json!([1]);
+    let summaries = summaries(vec![
+        serde_json::from_value::<Summary>(f1).unwrap(),
+        serde_json::from_value::<Summary>(f2).unwrap(),
+    ]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.self_referential_allocs(), vec![(FnID::new(1, 0), DefSite::HeapAlloc(0))]);
+}
+
+#[test]
+fn test_longest_chain() {
+    // An allocation in f1 is passed to f2, then to f3, and then to f4.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(1, vec![vec![DefSite::HeapAlloc(0)]])])],
+                (vec![], vec![]), Some(vec![DefSite::HeapAlloc(0)])),
+        summary(2, vec![callee(3, vec![(0, vec![vec![DefSite::Arg(1)]])])],
+                (vec![], vec![]), None),
+        summary(3, vec![callee(4, vec![(0, vec![vec![DefSite::Arg(1)]])])],
+                (vec![], vec![]), None),
+        summary(4, vec![], (vec![], vec![]), None),
+    ]);
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+
+    assert_eq!(wpa_result.max_propagation_depth(), 3);
+    assert_eq!(wpa_result.longest_chain(), vec!["krate::f1", "krate::f2", "krate::f3", "krate::f4"]);
+}
+
+#[test]
+fn test_archive_round_trip() {
+    // f1 allocates memory in unsafe code twice and passes one of them to f2.
+    // The sources of f1 are sorted by BB, not by their Debug strings.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(1, vec![vec![DefSite::HeapAlloc(10)]])])],
+                (vec![], vec![]), Some(vec![DefSite::HeapAlloc(10), DefSite::HeapAlloc(3)])),
+        summary(2, vec![], (vec![], vec![]), None),
+    ]);
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+
+    let path = std::env::temp_dir().join(format!("sandbox-archive-{}.json.gz", std::process::id()));
+    archive::write_archive(&path, &summaries, &wpa_result).unwrap();
+    let bundle = archive::load_archive(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(bundle.schema_version, archive::ARCHIVE_SCHEMA_VERSION);
+    let crate_summaries = &bundle.crates["krate"];
+    assert_eq!(crate_summaries.len(), 2);
+    for summary in crate_summaries {
+        assert_eq!(
+            serde_json::to_value(summary).unwrap(),
+            serde_json::to_value(&summaries[&summary.fn_id]).unwrap()
+        );
+    }
+    assert_eq!(bundle.call_graph, wpa_result.call_graph_edges());
+    assert_eq!(bundle.unsafe_sources, vec![
+        (FnID::new(1, 0), vec![DefSite::HeapAlloc(3), DefSite::HeapAlloc(10)]),
+        (FnID::new(2, 0), vec![DefSite::Arg(1)]),
+    ]);
+    assert_eq!(bundle.longest_chain, wpa_result.longest_chain());
+}
+
+#[test]
+fn test_low_level_fn_counts() {
+    let mut f1 = serde_json::to_value(summary(1, vec![], (vec![], vec![]), None)).unwrap();
+    f1["has_inline_asm"] = json!(true);
+    f1["has_intrinsic_call"] = json!(true);
+    let mut f2 = serde_json::to_value(summary(2, vec![], (vec![], vec![]), None)).unwrap();
+    f2["has_intrinsic_call"] = json!(true);
+    let summaries = summaries(vec![
+        serde_json::from_value::<Summary>(f1).unwrap(),
+        serde_json::from_value::<Summary>(f2).unwrap(),
+        summary(3, vec![], (vec![], vec![]), None),
+    ]);
+
+    assert_eq!(WpaResult::new(&summaries).unwrap().low_level_fn_counts(), (1, 2));
+}
+
+#[test]
+fn test_resolve_dyn_fn_calls() {
+    // f1 allocates a pointer, captures it in a closure (f3) coerced to a
+    // Box<dyn Fn()>, and calls the boxed closure via Fn::call (f9) at bb2.
+    // The closure dereferences the captured pointer.
+    let mut f1 = serde_json::to_value(summary(
+        1, vec![callee(9, vec![(2, vec![vec![DefSite::HeapAlloc(0)], vec![]])])],
+        (vec![], vec![]), None)).unwrap();
+    f1["dyn_fn_calls"] = json!([2]);
+    f1["dyn_fn_closures"] = json!([[callee(3, vec![]), 1]]);
+    let mut summaries = summaries(vec![
+        serde_json::from_value::<Summary>(f1).unwrap(),
+        summary(3, vec![], (vec![], vec![]), Some(vec![DefSite::Arg(1)])),
+    ]);
+
+    // Without resolving the dyn Fn call, the closure has no caller.
+    assert!(WpaResult::new(&summaries).unwrap().wp_summary.is_empty());
+
+    resolve_dyn_fn_calls(&mut summaries);
+    let f1 = &summaries[&FnID::new(1, 0)];
+    assert!(f1.is_dyn_callee(&FnID::new(3, 0)));
+    assert_eq!(f1.get_callee_bb(2).len(), 2);
+    assert_eq!(WpaResult::new(&summaries).unwrap().wp_summary,
+               wp_summary(vec![(FnID::new(1, 0), vec![DefSite::HeapAlloc(0)])]));
+}
+
+#[test]
+fn test_unpaired_allocs() {
+    // f1 leaks the Box::into_raw pointer allocated at bb0, passes the one
+    // allocated at bb1 to f2 that frees it by Box::from_raw, and drops the
+    // Box allocated at bb2. All three are used by unsafe code.
+    let mut from_raw = callee(9, vec![(0, vec![vec![DefSite::Arg(1)]])]);
+    from_raw["fn_name"] = json!("from_raw");
+    let mut f1 = serde_json::to_value(summary(
+        1, vec![callee(2, vec![(3, vec![vec![DefSite::HeapAlloc(1)]])])], (vec![], vec![]),
+        Some(vec![DefSite::HeapAlloc(0), DefSite::HeapAlloc(1), DefSite::HeapAlloc(2)]))).unwrap();
+    f1["drop_defs"] = json!([[4, [DefSite::HeapAlloc(2)]]]);
+    let mut f2 = serde_json::to_value(summary(2, vec![from_raw], (vec![], vec![]), None)).unwrap();
+    f2["free_callees"] = json!([[9, 0]]);
+    let summaries = summaries(vec![
+        serde_json::from_value::<Summary>(f1).unwrap(),
+        serde_json::from_value::<Summary>(f2).unwrap(),
+    ]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.unpaired_allocs().unwrap(), vec![(FnID::new(1, 0), DefSite::HeapAlloc(0))]);
+}
+
+#[test]
+fn test_dealloc_pairs() {
+    // f1 allocates at bb0 and frees the memory by dealloc at bb1 after using
+    // it in unsafe code. The memory allocated at bb2 is freed at bb3 but not
+    // used in unsafe code. The memory allocated at bb0 is also passed to a
+    // "free" at bb5 that is not a deallocation function, e.g., a method of a
+    // pool, which is not a deallocation site.
+    let mut dealloc = callee(9, vec![(1, vec![vec![DefSite::HeapAlloc(0)], vec![]]),
+                                     (3, vec![vec![DefSite::HeapAlloc(2)], vec![]])]);
+    dealloc["fn_name"] = json!("dealloc");
+    let mut free = callee(8, vec![(5, vec![vec![DefSite::HeapAlloc(0)]])]);
+    free["fn_name"] = json!("free");
+    let mut f1 = serde_json::to_value(summary(
+        1, vec![dealloc, free], (vec![], vec![]), Some(vec![DefSite::HeapAlloc(0)]))).unwrap();
+    f1["free_callees"] = json!([[9, 0]]);
+    let summaries = summaries(vec![serde_json::from_value::<Summary>(f1).unwrap()]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    let freed_at = DeallocSite { fn_id: FnID::new(1, 0), bb: 1 };
+    assert_eq!(wpa_result.unsafe_deallocs, [freed_at].into_iter().collect());
+    assert_eq!(wpa_result.dealloc_pairs().unwrap(),
+               vec![(freed_at, vec![(FnID::new(1, 0), DefSite::HeapAlloc(0))])]);
+    assert!(format_site_report(&wpa_result, &summaries).unwrap()
+            .contains("Unsafe deallocation: krate::f1 BB1 frees 1 allocation(s)\n"));
+}
+
+#[test]
+fn test_dealloc_pairs_recursive() {
+    // f1 uses the memory allocated at bb0 and bb1 in unsafe code. It passes
+    // the former to f2 at bb2 and drops the latter at bb3. f2 drops its
+    // argument at bb1 and passes it to itself at bb2.
+    let mut f1 = serde_json::to_value(summary(
+        1, vec![callee(2, vec![(2, vec![vec![DefSite::HeapAlloc(0)]])])], (vec![], vec![]),
+        Some(vec![DefSite::HeapAlloc(0), DefSite::HeapAlloc(1)]))).unwrap();
+    f1["drop_defs"] = json!([[3, [DefSite::HeapAlloc(1)]]]);
+    let mut f2 = serde_json::to_value(summary(
+        2, vec![callee(2, vec![(2, vec![vec![DefSite::Arg(1)]])])], (vec![], vec![]),
+        None)).unwrap();
+    f2["drop_defs"] = json!([[1, [DefSite::Arg(1)]]]);
+    let summaries = summaries(vec![
+        serde_json::from_value::<Summary>(f1).unwrap(),
+        serde_json::from_value::<Summary>(f2).unwrap(),
+    ]);
+
+    assert_eq!(WpaResult::new(&summaries).unwrap().dealloc_pairs().unwrap(), vec![
+        (DeallocSite { fn_id: FnID::new(1, 0), bb: 3 },
+         vec![(FnID::new(1, 0), DefSite::HeapAlloc(1))]),
+        (DeallocSite { fn_id: FnID::new(2, 0), bb: 1 },
+         vec![(FnID::new(1, 0), DefSite::HeapAlloc(0))]),
+    ]);
+}
+
+#[test]
+fn test_wpa_in_memory() {
+    // f1 of the main crate calls f2 of a dependency at bb1 and reads the
+    // result in unsafe code. f2 returns the memory allocated at bb0.
+    let main = vec![summary(1, vec![callee(2, vec![(1, vec![])])], (vec![], vec![]),
+                            Some(vec![DefSite::OtherCall(1)]))];
+    let deps = vec![summary(2, vec![], (vec![DefSite::HeapAlloc(0)], vec![]), None)];
+
+    assert_eq!(wpa_in_memory(main, deps).unwrap(), wp_summary(vec![
+        (FnID::new(1, 0), vec![DefSite::OtherCall(1)]),
+        (FnID::new(2, 0), vec![DefSite::HeapAlloc(0)]),
+    ]));
+}
+
+#[test]
+fn test_non_alloc_sources() {
+    // For each kind of unsafe source other than a heap allocation, f2 makes
+    // it at bb0 and returns it. f1 passes a heap allocation made at bb0 to f2
+    // at bb1, reads the result in unsafe code, and passes the result to f3 at
+    // bb2. A source that carries the taint of its operand, e.g., a transmute
+    // of the argument, makes the allocation unsafe as well. How each kind is
+    // found in MIR is checked by the fixtures in run-make/sandbox-summaries.
+    let global = DefSite::Global(def_id(7, 0));
+    // Each source, whether it carries the taint of its operand, and how the
+    // report lists it.
+    let sources = [
+        (DefSite::Uninit(0), false, "Uninit at BB0".to_owned()),
+        (DefSite::RawSlice(0), true, "RawSlice at BB0".to_owned()),
+        (DefSite::IntToPtr(0), true, "IntToPtr at BB0".to_owned()),
+        (global, false, format!("{:?}", global)),
+        (DefSite::UnionRead(0), false, "UnionRead at BB0".to_owned()),
+        (DefSite::Transmute(0), true, "Transmute at BB0".to_owned()),
+    ];
+
+    for (source, carries_arg, source_line) in sources {
+        let arg_defs = if carries_arg { vec![DefSite::Arg(1)] } else { vec![] };
+        let summaries = summaries(vec![
+            summary(1, vec![callee(2, vec![(1, vec![vec![DefSite::HeapAlloc(0)]])]),
+                            callee(3, vec![(2, vec![vec![DefSite::OtherCall(1)]])])],
+                    (vec![], vec![]), Some(vec![DefSite::OtherCall(1)])),
+            summary(2, vec![], (vec![source], arg_defs), None),
+            summary(3, vec![], (vec![], vec![]), None),
+        ]);
+
+        let (mut f1_sources, mut f2_sources) = (vec![DefSite::OtherCall(1)], vec![source]);
+        let mut unpaired = vec![];
+        if carries_arg {
+            f1_sources.push(DefSite::HeapAlloc(0));
+            f2_sources.push(DefSite::Arg(1));
+            unpaired.push((FnID::new(1, 0), DefSite::HeapAlloc(0)));
+        }
+        let wpa_result = WpaResult::new(&summaries).unwrap();
+        assert_eq!(wpa_result.wp_summary, wp_summary(vec![
+            (FnID::new(1, 0), f1_sources),
+            (FnID::new(2, 0), f2_sources),
+            (FnID::new(3, 0), vec![DefSite::Arg(1)]),
+        ]), "{:?}", source);
+        // None of them is ever freed, so only the allocation is unpaired.
+        assert_eq!(wpa_result.unpaired_allocs().unwrap(), unpaired, "{:?}", source);
+
+        let report = format_wp_summary(&wpa_result.wp_summary, &summaries, &wpa_result.reachable);
+        assert!(report.contains(&format!("krate::f2\n{}    {}\n",
+            if carries_arg { "    Arg 1\n" } else { "" }, source_line)), "{}", report);
+    }
+}
+
+#[test]
+fn test_uninit_source() {
+    // f1 returns the result of "mem::zeroed::<&u8>()" called at bb0. f2 calls
+    // f1 at bb1, reads the result in unsafe code, and passes it to f3 at bb2.
+    let summaries = summaries(vec![
+        summary(1, vec![], (vec![DefSite::Uninit(0)], vec![]), None),
+        summary(2, vec![callee(1, vec![(1, vec![])]),
+                        callee(3, vec![(2, vec![vec![DefSite::OtherCall(1)]])])],
+                (vec![], vec![]), Some(vec![DefSite::OtherCall(1)])),
+        summary(3, vec![], (vec![], vec![]), None),
+    ]);
+
+    assert_eq!(WpaResult::new(&summaries).unwrap().wp_summary, wp_summary(vec![
+        (FnID::new(1, 0), vec![DefSite::Uninit(0)]),
+        (FnID::new(2, 0), vec![DefSite::OtherCall(1)]),
+        (FnID::new(3, 0), vec![DefSite::Arg(1)]),
+    ]));
+}
+
+#[test]
+fn test_int_to_ptr_source() {
+    // f1 returns "addr as *mut u8" cast at bb0, which carries the taint of
+    // addr. f2 calls f1 at bb1 and writes through the pointer in unsafe code.
+    let summaries = summaries(vec![
+        summary(1, vec![], (vec![DefSite::IntToPtr(0)], vec![DefSite::Arg(1)]), None),
+        summary(2, vec![callee(1, vec![(1, vec![vec![]])])], (vec![], vec![]),
+                Some(vec![DefSite::OtherCall(1)])),
+    ]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.wp_summary, wp_summary(vec![
+        (FnID::new(1, 0), vec![DefSite::IntToPtr(0)]),
+        (FnID::new(2, 0), vec![DefSite::OtherCall(1)]),
+    ]));
+    assert_eq!(format_wp_summary(&wpa_result.wp_summary, &summaries, &wpa_result.reachable),
+        "krate::f1\n    IntToPtr at BB0\nkrate::f2\n    OtherCall at BB1: krate::f1\n");
+}
+
+#[test]
+fn test_global_source() {
+    // f2 returns "&raw mut X" of a "static mut X". f1 calls f2 at bb0 and
+    // writes to X through the result in unsafe code.
+    let global = DefSite::Global(def_id(7, 0));
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(0, vec![])])], (vec![], vec![]),
+                Some(vec![DefSite::OtherCall(0)])),
+        summary(2, vec![], (vec![global], vec![]), None),
+    ]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.wp_summary, wp_summary(vec![
+        (FnID::new(1, 0), vec![DefSite::OtherCall(0)]),
+        (FnID::new(2, 0), vec![global]),
+    ]));
+    // A global is never freed, so it is not an unpaired allocation.
+    assert!(wpa_result.unpaired_allocs().unwrap().is_empty());
+}
+
+#[test]
+fn test_union_read_source() {
+    // f2 reads a pointer field of a union at bb0 and returns it. f1 calls f2
+    // at bb1 and dereferences the result in unsafe code.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(1, vec![])])], (vec![], vec![]),
+                Some(vec![DefSite::OtherCall(1)])),
+        summary(2, vec![], (vec![DefSite::UnionRead(0)], vec![]), None),
+    ]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.wp_summary, wp_summary(vec![
+        (FnID::new(1, 0), vec![DefSite::OtherCall(1)]),
+        (FnID::new(2, 0), vec![DefSite::UnionRead(0)]),
+    ]));
+    assert!(wpa_result.unpaired_allocs().unwrap().is_empty());
+}
+
+#[test]
+fn test_transmute_source() {
+    // f2 transmutes its first argument to a pointer at bb0 and returns it. f1
+    // passes a heap allocation made at bb0 to f2 at bb1 and dereferences the
+    // result in unsafe code.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(1, vec![vec![DefSite::HeapAlloc(0)]])])],
+                (vec![], vec![]), Some(vec![DefSite::OtherCall(1)])),
+        summary(2, vec![], (vec![DefSite::Transmute(0)], vec![DefSite::Arg(1)]), None),
+    ]);
+
+    // Both the transmute and the allocation it is transmuted from are unsafe.
+    assert_eq!(WpaResult::new(&summaries).unwrap().wp_summary, wp_summary(vec![
+        (FnID::new(1, 0), vec![DefSite::HeapAlloc(0), DefSite::OtherCall(1)]),
+        (FnID::new(2, 0), vec![DefSite::Arg(1), DefSite::Transmute(0)]),
+    ]));
+}
+
+#[test]
+fn test_raw_slice_source() {
+    // f1 returns "slice::from_raw_parts(p, len)" called at bb0, which carries
+    // the taint of p but not of len. f2 calls f1 at bb1 and reads the slice
+    // in unsafe code.
+    let mut from_raw_parts = callee(9, vec![(0, vec![vec![DefSite::Arg(1)], vec![DefSite::Arg(2)]])]);
+    from_raw_parts["fn_name"] = json!("from_raw_parts");
+    let summaries = summaries(vec![
+        summary(1, vec![from_raw_parts], (vec![DefSite::RawSlice(0)], vec![DefSite::Arg(1)]),
+                None),
+        summary(2, vec![callee(1, vec![(1, vec![vec![], vec![]])])], (vec![], vec![]),
+                Some(vec![DefSite::OtherCall(1)])),
+    ]);
+
+    // len, i.e., Arg(2) of f1, stays untainted.
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.wp_summary, wp_summary(vec![
+        (FnID::new(1, 0), vec![DefSite::RawSlice(0)]),
+        (FnID::new(2, 0), vec![DefSite::OtherCall(1)]),
+    ]));
+    assert_eq!(format_wp_summary(&wpa_result.wp_summary, &summaries, &wpa_result.reachable),
+        "krate::f1\n    RawSlice at BB0: krate::from_raw_parts\nkrate::f2\n    OtherCall at BB1: krate::f1\n");
+}
+
+#[test]
+fn test_variadic_callee() {
+    // f1 calls the variadic foreign function printf (fn 9) at bb0 with two
+    // arguments and at bb1 with three, the last one being its own Arg(1). f2
+    // passes a heap allocation to f1 at bb0.
+    let printf = callee(9, vec![(0, vec![vec![], vec![]]),
+                                (1, vec![vec![], vec![], vec![DefSite::Arg(1)]])]);
+    let mut f1 = serde_json::to_value(summary(1, vec![printf.clone()], (vec![], vec![]),
+                                              Some(vec![DefSite::Arg(1)]))).unwrap();
+    f1["foreign_callees"] = json!([[9, 0]]);
+    let summaries = summaries(vec![
+        serde_json::from_value::<Summary>(f1).unwrap(),
+        summary(2, vec![callee(1, vec![(0, vec![vec![DefSite::HeapAlloc(1)]])])],
+                (vec![], vec![]), Some(vec![DefSite::HeapAlloc(1)])),
+    ]);
+
+    // The arguments of each call are looked up by the BB of the call.
+    let printf = serde_json::from_value::<Callee>(printf).unwrap();
+    assert!(printf.get_arg_defs(0, 3).is_none());
+    assert!(printf.get_arg_defs(1, 3).is_some_and(|defs| defs.contains(&DefSite::Arg(1))));
+
+    // The allocation flows into the third argument of the second call only.
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.wp_summary, wp_summary(vec![
+        (FnID::new(1, 0), vec![DefSite::Arg(1)]),
+        (FnID::new(2, 0), vec![DefSite::HeapAlloc(1)]),
+        (FnID::new(9, 0), vec![DefSite::Arg(3)]),
+    ]));
+    assert!(wpa_result.self_referential_allocs().is_empty());
+}
+
+#[test]
+fn test_other_call_without_callee() {
+    // f1 has an unsafe call at bb5, but no callee is recorded for bb5.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(0, vec![])])], (vec![], vec![]),
+                Some(vec![DefSite::OtherCall(5)])),
+        summary(2, vec![], (vec![], vec![]), None),
+    ]);
+
+    assert!(WpaResult::new(&summaries).unwrap().wp_summary.is_empty());
+}
+
+#[test]
+fn test_crate_stats() {
+    // f1 passes a heap allocation made at bb0 to f2 at bb1, which reads it in
+    // unsafe code. f3 has no unsafe source.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(1, vec![vec![DefSite::HeapAlloc(0)]])])],
+                (vec![], vec![]), None),
+        summary(2, vec![], (vec![], vec![]), Some(vec![DefSite::Arg(1)])),
+        summary(3, vec![], (vec![], vec![]), None),
+    ]);
+
+    let stats = WpaResult::new(&summaries).unwrap().stats();
+    assert_eq!(stats.len(), 1);
+    assert_eq!(stats["krate"], Stats {
+        fns_analyzed: 3,
+        unsafe_fns: 2,
+        heap_alloc_sites: 1,
+        native_calls: 0,
+        other_call_sources: 0,
+        arg_sources: 1,
+        uninit_sources: 0,
+        global_sources: 0,
+        union_read_sources: 0,
+        transmute_sources: 0,
+        raw_slice_sources: 0,
+        int_to_ptr_sources: 0,
+        const_sources: 0,
+        mut_sources: 0,
+        inline_asm_fns: 0,
+        intrinsic_call_fns: 0,
+        unsafe_derefs: 0,
+        fns_collected: 0,
+        fns_selected: 0,
+    });
+}
+
+#[test]
+fn test_crate_stats_by_kind() {
+    // f1 makes a native call at bb0 and a heap allocation at bb1, both used in
+    // unsafe code, and passes the allocation to f4 at bb3. f2 returns a heap
+    // allocation used in unsafe code to f3, which calls it at bb2.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(4, vec![(3, vec![vec![DefSite::HeapAlloc(1)]])])],
+                (vec![], vec![]), Some(vec![DefSite::NativeCall(0), DefSite::HeapAlloc(1)])),
+        summary(2, vec![], (vec![DefSite::HeapAlloc(0)], vec![]),
+                Some(vec![DefSite::HeapAlloc(0)])),
+        summary(3, vec![callee(2, vec![(2, vec![])])], (vec![], vec![]), None),
+        summary(4, vec![], (vec![], vec![]), None),
+    ]);
+
+    let stats = WpaResult::new(&summaries).unwrap().stats();
+    assert_eq!(stats["krate"], Stats {
+        fns_analyzed: 4,
+        unsafe_fns: 4,
+        heap_alloc_sites: 2,
+        native_calls: 1,
+        other_call_sources: 1,
+        arg_sources: 1,
+        uninit_sources: 0,
+        global_sources: 0,
+        union_read_sources: 0,
+        transmute_sources: 0,
+        raw_slice_sources: 0,
+        int_to_ptr_sources: 0,
+        const_sources: 0,
+        mut_sources: 0,
+        inline_asm_fns: 0,
+        intrinsic_call_fns: 0,
+        unsafe_derefs: 0,
+        fns_collected: 0,
+        fns_selected: 0,
+    });
+}
+
+#[test]
+fn test_crate_stats_low_level() {
+    // f1 has inline assembly, reads an uninitialized value and writes through
+    // a pointer cast from an integer. f2 calls an intrinsic.
+    let mut f1 = serde_json::to_value(summary(
+        1, vec![], (vec![], vec![]), Some(vec![DefSite::Uninit(0), DefSite::IntToPtr(1)]))).unwrap();
+    f1["has_inline_asm"] = json!(true);
+    f1["source_mutability"] = json!([[DefSite::Uninit(0), PtrMutability::Const],
+                                     [DefSite::IntToPtr(1), PtrMutability::Mut]]);
+    let mut f2 = serde_json::to_value(summary(2, vec![], (vec![], vec![]), None)).unwrap();
+    f2["has_intrinsic_call"] = json!(true);
+    let summaries = summaries(vec![
+        serde_json::from_value::<Summary>(f1).unwrap(),
+        serde_json::from_value::<Summary>(f2).unwrap(),
+    ]);
+
+    let stats = &WpaResult::new(&summaries).unwrap().stats()["krate"];
+    assert_eq!((stats.uninit_sources, stats.int_to_ptr_sources), (1, 1));
+    assert_eq!((stats.const_sources, stats.mut_sources), (1, 1));
+    assert_eq!((stats.inline_asm_fns, stats.intrinsic_call_fns), (1, 1));
+}
+
+#[test]
+fn test_select_rooted() {
+    // 1 is the root. 2 calls 1 and 3; 3 calls 4. 1 calls 5. 6 calls 2, and 7
+    // is unrelated.
+    let edges = [(2, 1), (2, 3), (3, 4), (1, 5), (6, 2)];
+    let mut callees = FxHashMap::<u32, Vec<u32>>::default();
+    let mut callers = FxHashMap::<u32, Vec<u32>>::default();
+    for (caller, callee) in edges {
+        callees.entry(caller).or_default().push(callee);
+        callers.entry(callee).or_default().push(caller);
+    }
+    callees.entry(7).or_default();
+
+    // The callees of a caller of the root are selected as well.
+    let selected = select_rooted(&[1], &callees, &callers);
+    assert_eq!(selected, [1, 2, 3, 4, 5, 6].into_iter().collect::<FxHashSet<_>>());
+    assert!(select_rooted(&[], &callees, &callers).is_empty());
+}
+
+#[test]
+fn test_update_call_graph_node() {
+    // f1 calls f2 and f3, and f4 calls f3. f1 is then changed to call f3 and
+    // f4 instead.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(0, vec![])]), callee(3, vec![(1, vec![])])],
+                (vec![], vec![]), None),
+        summary(2, vec![], (vec![], vec![]), None),
+        summary(3, vec![], (vec![], vec![]), None),
+        summary(4, vec![callee(3, vec![(0, vec![])])], (vec![], vec![]), None),
+    ]);
+    let new_f1 = summary(1, vec![callee(3, vec![(0, vec![])]), callee(4, vec![(1, vec![])])],
+                         (vec![], vec![]), None);
+    let mut cg = build_call_graph(&summaries);
+    cg.update_node(summaries.get(&FnID::new(1, 0)), &new_f1);
+
+    let fn_ids = |ids: Vec<u64>| ids.into_iter().map(|id| FnID::new(id, 0)).collect::<FxHashSet<_>>();
+    assert_eq!(*cg.get_callees(&FnID::new(1, 0)), fn_ids(vec![3, 4]));
+    assert_eq!(*cg.get_callees(&FnID::new(4, 0)), fn_ids(vec![3]));
+    assert_eq!(*cg.get_callers(&FnID::new(2, 0)), fn_ids(vec![]));
+    assert_eq!(*cg.get_callers(&FnID::new(3, 0)), fn_ids(vec![1, 4]));
+    assert_eq!(*cg.get_callers(&FnID::new(4, 0)), fn_ids(vec![1]));
+    // The node now refers to the new summary of f1.
+    assert!(cg.get_summary(&FnID::new(1, 0)).is_some_and(|s| std::ptr::eq(s, &new_f1)));
+}
+
+#[test]
+fn test_recursive_sccs() {
+    // f1 calls f2, and f2 and f3 are mutually recursive. f2 passes an unsafe
+    // allocation to f3, which passes it back to f2.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(0, vec![vec![]])])], (vec![], vec![]), None),
+        summary(2, vec![callee(3, vec![(1, vec![vec![DefSite::HeapAlloc(0), DefSite::Arg(1)]])])],
+                (vec![], vec![]), Some(vec![DefSite::HeapAlloc(0)])),
+        summary(3, vec![callee(2, vec![(2, vec![vec![DefSite::Arg(1)]])])],
+                (vec![], vec![]), None),
+    ]);
+    let sccs = build_call_graph(&summaries).sccs();
+    assert_eq!(sccs, vec![vec![FnID::new(2, 0), FnID::new(3, 0)], vec![FnID::new(1, 0)]]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.wp_summary, wp_summary(vec![
+        (FnID::new(2, 0), vec![DefSite::HeapAlloc(0), DefSite::Arg(1)]),
+        (FnID::new(3, 0), vec![DefSite::Arg(1)]),
+    ]));
+}
+
+#[test]
+fn test_recursive_ret_sources() {
+    // f1 calls f2 at bb0, and f2 and f3 are mutually recursive. f2 returns
+    // what f3 returns at bb1, and f3 returns an allocation at bb0 or what f2
+    // returns at bb2. The allocation is found from the call in f1 although
+    // neither f2 nor f3 has any unsafe def site of its own.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(0, vec![])])], (vec![], vec![]),
+                Some(vec![DefSite::OtherCall(0)])),
+        summary(2, vec![callee(3, vec![(1, vec![])])],
+                (vec![DefSite::OtherCall(1)], vec![]), None),
+        summary(3, vec![callee(2, vec![(2, vec![])])],
+                (vec![DefSite::HeapAlloc(0), DefSite::OtherCall(2)], vec![]), None),
+    ]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.wp_summary, wp_summary(vec![
+        (FnID::new(1, 0), vec![DefSite::OtherCall(0)]),
+        (FnID::new(2, 0), vec![DefSite::OtherCall(1)]),
+        (FnID::new(3, 0), vec![DefSite::HeapAlloc(0), DefSite::OtherCall(2)]),
+    ]));
+}
+
+#[test]
+fn test_unsafe_source_mutability() {
+    // f1 reads *const u8 arguments 2 and 10 and writes to a *mut u8 allocated
+    // at bb0; f2 writes to a Box allocated at bb1.
+    let mut f1 = serde_json::to_value(summary(
+        1, vec![], (vec![], vec![]),
+        Some(vec![DefSite::Arg(10), DefSite::Arg(2), DefSite::HeapAlloc(0)]))).unwrap();
+    f1["source_mutability"] = json!([[DefSite::Arg(10), PtrMutability::Const],
+                                     [DefSite::Arg(2), PtrMutability::Const],
+                                     [DefSite::HeapAlloc(0), PtrMutability::Mut]]);
+    let mut f2 = serde_json::to_value(summary(
+        2, vec![], (vec![], vec![]), Some(vec![DefSite::HeapAlloc(1)]))).unwrap();
+    f2["source_mutability"] = json!([[DefSite::HeapAlloc(1), PtrMutability::Mut]]);
+    let summaries = summaries(vec![
+        serde_json::from_value::<Summary>(f2).unwrap(),
+        serde_json::from_value::<Summary>(f1).unwrap(),
+    ]);
+
+    // Sorted by FnID and then by the order of DefSite, e.g., Arg(2) before
+    // Arg(10).
+    assert_eq!(WpaResult::new(&summaries).unwrap().unsafe_source_mutability(), vec![
+        (FnID::new(1, 0), DefSite::HeapAlloc(0), PtrMutability::Mut),
+        (FnID::new(1, 0), DefSite::Arg(2), PtrMutability::Const),
+        (FnID::new(1, 0), DefSite::Arg(10), PtrMutability::Const),
+        (FnID::new(2, 0), DefSite::HeapAlloc(1), PtrMutability::Mut),
+    ]);
+}
+
+#[test]
+fn test_missing_summary_dir() {
+    let dir = std::env::temp_dir().join(format!("sandbox-missing-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    assert!(matches!(read_summary_dir(dir.to_str().unwrap()), Err(SandboxError::SummaryIo(_))));
+}
+
+#[test]
+fn test_corrupted_summary_file() {
+    // A partially written file is skipped, and the other files are still read.
+    let dir = std::env::temp_dir().join(format!("sandbox-corrupted-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir(&dir).unwrap();
+    fs::write(dir.join("krate-1"), "{\"target\": ").unwrap();
+    let header = SummaryHeader { target: "x86_64-unknown-linux-gnu".to_string(), cfg: Vec::new(), generation: 0 };
+    write_summary_chunks(&header, &[summary(2, vec![], (vec![], vec![]), None)],
+                         dir.join("krate-2").to_str().unwrap(), None).unwrap();
+
+    let result = read_summary_dir(dir.to_str().unwrap());
+    fs::remove_dir_all(&dir).unwrap();
+    assert_eq!(result.unwrap().into_keys().collect::<Vec<_>>(), vec![FnID::new(2, 0)]);
+}
+
+#[test]
+fn test_concurrent_summary_writes() {
+    // Two threads repeatedly write different summaries of the same crate to
+    // the same directory. Each read sees the complete summaries of one thread.
+    let dir = std::env::temp_dir().join(format!("sandbox-concurrent-{}", std::process::id()));
+    let _ = fs::remove_dir_all(&dir);
+
+    let writers = (0..2u64).map(|t| {
+        let dir = dir.to_str().unwrap().to_owned();
+        std::thread::spawn(move || {
+            let header = SummaryHeader { target: "x86_64-unknown-linux-gnu".to_string(), cfg: Vec::new(), generation: 0 };
+            let all = (1..=10).map(|i| summary(t * 100 + i, vec![], (vec![], vec![]), None))
+                .collect::<Vec<_>>();
+            let max_bytes = serde_json::to_vec(&all[0]).unwrap().len() * 3;
+            for _ in 0..20 {
+                write_summary_files(&dir, "krate-1", &header, &all, Some(max_bytes)).unwrap();
+                let read_back = read_summary_dir(&dir).unwrap();
+                assert!(read_back.len() >= 10);
+            }
+        })
+    }).collect::<Vec<_>>();
+    for writer in writers {
+        writer.join().unwrap();
+    }
+
+    let mut fn_ids = read_summary_dir(dir.to_str().unwrap()).unwrap().into_keys()
+        .map(|fn_id| fn_id.as_value().0).collect::<Vec<_>>();
+    fn_ids.sort();
+    let file_num = fs::read_dir(&dir).unwrap().count();
+    fs::remove_dir_all(&dir).unwrap();
+    assert!(fn_ids == (1..=10).collect::<Vec<_>>() || fn_ids == (101..=110).collect::<Vec<_>>());
+    // The chunks and the lock file, with no temporary files left.
+    assert_eq!(file_num, 5);
+}
+
+#[test]
+fn test_missing_callee_summary() {
+    // f1 uses the return value of f9 in unsafe code, but f9 has no summary.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(9, vec![(0, vec![])])], (vec![], vec![]),
+                Some(vec![DefSite::OtherCall(0)])),
+    ]);
+
+    match WpaResult::new(&summaries) {
+        Err(SandboxError::MissingCallee { callee, caller }) => {
+            assert!(callee.contains("f9"), "{}", callee);
+            assert!(caller.contains("f1"), "{}", caller);
+        },
+        result => panic!("Expected MissingCallee, got {:?}", result.err()),
+    }
+}
+
+#[test]
+fn test_unexpected_ret_def() {
+    // f1 uses the return value of f2 in unsafe code, but the arg ret_defs of
+    // f2 has a heap allocation, e.g., from a corrupted summary file.
+    let summaries = summaries(vec![
+        summary(1, vec![callee(2, vec![(0, vec![])])], (vec![], vec![]),
+                Some(vec![DefSite::OtherCall(0)])),
+        summary(2, vec![], (vec![], vec![DefSite::HeapAlloc(0)]), None),
+    ]);
+
+    match WpaResult::new(&summaries) {
+        Err(SandboxError::UnexpectedDefSite { fn_name, .. }) => {
+            assert!(fn_name.contains("f2"), "{}", fn_name);
+        },
+        result => panic!("Expected UnexpectedDefSite, got {:?}", result.err()),
+    }
+}
+
+#[test]
+fn test_archive_schema_mismatch() {
+    use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+    use std::io::Write;
+
+    let summaries = summaries(vec![summary(1, vec![], (vec![], vec![]), None)]);
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    let path = std::env::temp_dir().join(format!("sandbox-schema-{}.json.gz", std::process::id()));
+    archive::write_archive(&path, &summaries, &wpa_result).unwrap();
+
+    // Rewrite the archive as if it were from a future version.
+    let mut bundle: Value = serde_json::from_reader(GzDecoder::new(fs::File::open(&path).unwrap()))
+        .unwrap();
+    bundle["schema_version"] = json!(999);
+    let mut encoder = GzEncoder::new(fs::File::create(&path).unwrap(), Compression::default());
+    encoder.write_all(bundle.to_string().as_bytes()).unwrap();
+    encoder.finish().unwrap();
+
+    let result = archive::load_archive(&path);
+    fs::remove_file(&path).unwrap();
+    match result {
+        Err(SandboxError::SchemaMismatch { expected, found }) => {
+            assert_eq!(expected, archive::ARCHIVE_SCHEMA_VERSION);
+            assert_eq!(found, 999);
+        },
+        result => panic!("Expected SchemaMismatch, got {:?}", result.err()),
+    }
+}
+
+#[test]
+#[cfg(any(unix, windows))]
+fn test_default_paths() {
+    // Without the overrides, the paths are in the temporary directory, which
+    // is absolute on all the tier-1 hosts.
+    if std::env::var("SANDBOX_SUMMARY_DIR").is_err() {
+        let summary_dir = get_summary_dir();
+        assert!(!summary_dir.is_empty() && Path::new(&summary_dir).is_absolute());
+    }
+    if std::env::var("SANDBOX_OUTPUT_DIR").is_err() {
+        let wp_summary_path = get_wp_summary_path();
+        assert!(!wp_summary_path.is_empty() && Path::new(&wp_summary_path).is_absolute());
+    }
+}
+
+#[test]
+fn test_ignore_crate_name() {
+    // Whether a crate is ignored only depends on its name, which is what
+    // makes caching it per crate safe.
+    for name in ["core", "std", "alloc", "build_script_build", "build_script_main"] {
+        assert!(ignore_crate_name(name), "{}", name);
+    }
+    for name in ["krate", "libc", "build_script"] {
+        assert!(!ignore_crate_name(name), "{}", name);
+    }
+}
+
+#[test]
+fn test_ignore_fn_name() {
+    let fns = parse_fn_list("foo, bar,,");
+    assert_eq!(fns, ["foo", "bar"].into_iter().map(|name| name.to_string()).collect::<FxHashSet<_>>());
+    assert!(!ignore_fn_name_in("foo", &fns));
+    assert!(!ignore_fn_name_in("bar", &fns));
+    assert!(ignore_fn_name_in("whatever", &fns));
+
+    // No function is left out by its name with an empty list.
+    let fns = parse_fn_list("");
+    assert!(fns.is_empty());
+    assert!(!ignore_fn_name_in("whatever", &fns));
+    assert!(!ignore_fn_name_in("foo", &fns));
+}
+
+#[test]
+fn test_unsafe_alloc_types() {
+    // f1 allocates a Vec<u64> at BB2, which is an unsafe source, and a String
+    // at BB5, which is not. It passes the Vec to the foreign function 9 at
+    // BB3, which has an unsafe Arg but no Summary.
+    let mut f1 = serde_json::to_value(summary(1,
+        vec![callee(9, vec![(3, vec![vec![DefSite::HeapAlloc(2)]])])],
+        (vec![], vec![]), Some(vec![DefSite::HeapAlloc(2)]))).unwrap();
+    f1["alloc_types"] = json!([[DefSite::HeapAlloc(2), "u64"], [DefSite::HeapAlloc(5), "String"]]);
+    f1["foreign_callees"] = json!([[9, 0]]);
+    let summaries = summaries(vec![serde_json::from_value::<Summary>(f1).unwrap()]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.unsafe_alloc_types(), vec![(FnID::new(1, 0), DefSite::HeapAlloc(2), "u64")]);
+}
+
+#[test]
+fn test_box_alloc_same_bb() {
+    // f1 allocates a Box<u8> in place at BB2, whose terminator is a call to
+    // allocate a Vec<u64>. Both are unsafe sources and leaked, and each keeps
+    // its own type.
+    let mut f1 = serde_json::to_value(summary(1, vec![], (vec![], vec![]),
+        Some(vec![DefSite::HeapAlloc(2), DefSite::BoxAlloc(2)]))).unwrap();
+    f1["alloc_types"] = json!([[DefSite::HeapAlloc(2), "u64"], [DefSite::BoxAlloc(2), "u8"]]);
+    let summaries = summaries(vec![serde_json::from_value::<Summary>(f1).unwrap()]);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.unsafe_alloc_types(), vec![
+        (FnID::new(1, 0), DefSite::HeapAlloc(2), "u64"),
+        (FnID::new(1, 0), DefSite::BoxAlloc(2), "u8"),
+    ]);
+    assert_eq!(wpa_result.unpaired_allocs().unwrap(), vec![
+        (FnID::new(1, 0), DefSite::HeapAlloc(2)),
+        (FnID::new(1, 0), DefSite::BoxAlloc(2)),
+    ]);
+}
+
+#[test]
+fn test_reachable_fns() {
+    // f1 is main() and passes an allocation at bb0 to f2 at bb1. f3 has an
+    // unsafe allocation at bb0, but nothing calls it.
+    let build = |f1_flag: &str, f3_flag: &str| {
+        let mut f1 = serde_json::to_value(summary(1,
+            vec![callee(2, vec![(1, vec![vec![DefSite::HeapAlloc(0)]])])],
+            (vec![], vec![]), Some(vec![DefSite::HeapAlloc(0)]))).unwrap();
+        f1[f1_flag] = json!(true);
+        let mut f3 = serde_json::to_value(
+            summary(3, vec![], (vec![], vec![]), Some(vec![DefSite::HeapAlloc(0)]))).unwrap();
+        f3[f3_flag] = json!(true);
+        summaries(vec![
+            serde_json::from_value::<Summary>(f1).unwrap(),
+            summary(2, vec![], (vec![], vec![]), None),
+            serde_json::from_value::<Summary>(f3).unwrap(),
+        ])
+    };
+
+    let summaries = build("is_entry", "is_exported");
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.unreachable_unsafe_fns(), vec![FnID::new(3, 0)]);
+    assert_eq!(format_wp_summary(&wpa_result.wp_summary, &summaries, &wpa_result.reachable),
+        "krate::f1\n    HeapAlloc at BB0\nkrate::f2\n    Arg 1\nkrate::f3 (unreachable)\n    HeapAlloc at BB0\n");
+
+    // Without main(), the exported functions are the entry points.
+    let summaries = build("is_exported", "is_exported");
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert!(wpa_result.unreachable_unsafe_fns().is_empty());
+    let summaries = build("is_exported", "has_inline_asm");
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    assert_eq!(wpa_result.unreachable_unsafe_fns(), vec![FnID::new(3, 0)]);
+}
+
+#[test]
+fn test_reachable_via_fn_values() {
+    // f1 is main() and calls nothing, but creates a vtable with f2, coerces
+    // the closure f3 to a Fn trait object, and coerces f4 to a fn pointer.
+    // Each of them has an unsafe allocation at bb0; f5 is unreachable.
+    let mut f1 = serde_json::to_value(summary(1, vec![], (vec![], vec![]), None)).unwrap();
+    f1["is_entry"] = json!(true);
+    f1["vtable_fns"] = json!([callee(2, vec![])]);
+    f1["dyn_fn_closures"] = json!([[callee(3, vec![]), 1]]);
+    f1["fn_ptr_fns"] = json!([callee(4, vec![])]);
+    let mut all = vec![serde_json::from_value::<Summary>(f1).unwrap()];
+    for fn_id in 2..=5 {
+        all.push(summary(fn_id, vec![], (vec![], vec![]), Some(vec![DefSite::HeapAlloc(0)])));
+    }
+    let summaries = summaries(all);
+
+    let wpa_result = WpaResult::new(&summaries).unwrap();
+    for fn_id in 1..=4 {
+        assert!(wpa_result.reachable.contains(&FnID::new(fn_id, 0)), "f{} is unreachable", fn_id);
+    }
+    assert_eq!(wpa_result.unreachable_unsafe_fns(), vec![FnID::new(5, 0)]);
+}
+
+#[test]
+fn test_read_extra_database() {
+    let path = std::env::temp_dir().join(format!("sandbox-db-{}.json", std::process::id()));
+    fs::write(&path, r#"{ "heap_alloc": ["alloc_page"] }"#).unwrap();
+
+    // An omitted list is empty.
+    let db = read_extra_database(path.to_str().unwrap()).unwrap();
+    assert_eq!(db, ExtraDatabase { heap_alloc: vec!["alloc_page".to_owned()], native_libs: vec![] });
+
+    // A missing or invalid file is an error, for which the built-in database
+    // is used alone.
+    fs::write(&path, "[").unwrap();
+    assert!(matches!(read_extra_database(path.to_str().unwrap()), Err(SandboxError::Deserialize(_))));
+    let _ = fs::remove_file(&path);
+    assert!(matches!(read_extra_database(path.to_str().unwrap()), Err(SandboxError::SummaryIo(_))));
+}
+
+#[test]
+fn test_fake_read_place() {
+    // Optimized MIR has no FakeRead, but the MIR of a pattern match or a
+    // let-binding before CleanupPostBorrowck does. Its Place is read.
+    let place = Place::from(Local::from_u32(1));
+    let stmt = Statement {
+        source_info: SourceInfo::outermost(DUMMY_SP),
+        kind: StatementKind::FakeRead(Box::new((FakeReadCause::ForMatchedPlace(None), place))),
+    };
+    let mut places = Vec::new();
+    get_place_in_stmt(&stmt, &mut places);
+    assert_eq!(places, vec![place]);
+}
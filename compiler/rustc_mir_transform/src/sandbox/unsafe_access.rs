@@ -4,15 +4,33 @@
 use rustc_middle::ty::{TyCtxt};
 use rustc_middle::mir::*;
 use rustc_hir::def_id::{DefId};
-use rustc_data_structures::fx::{FxHashSet};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::stable_hasher::{Hash64, StableHasher};
+use rustc_span::Span;
+use std::collections::VecDeque;
+use std::hash::Hash;
 use std::fs;
 use std::path::Path;
 use std::io::Write;
+use serde::Serialize;
 
+use super::context::{AnalysisCtxt, AnalysisFn};
+use super::error::{SandboxError, SandboxResult};
 use super::wpa::{WPSummary, UnsafeSources};
 use super::summarize_fn::{DefSite, FnID};
 use super::utils::*;
 
+/// Kind of an unsafe memory access.
+#[derive(Serialize, Copy, Clone, PartialEq, Eq, Debug)]
+pub enum AccessKind {
+    /// Dereference of an unsafe pointer.
+    Deref,
+    /// Dereference of an unsafe pointer that was most recently adjusted by
+    /// pointer arithmetic with an unsafe offset, e.g., "*p.add(i)" where i is
+    /// unsafe. This is a classic setup of out-of-bounds accesses.
+    ArithDeref,
+}
+
 /// Unsafe memory accesses in one Statement or one Terminator.
 pub struct UnsafeAccess{
     _bb: u32,
@@ -22,22 +40,77 @@ pub struct UnsafeAccess{
     /// Unsafe Local of Place in this Statement/Terminator. Each S/T may have
     /// multiple Place.
     locals: Vec::<u32>,
+    pub kind: AccessKind,
+    /// Unsafe Local in locals that this Statement/Terminator writes memory
+    /// through, e.g., p in "*p = *q". A write is more dangerous than a read.
+    written: Vec::<u32>,
+    /// User-visible source location of this Statement/Terminator. For code
+    /// expanded from a macro, this is where the macro is invoked.
+    pub span: Span,
+    /// The start of span, for tools that map the access back to the source.
+    pub loc: SourceLoc,
+    /// Name of the macro that this Statement/Terminator is expanded from.
+    pub macro_name: Option<String>,
+}
+
+impl UnsafeAccess {
+    /// Check if this Statement/Terminator writes to memory through an unsafe
+    /// pointer.
+    pub fn is_write(&self) -> bool {
+        !self.written.is_empty()
+    }
 }
 
 pub type UnsafeAccesses = (FnID, Vec::<UnsafeAccess>);
 
+/// A source location in the form to serialize. The line and the column are
+/// 1-based, as in diagnostics.
+#[derive(Serialize, Clone, PartialEq, Eq, Debug)]
+pub struct SourceLoc {
+    pub file: String,
+    pub line: u32,
+    pub col: u32,
+}
+
+impl SourceLoc {
+    /// Get the source location of the start of a Span.
+    fn new<'tcx>(tcx: TyCtxt<'tcx>, span: Span) -> SourceLoc {
+        let source_map = tcx.sess.source_map();
+        let loc = source_map.lookup_char_pos(span.lo());
+        SourceLoc {
+            file: source_map.filename_for_diagnostics(&loc.file.name).to_string(),
+            line: loc.line as u32,
+            col: loc.col.to_usize() as u32 + 1,
+        }
+    }
+}
+
 /// Read in the wholle-program analysis result, i.e., unsafe sources.
-pub fn read_wpa() -> WPSummary {
-    let wpa_result_str = fs::read_to_string(get_wp_summary_path()).expect(
-        "Read the WPA result to string");
-    let unsafe_sources = serde_json::from_str::<UnsafeSources>(&wpa_result_str).
-        expect("deserializing wpa result");
+pub fn read_wpa() -> SandboxResult<WPSummary> {
+    read_wpa_file(Path::new(&get_wp_summary_path()))
+}
+
+/// Read in a whole-program analysis result from a given file.
+pub fn read_wpa_file(path: &Path) -> SandboxResult<WPSummary> {
+    Ok(read_wpa_file_with_reachable(path)?.0)
+}
+
+/// Read in a whole-program analysis result from a given file, and the
+/// functions in it that are reachable from the entry points of the program.
+pub fn read_wpa_file_with_reachable(path: &Path)
+    -> SandboxResult<(WPSummary, FxHashSet<FnID>)> {
+    let wpa_result_str = fs::read_to_string(path)?;
+    let unsafe_sources = serde_json::from_str::<UnsafeSources>(&wpa_result_str)?;
     let mut wpa_result = WPSummary::default();
-    for fn_unsafe_source in unsafe_sources {
-        wpa_result.insert(fn_unsafe_source.0, fn_unsafe_source.1);
+    let mut reachable = FxHashSet::<FnID>::default();
+    for (fn_id, def_sites, is_reachable) in unsafe_sources {
+        wpa_result.insert(fn_id, def_sites);
+        if is_reachable {
+            reachable.insert(fn_id);
+        }
     }
 
-    wpa_result
+    Ok((wpa_result, reachable))
 }
 
 /// Get the max of a given u32 and the u32 of the Local of a Place.
@@ -91,87 +164,353 @@ fn count_place_num<'tcx>(body: &'tcx Body<'tcx>) -> u32 {
     max_local
 }
 
-/// Collect the Local of all unsafe Place of a function. The algorithm is
-/// simple: examine each StatementKind::Assign, and if any unsafe Place is
-/// used in the RHS, then the LHS is regarded as unsafe as well. Repeat this
-/// process until there is no new unsafe Place added.
-fn collect_unsafe_locals<'tcx>(unsafe_sources: &FxHashSet<DefSite>,
-                               body: &'tcx Body<'tcx>) -> FxHashSet<Local> {
-    // Unsafe arguments and non-arg places(as u32).
-    let mut unsafe_locals = FxHashSet::<Local>::default();
-    let mut unsafe_bb = FxHashSet::<u32>::default();
+/// Get the base pointer of a reborrow, i.e., a Rvalue::Ref of a Place with a
+/// Deref projection such as "&(*p).field" or "&*p". The new reference points
+/// into the pointee of the base pointer. For nested dereferences such as
+/// "&(*(*pp).0).1", the base is the outermost pointer (pp), or the field of
+/// a Local that holds it, e.g., _3.0 of "&(*(_3.0))".
+fn get_reborrow_base<'tcx>(rvalue: &Rvalue<'tcx>) -> Option<PlaceKey> {
+    if let Rvalue::Ref(_, _, place) = rvalue {
+        if place.projection.iter().any(|elem| elem == ProjectionElem::Deref) {
+            return Some(PlaceKey::of(place));
+        }
+    }
+
+    None
+}
+
+/// Unsafe Place (a Local or a field of it) and the unsafe sources that seed
+/// the taint of each of them.
+pub type Provenance = FxHashMap<PlaceKey, FxHashSet<DefSite>>;
+
+/// Collect the unsafe sources of all the Place in @state that may refer to the
+/// same memory as @key, e.g., both _3.0 and _3.1 for _3.
+fn get_provenance(state: &Provenance, key: &PlaceKey, sources: &mut FxHashSet<DefSite>) {
+    for (state_key, state_sources) in state {
+        if state_key.overlaps(key) {
+            sources.extend(state_sources.iter().copied());
+        }
+    }
+}
+
+/// Add the unsafe sources of one Place to another. Return true if @to changes.
+fn add_provenance(state: &mut Provenance, to: PlaceKey, sources: &FxHashSet<DefSite>)
+    -> bool {
+    let to_sources = state.entry(to).or_default();
+    let old_len = to_sources.len();
+    to_sources.extend(sources.iter().copied());
+    to_sources.len() != old_len
+}
+
+/// Set the unsafe sources of a Place written by a Statement or a Terminator.
+/// Writing a whole Local (@strong) replaces the sources of the Local and of
+/// all its fields, so that a safe value assigned to it clears its taint.
+/// Writing a projection of it only adds to them, as the rest of the Local is
+/// unchanged.
+fn assign_provenance(state: &mut Provenance, key: PlaceKey, strong: bool,
+                     sources: &FxHashSet<DefSite>) {
+    if strong {
+        state.retain(|state_key, _| state_key.local != key.local);
+    }
+    if !sources.is_empty() {
+        add_provenance(state, key, sources);
+    }
+}
+
+/// The unsafe Place of a function at each point of it; see
+/// collect_unsafe_provenance().
+struct UnsafeFlow<'a, 'tcx> {
+    body: &'tcx Body<'tcx>,
+    unsafe_sources: &'a FxHashSet<DefSite>,
+    /// Unsafe calls, by their BB.
+    unsafe_bb: FxHashMap<u32, DefSite>,
+    mut_borrows: FxHashMap<Local, Local>,
+    /// Unsafe Place at the entry of each BB.
+    entry_states: FxHashMap<BasicBlock, Provenance>,
+    /// All the Place that are unsafe at some point of the function, each with
+    /// all the unsafe sources that may flow into it.
+    provenance: Provenance,
+}
+
+impl<'a, 'tcx> UnsafeFlow<'a, 'tcx> {
+    /// Get the unsafe Place at the entry of a BB.
+    fn entry_state(&self, bb: BasicBlock) -> Provenance {
+        self.entry_states.get(&bb).cloned().unwrap_or_default()
+    }
+
+    /// Apply a Statement to the unsafe Place in @state. Return the Place it
+    /// writes.
+    fn transfer_stmt(&self, bb: BasicBlock, stmt: &Statement<'tcx>,
+                     state: &mut Provenance) -> Vec<PlaceKey> {
+        match &stmt.kind {
+            StatementKind::Assign(box (lhs_place, rvalue)) => {
+                let strong = lhs_place.projection.is_empty();
+                if let Some(operands) = get_field_operands(rvalue).filter(|_| strong) {
+                    // Each field has the provenance of its own operand.
+                    let mut field_sources = Vec::new();
+                    for (field, operand) in operands.iter_enumerated() {
+                        let mut sources = FxHashSet::<DefSite>::default();
+                        let mut places = Vec::<Place<'tcx>>::new();
+                        get_place_in_operand(operand, &mut places);
+                        for place in places {
+                            get_provenance(state, &PlaceKey::of(&place), &mut sources);
+                        }
+                        field_sources.push((PlaceKey::field(lhs_place.local, field), sources));
+                    }
+                    state.retain(|key, _| key.local != lhs_place.local);
+                    for (key, sources) in &field_sources {
+                        assign_provenance(state, *key, false, sources);
+                    }
+                    return field_sources.into_iter().map(|(key, _)| key).collect();
+                }
+                let mut rhs_keys = Vec::new();
+                if let Some(base) = get_reborrow_base(rvalue) {
+                    // A reborrow such as "_3 = &((*_2).0)" points into the
+                    // pointee of _2, so it has the same provenance as _2.
+                    rhs_keys.push(base);
+                } else {
+                    // The result of pointer arithmetic follows the pointer
+                    // only. The offset is still checked by is_arith_deref()
+                    // when the result is dereferenced.
+                    let mut place_in_rvalue = Vec::<Place<'tcx>>::new();
+                    get_source_place_in_rvalue(&rvalue, &mut place_in_rvalue);
+                    rhs_keys.extend(place_in_rvalue.iter().map(PlaceKey::of));
+                }
+                let mut sources = FxHashSet::<DefSite>::default();
+                for key in rhs_keys {
+                    get_provenance(state, &key, &mut sources);
+                }
+                // An unsafe Box allocated in place, an unsafe global, an unsafe
+                // union field read, an unsafe Transmute cast, or an unsafe
+                // integer-to-pointer cast.
+                let rvalue_site = def_site_from_rvalue(rvalue, self.body, bb.as_u32())
+                    .filter(|def_site| self.unsafe_sources.contains(def_site));
+                sources.extend(rvalue_site);
+                let key = PlaceKey::of(lhs_place);
+                assign_provenance(state, key, strong, &sources);
+                vec![key]
+            },
+            StatementKind::StorageDead(local) => {
+                state.retain(|key, _| key.local != *local);
+                vec![]
+            },
+            _ => vec![]
+        }
+    }
+
+    /// Apply the Terminator of a BB to the unsafe Place in @state. Return the
+    /// Place it writes.
+    fn transfer_terminator(&self, bb: BasicBlock, state: &mut Provenance) -> Vec<PlaceKey> {
+        let bbd = &self.body.basic_blocks()[bb];
+        let TerminatorKind::Call{func, args, destination, ..} = &bbd.terminator().kind else {
+            return vec![];
+        };
+        let ret = call_destination_local(bbd.terminator(), self.body);
+
+        // A native call or a call through a function pointer is not analyzed,
+        // so conservatively assume that the unsafe sources of its args flow to
+        // its return value and to the receiver of a method call that takes a
+        // (two-phase) mutable borrow, e.g., "_4 = Vec::push(move _5, move _6)"
+        // where "_5 = &mut _1".
+        //
+        // The exception is pointer arithmetic, e.g., "_4 = <*mut T>::add(_2, _3)".
+        // Like "Offset(ptr, idx)", its result points into the allocation of the
+        // pointer, so it follows the taint of the pointer only. So does a slice
+        // built by slice::from_raw_parts(ptr, len).
+        let (unanalyzed, ptr_only) = match func {
+            Operand::Constant(f) if let Some(def_site) = def_site_from_call(f, bb.as_u32()) => {
+                (matches!(def_site, DefSite::NativeCall(_) | DefSite::Transmute(_) |
+                                    DefSite::RawSlice(_)),
+                 (is_ptr_arith_call(f) && args.len() == 2) ||
+                    matches!(def_site, DefSite::RawSlice(_)))
+            },
+            // A function pointer, which may be a constant.
+            _ => (true, false),
+        };
+        let mut arg_sources = FxHashSet::<DefSite>::default();
+        if unanalyzed {
+            let mut arg_locals = FxHashSet::<Local>::default();
+            if ptr_only {
+                get_local_in_args(&args[..1], &mut arg_locals);
+            } else {
+                get_local_in_args(args, &mut arg_locals);
+            }
+            for local in arg_locals {
+                get_provenance(state, &PlaceKey::whole(local), &mut arg_sources);
+            }
+        }
+
+        // The return value of an unsafe call. A Transmute cast has been handled
+        // with the statements.
+        let transmute_cast = bbd.statements.iter().any(|stmt| matches!(&stmt.kind,
+            StatementKind::Assign(box (_, Rvalue::Cast(CastKind::Transmute, ..)))));
+        let mut ret_sources = arg_sources.clone();
+        if let Some(def_site) = self.unsafe_bb.get(&bb.as_u32()).filter(|def_site|
+            !(transmute_cast && matches!(def_site, DefSite::Transmute(_)))) {
+            ret_sources.insert(*def_site);
+        }
+
+        let mut written = Vec::new();
+        if !arg_sources.is_empty() {
+            for target in get_mut_borrowed_args(args, &self.mut_borrows) {
+                assign_provenance(state, PlaceKey::whole(target), false, &arg_sources);
+                written.push(PlaceKey::whole(target));
+            }
+        }
+        // The call writes its return value, which is safe unless it is an
+        // unsafe source or it comes from unsafe args.
+        if let Some(ret) = ret {
+            assign_provenance(state, PlaceKey::whole(ret), destination.projection.is_empty(),
+                              &ret_sources);
+            written.push(PlaceKey::whole(ret));
+        }
+
+        written
+    }
+}
+
+/// Collect the unsafe Place (a Local or a field of it) of a function at each
+/// point of it, and their provenance. The algorithm is a simple forward
+/// data-flow analysis: examine each StatementKind::Assign, and if any unsafe
+/// Place is used in the RHS, then the LHS is regarded as unsafe as well and
+/// inherits the unsafe sources of the RHS. Assigning a safe value to a whole
+/// Local clears its taint, and so does a StorageDead(local); the Local becomes
+/// unsafe again only if it is reassigned an unsafe value. This prevents the
+/// taint from leaking across the lifetime boundary of a Local, e.g., a Local
+/// reused in each iteration of a loop. Repeat this process until there is no
+/// new unsafe Place or source added at the entry of any BB.
+///
+/// Each field of a struct or tuple built by an aggregate only inherits the
+/// unsafe sources of its own operand, so an unsafe field does not taint the
+/// other fields of the same Local.
+///
+/// The result has the unsafe Place at the entry of each BB, from which those
+/// at any point in the BB can be computed by UnsafeFlow::transfer_stmt() and
+/// UnsafeFlow::transfer_terminator(), and all the Place that are unsafe at
+/// some point of the function.
+fn collect_unsafe_provenance<'a, 'tcx>(unsafe_sources: &'a FxHashSet<DefSite>,
+                                       body: &'tcx Body<'tcx>)
+    -> SandboxResult<UnsafeFlow<'a, 'tcx>> {
+    // Unsafe arguments and unsafe calls (by their BB).
+    let mut unsafe_args = Provenance::default();
+    let mut unsafe_bb = FxHashMap::<u32, DefSite>::default();
 
     // Collect the Local of unsafe args and the BB of unsafe calls.
     for def_site in unsafe_sources {
         match def_site {
             DefSite::Arg(arg) => {
-                unsafe_locals.insert(Local::from_u32(*arg));
-            },
-            DefSite::HeapAlloc(bb) | DefSite::OtherCall(bb) => {
-                unsafe_bb.insert(*bb);
+                unsafe_args.entry(PlaceKey::whole(Local::from_u32(*arg))).or_default()
+                    .insert(*def_site);
             },
+            DefSite::DropInPlace(_) => {
+                return Err(SandboxError::UnexpectedDefSite {
+                    def_site: format!("{:?}", def_site),
+                    fn_name: format!("{:?}", body.source.def_id()),
+                });
+            }
             _ => {
-                panic!("Native call should not be here");
+                if let Some(bb) = def_site.call_bb() {
+                    unsafe_bb.insert(bb, *def_site);
+                }
+                // Otherwise, e.g., a global, a union read, or an
+                // integer-to-pointer cast, it is seeded at the statement that
+                // takes the address of the global, reads the union field, or
+                // casts the integer to a pointer.
             }
         }
     }
 
-    // Get the LHS Place of unsafe calls.
-    for (bb, bbd) in body.basic_blocks().iter_enumerated() {
-        if unsafe_bb.contains(&bb.as_u32()) {
-            // This bb ends with an unsafe call.
-            match &bbd.terminator().kind {
-                TerminatorKind::Call {func: _, args: _, destination, ..} => {
-                    unsafe_locals.insert(destination.local);
-                },
-                _ => {
-                    panic!("Should be a call");
+    let mut flow = UnsafeFlow {
+        body: body,
+        unsafe_sources: unsafe_sources,
+        unsafe_bb: unsafe_bb,
+        mut_borrows: get_mut_borrows(body),
+        entry_states: FxHashMap::default(),
+        provenance: unsafe_args.clone(),
+    };
+    // Unsafe args are live at the entry of the function.
+    flow.entry_states.insert(START_BLOCK, unsafe_args);
+    let mut to_process = body.basic_blocks().indices().collect::<VecDeque<_>>();
+    let mut in_worklist = to_process.iter().copied().collect::<FxHashSet<_>>();
+
+    while let Some(bb) = to_process.pop_front() {
+        in_worklist.remove(&bb);
+        let bbd = &body.basic_blocks()[bb];
+        let mut state = flow.entry_state(bb);
+        let mut written = Vec::new();
+        for stmt in &bbd.statements {
+            for key in flow.transfer_stmt(bb, stmt, &mut state) {
+                if let Some(sources) = state.get(&key) {
+                    written.push((key, sources.clone()));
                 }
             }
         }
-    }
+        for key in flow.transfer_terminator(bb, &mut state) {
+            if let Some(sources) = state.get(&key) {
+                written.push((key, sources.clone()));
+            }
+        }
+        for (key, sources) in written {
+            add_provenance(&mut flow.provenance, key, &sources);
+        }
 
-    // Flow-insensitive data-flow analysis to find more unsafe places.
-    let mut change = true;
-    while change {
-        change = false;
-        for (_, bbd) in body.basic_blocks().iter_enumerated() {
-            for (_, stmt) in bbd.statements.iter().enumerate() {
-                match &stmt.kind {
-                    StatementKind::Assign(box (lhs_place, rvalue)) => {
-                        let mut place_in_rvalue = Vec::<Place<'tcx>>::new();
-                        get_place_in_rvalue(&rvalue, &mut place_in_rvalue);
-                        for place in place_in_rvalue {
-                            if unsafe_locals.contains(&place.local) {
-                                if unsafe_locals.insert(lhs_place.local) {
-                                    change = true;
-                                }
-                                break;
-                            }
-                        }
-                    },
-                    _ => {}
-                }
+        // Propagate the unsafe Place to successors.
+        for succ in bbd.terminator().successors() {
+            let succ_state = flow.entry_states.entry(succ).or_default();
+            let mut changed = false;
+            for (key, sources) in &state {
+                changed |= add_provenance(succ_state, *key, sources);
+            }
+            if changed && in_worklist.insert(succ) {
+                to_process.push_back(succ);
             }
         }
     }
     // Remove the return value Place.
-    unsafe_locals.remove(&Local::from_u32(0));
+    flow.provenance.retain(|key, _| key.local != RETURN_PLACE);
+
+    Ok(flow)
+}
+
+/// Get the unsafe Place in a state of UnsafeFlow.
+fn get_unsafe_places(state: &Provenance) -> UnsafePlaces {
+    let mut unsafe_places = UnsafePlaces::default();
+    for key in state.keys() {
+        unsafe_places.insert(*key);
+    }
+
+    unsafe_places
+}
 
-    unsafe_locals
+/// Check if a Deref on @base may be an unsafe memory access.
+///
+/// Dereferencing a safe reference (&T or &mut T) is not unsafe; only the
+/// dereference of a raw pointer is. References are treated the same as raw
+/// pointers only if -C sandbox-deref-refs is set.
+#[inline(always)]
+fn is_unsafe_deref<'tcx>(tcx: TyCtxt<'tcx>, body: &'tcx Body<'tcx>,
+                         base: PlaceRef<'tcx>) -> bool {
+    let ty = base.ty(body, tcx).ty;
+    return ty.is_unsafe_ptr() ||
+        (ty.is_ref() && tcx.sess.opts.cg.sandbox_deref_refs);
 }
 
 /// Check a Place to get the dereference to an unsafe Place, if there is one.
 ///
 /// Questions: It is true that a Place has at most one dereference?
-fn get_place_unsafe_deref<'tcx>(place: &Place<'tcx>,
+fn get_place_unsafe_deref<'tcx>(tcx: TyCtxt<'tcx>, body: &'tcx Body<'tcx>,
+                                place: &Place<'tcx>,
                                 stmt_unsafe_locals: &mut Vec<u32>,
-                                unsafe_locals: &FxHashSet<Local>,
+                                unsafe_places: &UnsafePlaces,
                                 deref_num: &mut u32) {
     let mut deref_in_place: u32 = 0;
-    for place_elem in place.projection {
+    let mut unsafe_deref = false;
+    for (base, place_elem) in place.iter_projections() {
         match place_elem {
-            ProjectionElem::Deref => { deref_in_place += 1;}
+            ProjectionElem::Deref => {
+                deref_in_place += 1;
+                unsafe_deref |= is_unsafe_deref(tcx, body, base);
+            }
             _ => {}
         }
     }
@@ -182,17 +521,165 @@ fn get_place_unsafe_deref<'tcx>(place: &Place<'tcx>,
     *deref_num += deref_in_place;
     assert!(deref_in_place < 2, "Place has multiple deref");
 
-    let local = place.local;
-    if unsafe_locals.contains(&local) {
-        stmt_unsafe_locals.push(local.as_u32());
+    if !unsafe_deref {
+        // Dereference of a safe reference.
+        return;
+    }
+
+    if unsafe_places.contains_place(place) {
+        stmt_unsafe_locals.push(place.local.as_u32());
+    }
+}
+
+/// Check if an Operand uses an unsafe Place.
+#[inline(always)]
+fn is_unsafe_operand<'tcx>(operand: &Operand<'tcx>,
+                           unsafe_places: &UnsafePlaces) -> bool {
+    match operand {
+        Operand::Copy(place) | Operand::Move(place) => {
+            unsafe_places.contains_place(place)
+        },
+        Operand::Constant(_) => false
+    }
+}
+
+/// Check if a pointer dereferenced at a location was most recently adjusted
+/// by pointer arithmetic with an unsafe offset.
+///
+/// This is a light backward inspection from the dereference to the last def
+/// of the pointer: it only follows the chain of single predecessors and the
+/// copies/casts of the pointer, and gives up on anything else.
+///
+/// Inputs:
+/// @body: The function body.
+/// @local: The Local of the dereferenced pointer.
+/// @location: Location of the dereference.
+/// @unsafe_places: All the unsafe Place of the function.
+fn is_arith_deref<'tcx>(body: &'tcx Body<'tcx>, local: Local, location: Location,
+                        unsafe_places: &UnsafePlaces) -> bool {
+    let mut target = local;
+    let mut bb = location.block;
+    let mut stmt_end = location.statement_index;
+    let mut visited = FxHashSet::<BasicBlock>::default();
+    while visited.insert(bb) {
+        let bbd = &body.basic_blocks()[bb];
+        for i in (0..stmt_end).rev() {
+            if let StatementKind::Assign(box (place, rvalue)) = &bbd.statements[i].kind {
+                if place.local != target || !place.projection.is_empty() {
+                    continue;
+                }
+                match rvalue {
+                    Rvalue::BinaryOp(BinOp::Offset, box (_, offset)) => {
+                        return is_unsafe_operand(offset, unsafe_places);
+                    },
+                    Rvalue::Use(Operand::Copy(src) | Operand::Move(src)) |
+                    Rvalue::Cast(_, Operand::Copy(src) | Operand::Move(src), _)
+                        if src.projection.is_empty() => {
+                        // Keep tracking the source of the copy.
+                        target = src.local;
+                    },
+                    _ => { return false; }
+                }
+            }
+        }
+
+        // Move to the only predecessor and examine its terminator.
+        let predecessors = &body.predecessors()[bb];
+        if predecessors.len() != 1 {
+            return false;
+        }
+        bb = predecessors[0];
+        let pbbd = &body.basic_blocks()[bb];
+        if let TerminatorKind::Call{func, args, ..} = &pbbd.terminator().kind {
+            if call_destination_local(pbbd.terminator(), body) == Some(target) {
+                if let Operand::Constant(f) = func {
+                    if is_ptr_arith_call(f) && args.len() == 2 {
+                        return is_unsafe_operand(&args[1], unsafe_places);
+                    }
+                }
+                return false;
+            }
+        }
+        stmt_end = pbbd.statements.len();
+    }
+
+    false
+}
+
+/// Get the AccessKind of an unsafe access.
+fn get_access_kind<'tcx>(body: &'tcx Body<'tcx>, access_locals: &Vec<u32>,
+                         location: Location,
+                         unsafe_places: &UnsafePlaces) -> AccessKind {
+    for local in access_locals {
+        if is_arith_deref(body, Local::from_u32(*local), location, unsafe_places) {
+            return AccessKind::ArithDeref;
+        }
     }
+
+    AccessKind::Deref
+}
+
+/// Check if a Place is a dereference of one of the unsafe Local.
+#[inline(always)]
+fn is_unsafe_deref_place<'tcx>(place: &Place<'tcx>, unsafe_locals: &Vec<u32>) -> bool {
+    place.is_indirect() && unsafe_locals.contains(&place.local.as_u32())
+}
+
+/// Get the unsafe Local that a Statement writes memory through, i.e., the
+/// dereferenced Local of the LHS of an Assign, or the destination pointer of a
+/// CopyNonOverlapping, if it is unsafe.
+fn get_written_in_stmt<'tcx>(stmt: &Statement<'tcx>, unsafe_locals: &Vec<u32>) -> Vec<u32> {
+    let dst = match &stmt.kind {
+        StatementKind::Assign(box (place, _)) => {
+            Some(place).filter(|place| is_unsafe_deref_place(place, unsafe_locals))
+        },
+        StatementKind::CopyNonOverlapping(box cno) => {
+            match &cno.dst {
+                Operand::Copy(dst) | Operand::Move(dst) => {
+                    Some(dst).filter(|dst| unsafe_locals.contains(&dst.local.as_u32()))
+                },
+                Operand::Constant(_) => None
+            }
+        },
+        _ => None
+    };
+
+    dst.into_iter().map(|place| place.local.as_u32()).collect()
+}
+
+/// Get the unsafe Local that a Terminator writes memory through, i.e., the
+/// dereferenced Local of the destination of a Call or of each output of inline
+/// assembly, if it is unsafe.
+fn get_written_in_terminator<'tcx>(terminator: &Terminator<'tcx>, body: &Body<'tcx>,
+                                   unsafe_locals: &Vec<u32>) -> Vec<u32> {
+    let dsts = match &terminator.kind {
+        TerminatorKind::Call{destination, ..} => {
+            if call_destination_local(terminator, body).is_some() {
+                vec![destination]
+            } else {
+                vec![]
+            }
+        },
+        TerminatorKind::InlineAsm{operands, ..} => {
+            operands.iter().filter_map(|operand| match operand {
+                InlineAsmOperand::Out{place: Some(place), ..} |
+                    InlineAsmOperand::InOut{out_place: Some(place), ..} => Some(place),
+                _ => None
+            }).collect()
+        },
+        _ => vec![]
+    };
+
+    dsts.into_iter().filter(|place| is_unsafe_deref_place(place, unsafe_locals))
+        .map(|place| place.local.as_u32()).collect()
 }
 
 /// Examine each statement and terminator to find unsafe memory accesses.
-/// An unsafe memory access is defined as a dereference to an unsafe Place.
-fn find_unsafe_accesses<'tcx>(unsafe_locals: FxHashSet<Local>, fn_id: FnID,
-                              body: &'tcx Body<'tcx>, total_deref: &mut u32)
-                              -> UnsafeAccesses {
+/// An unsafe memory access is defined as a dereference to a Place that is
+/// unsafe at that statement or terminator.
+fn find_unsafe_accesses<'tcx>(tcx: TyCtxt<'tcx>, flow: &UnsafeFlow<'_, 'tcx>,
+                              fn_id: FnID, body: &'tcx Body<'tcx>,
+                              total_deref: &mut u32) -> UnsafeAccesses {
     // Result.
     let mut unsafe_accesses = Vec::<UnsafeAccess>::new();
 
@@ -200,40 +687,67 @@ fn find_unsafe_accesses<'tcx>(unsafe_locals: FxHashSet<Local>, fn_id: FnID,
     let mut deref_num: u32 = 0;
 
     for (bb, bbd) in body.basic_blocks().iter_enumerated() {
+        // Unsafe Place before each statement and the terminator.
+        let mut state = flow.entry_state(bb);
         for (i, stmt) in bbd.statements.iter().enumerate() {
             // Handle a Statement.
+            let unsafe_places = get_unsafe_places(&state);
+            flow.transfer_stmt(bb, stmt, &mut state);
             let mut places = Vec::new();
             get_place_in_stmt(stmt, &mut places);
             let mut stmt_unsafe_locals = Vec::new();
             for place in &places {
-                get_place_unsafe_deref(place, &mut stmt_unsafe_locals,
-                                       &unsafe_locals, &mut deref_num)
+                get_place_unsafe_deref(tcx, body, place, &mut stmt_unsafe_locals,
+                                       &unsafe_places, &mut deref_num)
             }
             if !stmt_unsafe_locals.is_empty() {
+                let location = Location { block: bb, statement_index: i };
+                let kind = get_access_kind(body, &stmt_unsafe_locals, location,
+                                           &unsafe_places);
+                let written = get_written_in_stmt(stmt, &stmt_unsafe_locals);
+                let (span, macro_name) = get_macro_call_site(stmt.source_info.span);
                 let unsafe_access = UnsafeAccess {
                     _bb: bb.as_u32(),
                     _index: i as u32,
                     _is_terminator: false,
                     locals: stmt_unsafe_locals,
+                    kind: kind,
+                    written: written,
+                    span: span,
+                    loc: SourceLoc::new(tcx, span),
+                    macro_name: macro_name,
                 };
                 unsafe_accesses.push(unsafe_access);
             }
         }
 
         // Handle Terminator
+        let unsafe_places = get_unsafe_places(&state);
         let mut places = Vec::new();
         get_place_in_terminator(body, &bbd.terminator(), &mut places);
         let mut term_unsafe_locals = Vec::new();
         for place in &places {
-            get_place_unsafe_deref(place, &mut term_unsafe_locals,
-                                   &unsafe_locals, &mut deref_num);
+            get_place_unsafe_deref(tcx, body, place, &mut term_unsafe_locals,
+                                   &unsafe_places, &mut deref_num);
         }
         if !term_unsafe_locals.is_empty() {
+            let location = Location { block: bb, statement_index: bbd.statements.len() };
+            let kind = get_access_kind(body, &term_unsafe_locals, location,
+                                       &unsafe_places);
+            let written = get_written_in_terminator(bbd.terminator(), body,
+                                                    &term_unsafe_locals);
+            let (span, macro_name) =
+                get_macro_call_site(bbd.terminator().source_info.span);
             let unsafe_access = UnsafeAccess {
                 _bb: bb.as_u32(),
                 _index: bbd.statements.len() as u32,
                 _is_terminator: true,
-                locals: Vec::new()
+                locals: term_unsafe_locals,
+                kind: kind,
+                written: written,
+                span: span,
+                loc: SourceLoc::new(tcx, span),
+                macro_name: macro_name,
             };
             unsafe_accesses.push(unsafe_access);
         }
@@ -244,12 +758,20 @@ fn find_unsafe_accesses<'tcx>(unsafe_locals: FxHashSet<Local>, fn_id: FnID,
     (fn_id, unsafe_accesses)
 }
 
-/// Count the total number of unsafe accesses in the whole crate.
-pub fn unsafe_access_num(unsafe_accesses_all: &Vec::<UnsafeAccesses>) -> u32 {
+/// Count the total number of unsafe accesses in the whole crate, or only
+/// the writes through an unsafe pointer if @writes_only is true. A
+/// Statement/Terminator that writes may also read, e.g., "*p = *q", and only
+/// its writes are counted as such.
+pub fn unsafe_access_num(unsafe_accesses_all: &Vec::<UnsafeAccesses>,
+                         writes_only: bool) -> u32 {
     let mut unsafe_deref_num = 0;
     for unsafe_accesses in unsafe_accesses_all {
         for unsafe_access in &unsafe_accesses.1 {
-            unsafe_deref_num += unsafe_access.locals.len();
+            if writes_only {
+                unsafe_deref_num += unsafe_access.written.len();
+            } else {
+                unsafe_deref_num += unsafe_access.locals.len();
+            }
         }
     }
 
@@ -278,22 +800,181 @@ fn count_access_in_fn<'tcx>(body: &'tcx Body<'tcx>, total_deref: &mut u32) {
     *total_deref += access_num;
 }
 
-/// Write the analysis result to file.
-pub fn write_result(unsafe_access: u32, total_deref: u32) {
+/// Write the analysis result to file, e.g., "crate: 3/10 (writes: 1)" for 3
+/// unsafe accesses, 1 of which is a write, out of 10 dereferences.
+pub fn write_result(unsafe_access: u32, unsafe_write: u32,
+                    total_deref: u32) -> SandboxResult<()> {
     let file_path = get_deref_result_path();
     if !Path::new(&file_path).exists() {
         // Create a new file if not existed.
-        fs::File::create(&file_path).expect("Create a file for result");
+        fs::File::create(&file_path)?;
     }
 
     let result = get_local_crate_name() + ": " + &unsafe_access.to_string() +
-        "/" + &total_deref.to_string() + "\n";
+        "/" + &total_deref.to_string() + " (writes: " + &unsafe_write.to_string() +
+        ")\n";
+    let mut file = fs::OpenOptions::new()
+        .append(true)
+        .open(file_path)?;
+    file.write_all(result.as_bytes())?;
+
+    Ok(())
+}
+
+/// Get a key of an unsafe access that is stable across compilation sessions:
+/// the DefPathHash of the function followed by a hash of the source code of
+/// the access and the number of earlier accesses in the function with the same
+/// source code. Unlike BB indices and line numbers, the key does not change
+/// when an unrelated part of the function (or the file) is edited.
+///
+/// Inputs:
+/// @fn_id: The function of the access.
+/// @span: The user-visible source location of the access.
+/// @snippet_nums: Number of the accesses of each source code so far in the fn.
+fn get_stable_key<'tcx>(tcx: TyCtxt<'tcx>, fn_id: FnID, span: Span,
+                        snippet_nums: &mut FxHashMap<String, u32>) -> String {
+    let snippet = tcx.sess.source_map().span_to_snippet(span).unwrap_or_default();
+    let num = snippet_nums.entry(snippet.clone()).or_insert(0);
+    let mut hasher = StableHasher::new();
+    snippet.hash(&mut hasher);
+    num.hash(&mut hasher);
+    *num += 1;
+    let hash: Hash64 = hasher.finish();
+
+    format!("{}:{:016x}", fn_id.to_hex(), hash.as_u64())
+}
+
+/// Write the source location of each unsafe access to file. An access from
+/// a macro expansion is reported at the macro's call site together with the
+/// macro's name, e.g., "src/lib.rs:10:5 (in macro `my_unsafe!`)". An
+/// ArithDeref access is further marked with "(arith deref)", and a write with
+/// "(write)". With
+/// SANDBOX_STABLE_KEYS, each access ends with "#" and its stable key.
+pub fn write_access_sites<'tcx>(tcx: TyCtxt<'tcx>,
+                                unsafe_accesses_all: &Vec::<UnsafeAccesses>)
+    -> SandboxResult<()> {
+    let mut result = String::new();
+    let stable_keys = stable_keys_enabled();
+    for unsafe_accesses in unsafe_accesses_all {
+        let mut snippet_nums = FxHashMap::<String, u32>::default();
+        for unsafe_access in &unsafe_accesses.1 {
+            result += &(get_local_crate_name() + ": " +
+                &tcx.sess.source_map().span_to_diagnostic_string(unsafe_access.span));
+            if let Some(macro_name) = &unsafe_access.macro_name {
+                result += &(" (in macro `".to_owned() + macro_name + "!`)");
+            }
+            if unsafe_access.kind == AccessKind::ArithDeref {
+                result += " (arith deref)";
+            }
+            if unsafe_access.is_write() {
+                result += " (write)";
+            }
+            if stable_keys {
+                result += &(" #".to_owned() + &get_stable_key(tcx, unsafe_accesses.0,
+                    unsafe_access.span, &mut snippet_nums));
+            }
+            result += "\n";
+        }
+    }
+
+    let mut file = fs::OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(get_access_sites_path())?;
+    file.write_all(result.as_bytes())?;
+
+    Ok(())
+}
+
+/// An unsafe access in the form to serialize; see write_access_locs().
+#[derive(Serialize)]
+struct AccessLoc<'a> {
+    crate_name: &'a str,
+    fn_id: FnID,
+    #[serde(flatten)]
+    loc: &'a SourceLoc,
+    kind: AccessKind,
+    is_write: bool,
+    macro_name: &'a Option<String>,
+}
+
+/// Write the source location of each unsafe access to file for other tools,
+/// one JSON object per line, e.g.,
+/// {"crate_name":"main","fn_id":[..],"file":"src/main.rs","line":9,"col":22,
+///  "kind":"Deref","is_write":false,"macro_name":null}.
+/// Like write_access_sites(), each crate appends its accesses to the file.
+pub fn write_access_locs(unsafe_accesses_all: &Vec::<UnsafeAccesses>) -> SandboxResult<()> {
+    let crate_name = get_local_crate_name();
+    let mut result = String::new();
+    for (fn_id, unsafe_accesses) in unsafe_accesses_all {
+        for unsafe_access in unsafe_accesses {
+            let access_loc = AccessLoc {
+                crate_name: &crate_name,
+                fn_id: *fn_id,
+                loc: &unsafe_access.loc,
+                kind: unsafe_access.kind,
+                is_write: unsafe_access.is_write(),
+                macro_name: &unsafe_access.macro_name,
+            };
+            result += &serde_json::to_string(&access_loc)?;
+            result += "\n";
+        }
+    }
+
     let mut file = fs::OpenOptions::new()
+        .create(true)
         .append(true)
-        .open(file_path)
-        .expect("Open the result file");
-    file.write_all(result.as_bytes()).expect(
-        "Write the unsafe_deref / total_deref result to file");
+        .open(get_access_locs_path())?;
+    file.write_all(result.as_bytes())?;
+
+    Ok(())
+}
+
+/// Provenance of the unsafe Local of a function, in the form to serialize.
+#[derive(Serialize)]
+struct FnProvenance {
+    fn_id: FnID,
+    fn_name: String,
+    crate_name: String,
+    /// Each unsafe Local, its type name, and the unsafe sources that seed its
+    /// taint, including those of its fields. The type name is bounded by
+    /// short_type_name().
+    locals: Vec<(u32, String, Vec<DefSite>)>,
+}
+
+/// Write the provenance of the unsafe Local of a function to a JSON file in
+/// @dir. This is for debugging and for tools that need the whole chain from an
+/// unsafe source to an unsafe access.
+fn write_provenance<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId, fn_id: FnID,
+                          body: &Body<'tcx>, provenance: &Provenance,
+                          dir: &str) -> SandboxResult<()> {
+    let mut local_sources = FxHashMap::<Local, FxHashSet<DefSite>>::default();
+    for (key, sources) in provenance {
+        local_sources.entry(key.local).or_default().extend(sources.iter().copied());
+    }
+    let mut locals = Vec::new();
+    for (local, sources) in &local_sources {
+        let mut sources = sources.iter().copied().collect::<Vec<_>>();
+        sources.sort();
+        let ty_name = short_type_name(tcx, body.local_decls[*local].ty,
+                                      MAX_TYPE_NAME_DEPTH);
+        locals.push((local.as_u32(), ty_name, sources));
+    }
+    locals.sort_by_key(|(local, _, _)| *local);
+
+    let fn_provenance = FnProvenance {
+        fn_id: fn_id,
+        fn_name: get_fn_name(def_id),
+        crate_name: get_crate_name(def_id),
+        locals: locals,
+    };
+    let _ = fs::create_dir_all(dir);
+    let file_path = format!("{}/{}-{}-{}.json", dir, fn_provenance.crate_name,
+        fn_provenance.fn_name, fn_id.to_hex());
+    let serialized = serde_json::to_string(&fn_provenance)?;
+    fs::write(file_path, serialized)?;
+
+    Ok(())
 }
 
 /// Entrance of this module.
@@ -301,30 +982,33 @@ pub fn write_result(unsafe_access: u32, total_deref: u32) {
 /// Local analysis to find unsafe memory accesses. It uses the three types of
 /// unsafe sources (arg, heap-alloc call, and non-heap-alloc call) from
 /// previous whole-program analysis.
-pub fn analyze<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId,
+pub fn analyze<'tcx>(cx: &mut AnalysisCtxt<'tcx>, def_id: DefId,
                      unsafe_sources_all: &WPSummary,
                      unsafe_accesses_all: &mut Vec::<UnsafeAccesses>,
-                     total_deref: &mut u32) {
-    if ignore_fn(tcx, def_id) {
-        return;
-    }
-
-    let fn_id = get_fn_fingerprint(tcx, def_id);
-    let body = tcx.optimized_mir(def_id);
+                     total_deref: &mut u32) -> SandboxResult<()> {
+    let Some(AnalysisFn { fn_id, body }) = cx.get(def_id) else {
+        return Ok(());
+    };
+    let tcx = cx.tcx();
     let unsafe_sources = unsafe_sources_all.get(&fn_id);
     if unsafe_sources.is_none() {
         // This function does not have any unsafe resources. We just count its
         // memory dereferences.
         count_access_in_fn(body, total_deref);
-        return;
+        return Ok(());
     }
 
-    // Collect all unsafe Place (represented in Local) based on unsafe sources.
-    let unsafe_locals = collect_unsafe_locals(unsafe_sources.unwrap(), &body);
+    // Collect all unsafe Place (a Local or a field of it) based on unsafe
+    // sources.
+    let flow = collect_unsafe_provenance(unsafe_sources.unwrap(), &body)?;
+    if let Some(dir) = get_provenance_dir() {
+        write_provenance(tcx, def_id, fn_id, &body, &flow.provenance, &dir)?;
+    }
 
     // Find all unsafe accesses.
-    let unsafe_accesses = find_unsafe_accesses(unsafe_locals, fn_id, &body,
-                                               total_deref);
+    let unsafe_accesses = find_unsafe_accesses(tcx, &flow, fn_id, &body, total_deref);
 
     unsafe_accesses_all.push(unsafe_accesses);
+
+    Ok(())
 }
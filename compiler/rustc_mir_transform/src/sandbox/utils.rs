@@ -1,15 +1,27 @@
 //! Library functions for the sandboxing unsafe code module.
 
 use rustc_middle::mir::*;
+use rustc_middle::mir::interpret::{GlobalAlloc, Scalar};
 use rustc_middle::ty::{self, TyCtxt, Ty};
-use rustc_hir::def_id::{DefId,DefIndex,CrateNum,LOCAL_CRATE};
-use rustc_data_structures::fx::{FxHashSet};
+use rustc_index::IndexVec;
+use rustc_target::abi::FieldIdx;
+use rustc_hir::def_id::{CrateNum,DefId,DefPathHash,LOCAL_CRATE};
+use rustc_hir::definitions::DefPathData;
+use rustc_hir::{BlockCheckMode, Node, UnsafeSource, Unsafety};
+use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::fingerprint::Fingerprint;
+use rustc_data_structures::stable_hasher::StableHasher;
 use rustc_span::symbol::{sym};
-use nix::unistd::getppid;
+use rustc_span::{Span, ExpnKind, MacroKind};
+use std::hash::Hash;
 
 use super::database::*;
+use super::error::{SandboxError, SandboxResult};
 use super::debug::*;
-use super::summarize_fn::{DefSite,FnID};
+use super::summarize_fn::{DefSite,FnID,PtrMutability,SerDefId};
+
+// For debugging purpose.
+static _DEBUG: bool = false;
 
 #[inline(always)]
 pub(crate) fn get_crate_name(def_id: DefId) -> String {
@@ -28,33 +40,137 @@ pub(crate) fn get_fn_name(def_id: DefId) -> String {
     })
 }
 
-/// Get the name of the currently compiled crate.
-#[inline(always)]
-pub(crate) fn get_local_crate_name() -> String {
+/// Get the full path of a function, i.e., its crate, modules and name, e.g.,
+/// "alloc::alloc::alloc" for std::alloc::alloc. Re-exports are not followed.
+/// The impl of a method is replaced by the name of its self type if it is an
+/// ADT, e.g., "alloc::alloc::Global::allocate".
+pub(crate) fn get_fn_path(def_id: DefId) -> String {
     ty::tls::with(|tcx| {
-        return tcx.crate_name(LOCAL_CRATE).to_ident_string();
+        let mut path = vec![tcx.crate_name(def_id.krate).to_string()];
+        for data in tcx.def_path(def_id).data {
+            match data.data {
+                DefPathData::Impl => {
+                    let self_ty = tcx.impl_of_method(def_id)
+                        .and_then(|impl_did| tcx.type_of(impl_did).skip_binder().ty_adt_def())
+                        .map_or(data.data.to_string(), |adt| tcx.item_name(adt.did()).to_string());
+                    path.push(self_ty);
+                },
+                _ => path.push(data.data.to_string()),
+            }
+        }
+        path.join("::")
     })
 }
 
-/// A helper function that filters out uninterested functions.
-#[allow(dead_code)]
-pub(crate) fn ignore_fn_dev<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> bool {
-    if ignore_fn(tcx, def_id) {
-        return true;
+/// Check if a function is a closure written in an unsafe block or in an
+/// unsafe fn. The body of such a closure is built as safe code, although
+/// the closure is in an unsafe context, e.g., it may dereference raw pointers.
+pub(crate) fn is_closure_in_unsafe(def_id: DefId) -> bool {
+    ty::tls::with(|tcx| {
+        let Some(local_id) = def_id.as_local() else { return false };
+        if !tcx.is_closure(def_id) {
+            return false;
+        }
+
+        let hir = tcx.hir();
+        for (_, node) in hir.parent_iter(hir.local_def_id_to_hir_id(local_id)) {
+            match node {
+                Node::Block(block) => {
+                    if block.rules == BlockCheckMode::UnsafeBlock(UnsafeSource::UserProvided) {
+                        return true;
+                    }
+                },
+                Node::Item(_) | Node::ImplItem(_) | Node::TraitItem(_) => {
+                    // Reached the item that the closure is defined in.
+                    return node.fn_sig()
+                        .is_some_and(|sig| sig.header.unsafety == Unsafety::Unsafe);
+                },
+                _ => {}
+            }
+        }
+
+        false
+    })
+}
+
+/// Get the full path of the function called via a Constant. The FnDef of a
+/// call to a trait method is the method of the trait, so a trait method is
+/// named after the self type if it is an ADT, e.g., "alloc::alloc::Global::allocate"
+/// for "Allocator::allocate" on Global.
+pub(crate) fn get_callee_path<'tcx>(f: &Constant<'tcx>) -> Option<String> {
+    if let ty::FnDef(def_id, substs) = *f.literal.ty().kind() {
+        let self_adt = ty::tls::with(|tcx| tcx.trait_of_item(def_id))
+            .and_then(|_| substs.types().next())
+            .and_then(|self_ty| self_ty.ty_adt_def());
+        return Some(match self_adt {
+            Some(adt) => get_fn_path(adt.did()) + "::" + &get_fn_name(def_id),
+            None => get_fn_path(def_id),
+        });
     }
 
-    let name = tcx.opt_item_name(def_id);
-    if name.is_none() {
+    None
+}
+
+/// Check if a function is a method of a type known to allocate heap memory,
+/// i.e., Box, String, or one of ALLOCATOR_TYPES. A method named like an
+/// allocation in HEAP_ALLOC, e.g., "new", of any other type is not one. An
+/// allocation added by SURUST_DB (see ExtraDatabase) is always known.
+pub(crate) fn is_known_allocator<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> bool {
+    if EXTRA_DB.heap_alloc.contains(&get_fn_name(def_id)) {
         return true;
     }
 
-    let name = name.unwrap();
-    // The second condition is for debugging and development only.
-    if name.is_empty() || !FN_TO_PROCESS.contains(&name.to_ident_string()) {
-        return true;
+    let adt = tcx.impl_of_method(def_id)
+        .and_then(|impl_did| tcx.type_of(impl_did).skip_binder().ty_adt_def());
+    match adt {
+        Some(adt) => {
+            adt.is_box() || tcx.lang_items().string() == Some(adt.did()) ||
+                tcx.get_diagnostic_name(adt.did())
+                    .is_some_and(|name| ALLOCATOR_TYPES.contains(&name))
+        },
+        None => false
     }
+}
 
-    return false;
+/// Check if a function is an allocation method of an allocator trait in
+/// ALLOCATOR_TRAITS, or of an impl of one. MIR calls a trait method by the
+/// DefId of the method in the trait, so the types implementing it, e.g., a
+/// custom #[global_allocator], need not be known.
+pub(crate) fn is_allocator_trait_fn<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> bool {
+    if !ALLOCATOR_TRAIT_FNS.contains(&get_fn_name(def_id)) {
+        return false;
+    }
+
+    tcx.trait_of_item(def_id)
+        .or_else(|| tcx.impl_of_method(def_id)
+                 .and_then(|impl_did| tcx.trait_id_of_impl(impl_did)))
+        .and_then(|trait_id| tcx.get_diagnostic_name(trait_id))
+        .is_some_and(|name| ALLOCATOR_TRAITS.contains(&name))
+}
+
+/// Check if a call is to a deallocation function of the global allocator API.
+pub(crate) fn is_global_dealloc<'tcx>(f: &Constant<'tcx>) -> bool {
+    return get_callee_path(f).is_some_and(|path| GLOBAL_DEALLOC.contains(&path));
+}
+
+/// Check if a function frees the memory its first argument points to, i.e.,
+/// one of FREE_FNS by its full path, or a native one of FOREIGN_FREE_FNS by
+/// its name. A function only named like one, e.g., a "free" method of a pool,
+/// is not.
+pub(crate) fn is_free_fn<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> bool {
+    if is_ffi_decl(tcx, def_id) {
+        return FOREIGN_FREE_FNS.contains(&get_fn_name(def_id));
+    }
+
+    return FREE_FNS.contains(&get_fn_path(def_id));
+}
+
+/// Get the name of the currently compiled crate.
+#[inline(always)]
+pub(crate) fn get_local_crate_name() -> String {
+    ty::tls::with(|tcx| {
+        return tcx.crate_name(LOCAL_CRATE).to_ident_string();
+    })
 }
 
 /// Checks if a fn is a compiler builtin or from the native libraries such as
@@ -63,27 +179,59 @@ pub(crate) fn ignore_fn_dev<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> bool {
 /// Question: Do we need exclude all the crates in "rust/library"?
 #[inline(always)]
 pub(crate) fn ignore_fn<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> bool {
+    return ignore_crate(tcx, def_id.krate) || ignore_fn_item(tcx, def_id);
+}
+
+/// Checks if all the fns of a crate are ignored, i.e., it is the compiler
+/// builtins, a native library, or a build script. Unlike ignore_fn_item(),
+/// the result is the same for every fn of the crate and so can be cached.
+pub(crate) fn ignore_crate<'tcx>(tcx: TyCtxt<'tcx>, krate: CrateNum) -> bool {
     // Ignore compiler builtins.
-    if tcx.is_compiler_builtins(def_id.krate) {
+    if tcx.is_compiler_builtins(krate) {
         return true;
     }
 
+    return ignore_crate_name(&tcx.crate_name(krate).to_ident_string());
+}
+
+/// Checks if a crate is ignored by its name, i.e., it is one of the standard
+/// and builtin libraries, or a build script.
+pub(crate) fn ignore_crate_name(name: &str) -> bool {
     // Ignore standard and builtin libraries.
-    let crate_name = get_crate_name(def_id);
-    if NATIVE_LIBS.contains(&crate_name) { return true; }
+    if NATIVE_LIBS.contains(name) { return true; }
 
-    // Ignore functions without a name.
+    // Ignore main() from build_script_build
+    return ignore_build_crate(name);
+}
+
+/// Checks if a fn is ignored by itself, regardless of its crate.
+pub(crate) fn ignore_fn_item<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> bool {
+    // Ignore functions without a name except closures, which may be called
+    // via Fn trait objects.
     // Jie Zhou: What are these functions exactly?
     let fn_name = tcx.opt_item_name(def_id);
-    if fn_name.is_none() { return true; }
-    if fn_name.unwrap().is_empty() { return true; }
+    if fn_name.is_none() && !tcx.is_closure(def_id) { return true; }
+    if fn_name.is_some_and(|name| name.is_empty()) { return true; }
 
-    // Ignore main() from build_script_build
-    if ignore_build_crate(&crate_name) { return true; }
+    // Only analyze the functions listed for debugging, if any. Closures have
+    // no name to list and are always kept.
+    if let Some(name) = fn_name && ignore_fn_name(name.as_str()) { return true; }
 
     return false;
 }
 
+/// Checks if a fn is left out by its name for debugging, i.e., SURUST_ONLY_FNS
+/// lists the functions to analyze and does not include it; see FN_TO_PROCESS.
+pub(crate) fn ignore_fn_name(name: &str) -> bool {
+    return ignore_fn_name_in(name, &FN_TO_PROCESS);
+}
+
+/// Checks if a fn is left out by its name given the functions to analyze. An
+/// empty list leaves out nothing.
+pub(crate) fn ignore_fn_name_in(name: &str, fns: &FxHashSet<String>) -> bool {
+    return !fns.is_empty() && !fns.contains(name);
+}
+
 /// Ignore crates from build.rs.
 /// Any others?
 #[inline(always)]
@@ -147,9 +295,8 @@ pub(crate) fn get_place_in_rvalue<'tcx>(rvalue: &Rvalue<'tcx>,
             places.push(*place);
         },
         Rvalue::Aggregate(_, operands) => {
-            // Do we need to collect each field? Or should we just find out the
-            // single allocation site for the whole aggregate, if that is
-            // represented in MIR?
+            // All the fields. Callers that track some fields of a Local only
+            // take the operands of those fields; see get_field_operands().
             for operand in operands {
                 get_place_in_operand(operand, places);
             }
@@ -157,7 +304,31 @@ pub(crate) fn get_place_in_rvalue<'tcx>(rvalue: &Rvalue<'tcx>,
         Rvalue::ShallowInitBox(operand, _) => {
             get_place_in_operand(operand, places);
         },
-        _ => {}
+        Rvalue::CopyForDeref(place) => {
+            // E.g., "_5 = deref_copy (*_4)" generated for "**a", where a is
+            // bound by destructuring "let [a, ..] = &ptrs;".
+            places.push(*place);
+        },
+        Rvalue::NullaryOp(..) => {
+            // SizeOf, AlignOf and OffsetOf of a type have no operand, so there
+            // is no Place.
+        },
+    }
+}
+
+/// Get the Place(s) in a Rvalue that the value of the Rvalue derives from.
+///
+/// This is the same as get_place_in_rvalue() except for pointer arithmetic.
+/// The result of "_3 = Offset(_1, _2)" is a pointer into the same object as
+/// the pointer _1, so only _1 is collected; the offset _2 does not decide
+/// which object _3 points to.
+pub(crate) fn get_source_place_in_rvalue<'tcx>(rvalue: &Rvalue<'tcx>,
+                                               places: &mut Vec<Place<'tcx>>) {
+    match rvalue {
+        Rvalue::BinaryOp(BinOp::Offset, box (ref ptr, _)) => {
+            get_place_in_operand(ptr, places);
+        },
+        _ => { get_place_in_rvalue(rvalue, places); }
     }
 }
 
@@ -167,7 +338,7 @@ pub(crate) fn get_place_in_rvalue<'tcx>(rvalue: &Rvalue<'tcx>,
 /// @args: The args of a TerminatorKind::Call.
 /// @locals: Destination for the Local of Place in @args.
 #[inline(always)]
-pub(crate) fn get_local_in_args<'tcx>(args: &Vec<Operand<'tcx>>,
+pub(crate) fn get_local_in_args<'tcx>(args: &[Operand<'tcx>],
                                       locals: &mut FxHashSet<Local>) {
     let mut places = Vec::<Place<'tcx>>::with_capacity(args.len());
     args.iter().for_each(|arg| get_place_in_operand(arg, &mut places));
@@ -187,6 +358,130 @@ pub(crate) fn get_local_in_rvalue<'tcx>(rvalue: &Rvalue<'tcx>,
     for place in places { locals.insert(place.local); }
 }
 
+/// Get the operand of each field of a struct, tuple or closure built by an
+/// Rvalue::Aggregate, indexed by the field. Arrays and unions are not split
+/// into fields.
+pub(crate) fn get_field_operands<'a, 'tcx>(rvalue: &'a Rvalue<'tcx>)
+    -> Option<&'a IndexVec<FieldIdx, Operand<'tcx>>> {
+    match rvalue {
+        Rvalue::Aggregate(box AggregateKind::Adt(_, _, _, _, None), operands) |
+        Rvalue::Aggregate(box AggregateKind::Tuple, operands) |
+        Rvalue::Aggregate(box AggregateKind::Closure(..), operands) => Some(operands),
+        _ => None
+    }
+}
+
+/// A Local, or one field of it for a Place whose first projection is a Field,
+/// e.g., _3.0 in "(*(_3.0: *mut u8))". Tracking unsafe Place by PlaceKey
+/// instead of by Local distinguishes the fields of a struct or tuple, so that
+/// touching one field unsafely does not taint the other fields.
+#[derive(Copy, Clone, PartialEq, Eq, Hash, Debug)]
+pub(crate) struct PlaceKey {
+    pub(crate) local: Local,
+    /// Index of the leading field, or None for the whole Local.
+    pub(crate) field: Option<u32>,
+}
+
+impl PlaceKey {
+    pub(crate) fn whole(local: Local) -> PlaceKey {
+        PlaceKey { local: local, field: None }
+    }
+
+    pub(crate) fn field(local: Local, field: FieldIdx) -> PlaceKey {
+        PlaceKey { local: local, field: Some(field.as_u32()) }
+    }
+
+    pub(crate) fn of<'tcx>(place: &Place<'tcx>) -> PlaceKey {
+        match place.projection.first() {
+            Some(ProjectionElem::Field(field, _)) => PlaceKey::field(place.local, *field),
+            _ => PlaceKey::whole(place.local)
+        }
+    }
+
+    /// Check if two keys may refer to the same memory.
+    pub(crate) fn overlaps(&self, other: &PlaceKey) -> bool {
+        self.local == other.local &&
+            (self.field.is_none() || other.field.is_none() || self.field == other.field)
+    }
+}
+
+/// The unsafe part of a Local in UnsafePlaces.
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub(crate) enum LocalPart {
+    Whole,
+    Fields(FxHashSet<u32>),
+}
+
+/// A set of unsafe Place, each of which is a whole Local or some fields of it.
+#[derive(Clone, Default, Debug)]
+pub(crate) struct UnsafePlaces(FxHashMap<Local, LocalPart>);
+
+impl UnsafePlaces {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub(crate) fn insert(&mut self, key: PlaceKey) {
+        match (self.0.get_mut(&key.local), key.field) {
+            (Some(LocalPart::Whole), _) => {},
+            (Some(LocalPart::Fields(fields)), Some(field)) => { fields.insert(field); },
+            (_, None) => { self.0.insert(key.local, LocalPart::Whole); },
+            (None, Some(field)) => {
+                self.0.insert(key.local, LocalPart::Fields([field].into_iter().collect()));
+            },
+        }
+    }
+
+    pub(crate) fn insert_place<'tcx>(&mut self, place: &Place<'tcx>) {
+        self.insert(PlaceKey::of(place));
+    }
+
+    /// Insert the whole of each Local.
+    pub(crate) fn insert_locals(&mut self, locals: FxHashSet<Local>) {
+        for local in locals {
+            self.insert(PlaceKey::whole(local));
+        }
+    }
+
+    /// Check if any part of @local is unsafe.
+    pub(crate) fn contains_local(&self, local: &Local) -> bool {
+        self.0.contains_key(local)
+    }
+
+    /// Check if a Place may refer to an unsafe Place.
+    pub(crate) fn contains_place<'tcx>(&self, place: &Place<'tcx>) -> bool {
+        let key = PlaceKey::of(place);
+        match (self.0.get(&key.local), key.field) {
+            (None, _) => false,
+            (Some(LocalPart::Whole), _) | (Some(LocalPart::Fields(_)), None) => true,
+            (Some(LocalPart::Fields(fields)), Some(field)) => fields.contains(&field),
+        }
+    }
+
+    pub(crate) fn get(&self, local: &Local) -> Option<&LocalPart> {
+        self.0.get(local)
+    }
+
+    /// Remove all the parts of @local. Return true if any part was unsafe.
+    pub(crate) fn remove_local(&mut self, local: &Local) -> bool {
+        self.0.remove(local).is_some()
+    }
+
+    /// Remove one field of @local that is unsafe by itself. Return true if it
+    /// was unsafe.
+    pub(crate) fn remove_field(&mut self, local: &Local, field: u32) -> bool {
+        if let Some(LocalPart::Fields(fields)) = self.0.get_mut(local) {
+            let removed = fields.remove(&field);
+            if fields.is_empty() {
+                self.0.remove(local);
+            }
+            return removed;
+        }
+
+        false
+    }
+}
+
 
 /// Check if a type is the empty type, i.e., '()'.
 pub(crate) fn is_empty_ty<'tcx>(t: Ty<'tcx>) -> bool {
@@ -197,6 +492,203 @@ pub(crate) fn is_empty_ty<'tcx>(t: Ty<'tcx>) -> bool {
     return false;
 }
 
+/// Max nesting of generic arguments printed by short_type_name().
+pub(crate) const MAX_TYPE_NAME_DEPTH: usize = 3;
+
+/// Get a bounded, human-readable name of a type for reports.
+///
+/// ADTs are printed by their item name only, without defaulted generic
+/// arguments, and generic arguments nested deeper than @max_depth are replaced
+/// by "…", e.g., "Vec<Vec<…>>" for Vec<Vec<Vec<u8>>> with @max_depth 1.
+/// Without the bound, a recursive or deeply nested type makes the name (and
+/// the time to build it) blow up.
+pub(crate) fn short_type_name<'tcx>(tcx: TyCtxt<'tcx>, t: Ty<'tcx>,
+                                    max_depth: usize) -> String {
+    match t.kind() {
+        ty::Adt(adt_def, args) => {
+            let name = tcx.item_name(adt_def.did()).to_ident_string();
+            // Skip defaulted arguments such as the allocator of Vec, as the
+            // rustc type printer does.
+            let tys = tcx.generics_of(adt_def.did())
+                .own_args_no_defaults(tcx, args)
+                .iter()
+                .filter_map(|arg| arg.as_type())
+                .collect::<Vec<_>>();
+            if tys.is_empty() {
+                return name;
+            }
+            if max_depth == 0 {
+                return format!("{}<…>", name);
+            }
+            let args = tys.iter()
+                .map(|t| short_type_name(tcx, *t, max_depth - 1))
+                .collect::<Vec<_>>();
+            return format!("{}<{}>", name, args.join(", "));
+        }
+        ty::RawPtr(tm) => {
+            let prefix = if tm.mutbl.is_mut() { "*mut " } else { "*const " };
+            return format!("{}{}", prefix, short_type_name(tcx, tm.ty, max_depth));
+        }
+        ty::Ref(_, t, mutbl) => {
+            return format!("{}{}", mutbl.ref_prefix_str(),
+                           short_type_name(tcx, *t, max_depth));
+        }
+        ty::Slice(t) => {
+            return format!("[{}]", short_type_name(tcx, *t, max_depth));
+        }
+        ty::Array(t, len) => {
+            return format!("[{}; {}]", short_type_name(tcx, *t, max_depth), len);
+        }
+        ty::FnPtr(sig) => {
+            let sig = sig.skip_binder();
+            if max_depth == 0 {
+                return format!("{}fn(…)", sig.unsafety.prefix_str());
+            }
+            let inputs = sig.inputs().iter()
+                .map(|t| short_type_name(tcx, *t, max_depth - 1))
+                .collect::<Vec<_>>();
+            let output = if sig.output().is_unit() {
+                String::new()
+            } else {
+                format!(" -> {}", short_type_name(tcx, sig.output(), max_depth - 1))
+            };
+            return format!("{}fn({}){}", sig.unsafety.prefix_str(), inputs.join(", "), output);
+        }
+        ty::Tuple(tys) => {
+            if max_depth == 0 && !tys.is_empty() {
+                return "(…)".to_owned();
+            }
+            let elems = tys.iter()
+                .map(|t| short_type_name(tcx, t, max_depth.saturating_sub(1)))
+                .collect::<Vec<_>>();
+            if elems.len() == 1 {
+                return format!("({},)", elems[0]);
+            }
+            return format!("({})", elems.join(", "));
+        }
+        ty::Bool | ty::Char | ty::Int(_) | ty::Uint(_) | ty::Float(_)
+            | ty::Str | ty::Never => {
+            return t.to_string();
+        }
+        _ => {
+            // Closures, trait objects, etc. can print their whole signature,
+            // so only print them when there is depth left.
+            if max_depth == 0 { return "…".to_owned(); }
+            return t.to_string();
+        }
+    }
+}
+
+/// Get the mutability of a pointer type. A Box or Vec uniquely owns its
+/// target and is thus mutable, while an Rc or Arc shares it. It returns None
+/// for a type that is not a pointer.
+pub(crate) fn get_ptr_mutability<'tcx>(tcx: TyCtxt<'tcx>, t: Ty<'tcx>)
+    -> Option<PtrMutability> {
+    let to_ptr_mutability = |mutbl: Mutability| {
+        if mutbl.is_mut() { PtrMutability::Mut } else { PtrMutability::Const }
+    };
+    match t.kind() {
+        ty::RawPtr(tm) => Some(to_ptr_mutability(tm.mutbl)),
+        ty::Ref(_, _, mutbl) => Some(to_ptr_mutability(*mutbl)),
+        ty::Adt(adt_def, _) => {
+            if t.is_box() || tcx.is_diagnostic_item(sym::Vec, adt_def.did()) {
+                Some(PtrMutability::Mut)
+            } else if tcx.is_diagnostic_item(sym::Rc, adt_def.did()) ||
+                tcx.is_diagnostic_item(sym::Arc, adt_def.did()) {
+                Some(PtrMutability::Const)
+            } else {
+                None
+            }
+        },
+        _ => None
+    }
+}
+
+/// Check if a native call originates a raw pointer, i.e., it returns a raw
+/// pointer to @ret but takes no pointer (see get_ptr_mutability()) that the
+/// result may be derived from, e.g., CString::into_raw() or ptr::null_mut().
+pub(crate) fn is_native_ptr_origin<'tcx>(body: &Body<'tcx>, args: &[Operand<'tcx>],
+                                         ret: Local) -> bool {
+    ty::tls::with(|tcx| {
+        body.local_decls[ret].ty.is_unsafe_ptr() &&
+            !args.iter().any(|arg| get_ptr_mutability(tcx, arg.ty(body, tcx)).is_some())
+    })
+}
+
+/// Check if a function is an FFI declaration, i.e., a function of any ABI that
+/// has no body in Rust and thus cannot be analyzed. A function defined in Rust
+/// with a non-default ABI, e.g., 'extern "system" fn f() {..}', has MIR and is
+/// analyzed like other functions.
+///
+/// The MIR of a function in another crate is only available if the function
+/// is generic or inlinable, so for such a function it falls back to checking
+/// if the function is declared in an extern block.
+pub(crate) fn is_ffi_decl<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> bool {
+    if def_id.is_local() {
+        return !tcx.is_mir_available(def_id);
+    }
+
+    return tcx.is_foreign_item(def_id);
+}
+
+/// Get the Local of the destination of a call that returns something other
+/// than "()". Since rustc moved the target BB out of the destination of a
+/// TerminatorKind::Call, the destination is a Place that is always present,
+/// even for a diverging call (e.g., one to a "-> !" function) that never
+/// writes it. Such a call has no target BB and no destination here.
+/// This is the only place to read a call's destination, so that each user
+/// sees the same shape no matter how rustc represents it.
+pub(crate) fn call_destination_local<'tcx>(terminator: &Terminator<'tcx>,
+                                           body: &Body<'tcx>) -> Option<Local> {
+    if let TerminatorKind::Call{destination, target, ..} = &terminator.kind {
+        if target.is_some() && !is_empty_ty(body.local_decls[destination.local].ty) {
+            return Some(destination.local);
+        }
+    }
+
+    return None;
+}
+
+/// Get the Local that are a mutable borrow of another Local, e.g., _5 in
+/// "_5 = &mut _1", and the borrowed Local. Such a borrow is usually the
+/// (two-phase) borrow of the receiver of a method call like "v.push(p)":
+///
+///   _5 = &mut _1;
+///   _4 = Vec::<*mut u8>::push(move _5, move _6)
+///
+/// The data passed to the call may then be stored in the borrowed Local. This
+/// is flow-insensitive, like the MIR temporaries it is meant for.
+pub(crate) fn get_mut_borrows<'tcx>(body: &Body<'tcx>) -> FxHashMap<Local, Local> {
+    let mut borrows = FxHashMap::default();
+    for bbd in body.basic_blocks().iter() {
+        for stmt in &bbd.statements {
+            if let StatementKind::Assign(box (place, rvalue)) = &stmt.kind {
+                match rvalue {
+                    Rvalue::Ref(_, BorrowKind::Mut{..}, borrowed) |
+                    Rvalue::AddressOf(Mutability::Mut, borrowed) => {
+                        if place.projection.is_empty() {
+                            borrows.insert(place.local, borrowed.local);
+                        }
+                    },
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    borrows
+}
+
+/// Get the Local mutably borrowed by the arguments of a call. See
+/// get_mut_borrows().
+pub(crate) fn get_mut_borrowed_args<'tcx>(args: &Vec<Operand<'tcx>>,
+                                          borrows: &FxHashMap<Local, Local>)
+    -> Vec<Local> {
+    let mut places = Vec::<Place<'tcx>>::with_capacity(args.len());
+    args.iter().for_each(|arg| get_place_in_operand(arg, &mut places));
+    places.iter().filter_map(|place| borrows.get(&place.local).copied()).collect()
+}
+
 /// Check if a type is Result<T, E>.
 pub(crate) fn is_result_ty<'tcx>(tcx: TyCtxt<'tcx>, t: Ty<'tcx>) -> bool {
     match t.kind() {
@@ -207,35 +699,166 @@ pub(crate) fn is_result_ty<'tcx>(tcx: TyCtxt<'tcx>, t: Ty<'tcx>) -> bool {
     }
 }
 
-/// Get a function's DefId from a function Constant.
+/// Get a function's DefId from a function Constant, or None if it is not a
+/// function item, e.g., a constant function pointer.
 #[allow(dead_code)]
-pub(crate) fn get_callee_id_local<'tcx>(f: &Constant<'tcx>) -> DefId {
+pub(crate) fn get_callee_id_local<'tcx>(f: &Constant<'tcx>) -> Option<DefId> {
     if let ty::FnDef(def_id, _) = *f.literal.ty().kind() {
-        return def_id;
+        return Some(def_id);
     }
 
-    panic!("Not a function");
+    None
+}
+
+/// Wrap a DefId to be serialized as its DefIndex and CrateNum.
+pub(crate) fn break_def_id(def_id: DefId) -> SerDefId {
+    SerDefId(def_id)
 }
 
-/// Break a DefId into a tuple of its DefIndex and CrateNum.
-pub(crate) fn break_def_id(def_id: DefId) -> (u32, u32) {
-    (def_id.index.as_u32(), def_id.krate.as_u32())
+/// Get the DefId of a serialized DefId, whose DefIndex and CrateNum were
+/// validated when it was deserialized.
+pub(crate) fn assemble_def_id(def_id: SerDefId) -> DefId {
+    def_id.0
 }
 
-/// Create a DefId based on two u32 as DefIndex and CrateNum, respectively.
-pub(crate) fn assemble_def_id((index, krate): (u32, u32)) -> DefId {
-    DefId {
-        index: DefIndex::from_u32(index),
-        krate: CrateNum::from_u32(krate)
+/// Get the default directory for temporary files, i.e., "/tmp" on Unix.
+fn get_tmp_dir() -> String {
+    if cfg!(unix) {
+        return "/tmp".to_owned();
     }
+    return std::env::temp_dir().to_string_lossy().into_owned();
+}
+
+/// Get the pid that identifies the build, i.e., the pid of the parent process
+/// on Unix. There is no portable way to get the parent pid elsewhere, so the
+/// pid of the current process is used instead; each rustc process then has
+/// its own summary directory, and a build of multiple crates should set
+/// SANDBOX_SUMMARY_DIR.
+fn get_build_pid() -> u32 {
+    #[cfg(unix)]
+    return std::os::unix::process::parent_id();
+    #[cfg(not(unix))]
+    return std::process::id();
 }
 
 /// Get the directory that contains all the summary files.
 ///
-/// We assume that a Rust project is built by invoking `cargo`. The getppid()
-/// would therefore be the pid of the cargo process.
+/// We assume that a Rust project is built by invoking `cargo`. The parent pid
+/// would therefore be the pid of the cargo process. The environment variable
+/// SANDBOX_SUMMARY_DIR overrides it, e.g., for tests that invoke rustc directly.
 pub(crate) fn get_summary_dir() -> String {
-    return "/tmp/rust-sandbox-".to_owned() + &getppid().to_string();
+    if let Ok(dir) = std::env::var("SANDBOX_SUMMARY_DIR") {
+        return dir;
+    }
+    let dir = std::path::Path::new(&get_tmp_dir())
+        .join("rust-sandbox-".to_owned() + &get_build_pid().to_string());
+    return dir.to_string_lossy().into_owned();
+}
+
+/// Get the extra directories of summary files from the colon-separated
+/// environment variable SANDBOX_EXTRA_SUMMARY_DIRS, e.g., the summaries kept
+/// from a previous build or vendored with the dependencies. WPA reads them in
+/// addition to the summary directory of the current build.
+pub(crate) fn get_extra_summary_dirs() -> Vec<String> {
+    match std::env::var("SANDBOX_EXTRA_SUMMARY_DIRS") {
+        Ok(dirs) => dirs.split(':').filter(|dir| !dir.is_empty())
+            .map(|dir| dir.to_owned()).collect(),
+        Err(_) => Vec::new()
+    }
+}
+
+/// Check if the summary directory should be kept after WPA for debugging,
+/// i.e., the environment variable SANDBOX_KEEP_SUMMARIES is set to 1.
+///
+/// A kept directory may be reused by a later build whose cargo process happens
+/// to have the same pid. WPA therefore bumps the generation of the directory,
+/// and ignores the summary files written in an earlier generation.
+pub(crate) fn keep_summaries() -> bool {
+    return std::env::var("SANDBOX_KEEP_SUMMARIES").map_or(false, |keep| keep == "1");
+}
+
+/// Check if the unsafe access sites should be reported with a stable key, i.e.,
+/// the environment variable SANDBOX_STABLE_KEYS is set to 1.
+pub(crate) fn stable_keys_enabled() -> bool {
+    return std::env::var("SANDBOX_STABLE_KEYS").map_or(false, |enabled| enabled == "1");
+}
+
+/// Check if the fast path that skips the def site analysis of values that
+/// cannot carry taint is off, i.e., the environment variable
+/// SANDBOX_NO_FAST_PATH is set to 1.
+pub(crate) fn fast_path_disabled() -> bool {
+    return std::env::var("SANDBOX_NO_FAST_PATH").map_or(false, |v| v == "1");
+}
+
+/// Check if the per-crate caches of AnalysisCtxt are off, i.e., the
+/// environment variable SANDBOX_NO_CONTEXT_CACHE is set to 1. Each query then
+/// recomputes its answer, which is only useful to check the caches.
+pub(crate) fn context_cache_disabled() -> bool {
+    return std::env::var("SANDBOX_NO_CONTEXT_CACHE").map_or(false, |v| v == "1");
+}
+
+/// Check if the calls to unsafe functions are unsafe sources, i.e., the
+/// environment variable SANDBOX_UNSAFE_FN_CALLS is set to 1. By calling an
+/// unsafe fn, the caller asserts the invariants that the callee relies on.
+pub(crate) fn unsafe_fn_calls_as_sources() -> bool {
+    return std::env::var("SANDBOX_UNSAFE_FN_CALLS").map_or(false, |v| v == "1");
+}
+
+/// Get the soft limit of the size of a summary file from the environment
+/// variable SANDBOX_MAX_SUMMARY_BYTES. Summaries larger than the limit are
+/// split into multiple files. There is no limit by default. It is an error if
+/// the variable is not a number of bytes.
+pub(crate) fn get_max_summary_bytes() -> SandboxResult<Option<usize>> {
+    parse_max_summary_bytes(std::env::var("SANDBOX_MAX_SUMMARY_BYTES").ok())
+}
+
+/// Parse the value of SANDBOX_MAX_SUMMARY_BYTES, if it is set.
+pub(crate) fn parse_max_summary_bytes(value: Option<String>) -> SandboxResult<Option<usize>> {
+    match value {
+        Some(max_bytes) => match max_bytes.parse::<usize>() {
+            Ok(max_bytes) => Ok(Some(max_bytes)),
+            Err(_) => Err(SandboxError::InvalidEnv {
+                name: "SANDBOX_MAX_SUMMARY_BYTES".to_string(),
+                value: max_bytes,
+            }),
+        },
+        None => Ok(None)
+    }
+}
+
+/// Get the path to write the analysis archive to, from the environment
+/// variable SANDBOX_ARCHIVE. No archive is written by default.
+pub(crate) fn get_archive_path() -> Option<String> {
+    return std::env::var("SANDBOX_ARCHIVE").ok();
+}
+
+/// Get the path to write the whole-program call graph to in the Graphviz DOT
+/// format, from the environment variable SANDBOX_CALL_GRAPH_DOT, e.g., for
+/// "dot -Tpng". No call graph is written by default.
+pub(crate) fn get_call_graph_dot_path() -> Option<String> {
+    return std::env::var("SANDBOX_CALL_GRAPH_DOT").ok();
+}
+
+/// Get the directory to write the per-function provenance of unsafe Local to,
+/// from the environment variable SANDBOX_PROVENANCE. It is off by default as
+/// there is one file for each function with unsafe sources.
+pub(crate) fn get_provenance_dir() -> Option<String> {
+    return std::env::var("SANDBOX_PROVENANCE").ok();
+}
+
+/// Get the directory of the whole-program summary and the analysis results.
+/// The environment variable SANDBOX_OUTPUT_DIR overrides the default "/tmp",
+/// or the temporary directory of the system on other hosts.
+fn get_output_dir() -> String {
+    if let Ok(dir) = std::env::var("SANDBOX_OUTPUT_DIR") {
+        return dir;
+    }
+    return get_tmp_dir();
+}
+
+/// Get the path of a file in the output directory.
+fn get_output_path(file: &str) -> String {
+    return std::path::Path::new(&get_output_dir()).join(file).to_string_lossy().into_owned();
 }
 
 /// Get the path of the whole-program summary.
@@ -243,37 +866,247 @@ pub(crate) fn get_summary_dir() -> String {
 /// TODO: Now we write it to "/tmp/rust-sandbox-summary" beause we still haven't
 /// solved the synchronization between the analysis results of dependece crates
 /// and the binary crate. Once that is solved, we should write it to
-/// "/tmp/rust-sandbox-".to_owned() + &getppid().to_string() + "-summary""
+/// get_summary_dir() + "-summary".
 pub(crate) fn get_wp_summary_path() -> String {
-    return "/tmp/rust-sandbox-summary".to_owned();
+    return get_output_path("rust-sandbox-summary");
+}
+
+/// Get the path of a copy of the whole-program summary named after the main
+/// crate, if the environment variable SANDBOX_WP_SUMMARY_PER_CRATE is set to 1.
+/// The binary crates of a workspace overwrite each other's summary at
+/// get_wp_summary_path(), but not these copies, which an external driver can
+/// combine by merge::merge_wp_summaries().
+pub(crate) fn get_crate_wp_summary_path() -> Option<String> {
+    if !std::env::var("SANDBOX_WP_SUMMARY_PER_CRATE").map_or(false, |v| v == "1") {
+        return None;
+    }
+    let stable_crate_id = ty::tls::with(|tcx| tcx.stable_crate_id(LOCAL_CRATE).to_u64());
+    Some(get_wp_summary_path() + "-" + &get_local_crate_name() + "-" +
+         &stable_crate_id.to_string())
+}
+
+/// Get the path of the human-readable report of the whole-program summary.
+pub(crate) fn get_wp_report_path() -> String {
+    return get_wp_summary_path() + ".txt";
+}
+
+/// Get the path of the per-crate statistics of the analysis in JSON; see
+/// stats::Stats.
+pub(crate) fn get_stats_path() -> String {
+    return get_output_path("rust-sandbox-stats.json");
 }
 
 /// Get the path of the memory dereference analysis result file.
 pub(crate) fn get_deref_result_path() -> String {
-    return "/tmp/rust-sandbox-deref".to_owned();
+    return get_output_path("rust-sandbox-deref");
+}
+
+/// Get the path of the file that lists the source location of each unsafe
+/// memory access.
+pub(crate) fn get_access_sites_path() -> String {
+    return get_output_path("rust-sandbox-access-sites");
+}
+
+/// Get the path of the file that lists the source location of each unsafe
+/// memory access in JSON, one access per line.
+pub(crate) fn get_access_locs_path() -> String {
+    return get_output_path("rust-sandbox-access-locs.jsonl");
+}
+
+/// Get the user-visible call site of a span, and the name of the innermost
+/// bang macro (e.g., "my_unsafe_macro") the span was expanded from, if any.
+/// Unsafe code written inside a macro is better attributed to the macro than
+/// to the place where the macro is invoked.
+pub(crate) fn get_macro_call_site(span: Span) -> (Span, Option<String>) {
+    let macro_name = span.macro_backtrace().find_map(|expn_data| {
+        match expn_data.kind {
+            ExpnKind::Macro(MacroKind::Bang, name) => Some(name.to_string()),
+            _ => None
+        }
+    });
+
+    return (span.source_callsite(), macro_name);
 }
 
-/// Create a DefSite from a function call.
+/// Create a DefSite from a function call, or None if the callee is not a
+/// function item, e.g., a constant function pointer. Such a call is handled
+/// like a call through a function pointer in a Local.
 pub(crate) fn def_site_from_call<'tcx>(f: &Constant<'tcx>, bb_index: u32)
-    -> DefSite {
+    -> Option<DefSite> {
     if let ty::FnDef(def_id, _) = *f.literal.ty().kind() {
+        if ty::tls::with(|tcx| is_allocator_trait_fn(tcx, def_id)) {
+            // Any allocator, including one outside the native libraries.
+            return Some(DefSite::HeapAlloc(bb_index));
+        }
         if NATIVE_LIBS.contains(&get_crate_name(def_id)) {
-            if HEAP_ALLOC.contains(&get_fn_name(def_id)) {
-                return DefSite::HeapAlloc(bb_index);
+            if get_fn_path(def_id) == DROP_IN_PLACE {
+                return Some(DefSite::DropInPlace(bb_index));
+            } else if TRANSMUTE.contains(&get_fn_name(def_id)) {
+                return Some(DefSite::Transmute(bb_index));
+            } else if UNINIT_FNS.contains(&get_fn_name(def_id)) {
+                return Some(DefSite::Uninit(bb_index));
+            } else if RAW_SLICE.contains(&get_fn_path(def_id)) {
+                return Some(DefSite::RawSlice(bb_index));
+            } else if (HEAP_ALLOC.contains(&get_fn_name(def_id)) &&
+                       ty::tls::with(|tcx| is_known_allocator(tcx, def_id))) ||
+                get_callee_path(f).is_some_and(|path| GLOBAL_ALLOC.contains(&path)) {
+                return Some(DefSite::HeapAlloc(bb_index));
             } else {
-                return DefSite::NativeCall(bb_index);
+                return Some(DefSite::NativeCall(bb_index));
             }
         } else {
-            return DefSite::OtherCall(bb_index);
+            return Some(DefSite::OtherCall(bb_index));
         }
     }
 
-    panic!("Not a function");
+    None
+}
+
+/// Get the function a constant function pointer points to, e.g., the value of
+/// "const F: fn() = foo;", or None if the constant cannot be evaluated or does
+/// not point to a function.
+pub(crate) fn resolve_const_fn_ptr<'tcx>(tcx: TyCtxt<'tcx>, f: &Constant<'tcx>)
+    -> Option<DefId> {
+    if !f.literal.ty().is_fn_ptr() { return None; }
+    let val = f.literal.eval(tcx, ty::ParamEnv::reveal_all(), None).ok()?;
+    let Some(Scalar::Ptr(ptr, _)) = val.try_to_scalar() else { return None; };
+    match tcx.try_get_global_alloc(ptr.into_parts().0)? {
+        GlobalAlloc::Function(instance) => Some(instance.def_id()),
+        _ => None
+    }
+}
+
+/// Get the type allocated by a call to a heap allocation function, i.e., the
+/// first generic type argument of the callee, e.g., T of Vec::<T>::with_capacity
+/// or Box::<T>::new. Untyped allocations, e.g., String::new(), and the methods
+/// of allocator traits, whose generic argument is the allocator, have none.
+pub(crate) fn alloc_type_from_call<'tcx>(tcx: TyCtxt<'tcx>, f: &Constant<'tcx>,
+                                         bb_index: u32) -> Option<String> {
+    let ty::FnDef(def_id, substs) = *f.literal.ty().kind() else { return None; };
+    if def_site_from_call(f, bb_index) != Some(DefSite::HeapAlloc(bb_index)) ||
+        is_allocator_trait_fn(tcx, def_id) {
+        return None;
+    }
+
+    substs.types().next().map(|t| short_type_name(tcx, t, MAX_TYPE_NAME_DEPTH))
+}
+
+/// Create a DefSite from a Rvalue if it is where a value originates, i.e.,
+/// it allocates memory or it takes the address of a global variable.
+///
+/// Box::new() is lowered to a call to exchange_malloc followed by
+/// "_6 = ShallowInitBox(move _5, T)", and the call is gone once exchange_malloc
+/// is inlined; so the ShallowInitBox is itself a heap allocation site, located
+/// by the BB of the statement; see DefSite::BoxAlloc.
+///
+/// A static is accessed through its address, e.g., "_2 = const {alloc1: *mut i32}"
+/// followed by "(*_2) = ...", and a thread-local through "_2 = &/*tls*/ X".
+///
+/// A read of a union field creates a possibly invalid value; see
+/// is_union_field(). A Transmute cast also creates one, but the result
+/// inherits the taint of its operand; see DefSite::inherits_taint(). So does
+/// a cast from an integer to a pointer, whereas a cast between pointers only
+/// propagates the taint of its operand.
+pub(crate) fn def_site_from_rvalue<'tcx>(rvalue: &Rvalue<'tcx>, body: &Body<'tcx>,
+                                         bb_index: u32) -> Option<DefSite> {
+    match rvalue {
+        Rvalue::ShallowInitBox(..) => Some(DefSite::BoxAlloc(bb_index)),
+        Rvalue::Use(Operand::Copy(place) | Operand::Move(place))
+            if is_union_field(place, body) => Some(DefSite::UnionRead(bb_index)),
+        Rvalue::Cast(CastKind::Transmute, ..) => Some(DefSite::Transmute(bb_index)),
+        Rvalue::Cast(CastKind::PointerFromExposedAddress, ..) => {
+            Some(DefSite::IntToPtr(bb_index))
+        },
+        Rvalue::ThreadLocalRef(def_id) => Some(DefSite::Global(break_def_id(*def_id))),
+        Rvalue::Use(Operand::Constant(constant)) => {
+            ty::tls::with(|tcx| constant.check_static_ptr(tcx))
+                .map(|def_id| DefSite::Global(break_def_id(def_id)))
+        },
+        _ => None
+    }
+}
+
+/// Check if a Place is (in) a field of a union, e.g., "(_1.1: *mut u8)" where
+/// _1 is a union. Reading it reinterprets the bytes of the union as the type
+/// of the field, which is why it is unsafe.
+pub(crate) fn is_union_field<'tcx>(place: &Place<'tcx>, body: &Body<'tcx>) -> bool {
+    ty::tls::with(|tcx| {
+        place.iter_projections().any(|(base, elem)| {
+            matches!(elem, ProjectionElem::Field(..)) &&
+                Place::ty_from(base.local, base.projection, body, tcx).ty
+                    .ty_adt_def().is_some_and(|adt| adt.is_union())
+        })
+    })
+}
+
+/// Check if a Place is (in) a field of an enum variant, e.g., "((_1 as
+/// Some).0: *mut u8)". A write to it only defines part of the enum, which is
+/// completed by a SetDiscriminant.
+pub(crate) fn is_variant_field<'tcx>(place: &Place<'tcx>) -> bool {
+    matches!(place.projection.first(), Some(ProjectionElem::Downcast(..)))
+}
+
+/// Get the Local defined by a Box allocated in place in a BB, i.e., the Box
+/// initialized by a ShallowInitBox.
+pub(crate) fn box_alloc_local<'tcx>(bbd: &BasicBlockData<'tcx>) -> Option<Local> {
+    return bbd.statements.iter().rev().find_map(|stmt| match &stmt.kind {
+        StatementKind::Assign(box (place, Rvalue::ShallowInitBox(..))) => Some(place.local),
+        _ => None
+    });
+}
+
+/// Get the Local defined by a transmute in a BB, either by a Transmute cast
+/// statement or by a call to mem::transmute_copy.
+pub(crate) fn transmute_local<'tcx>(bbd: &BasicBlockData<'tcx>, body: &Body<'tcx>)
+    -> Option<Local> {
+    let cast_local = bbd.statements.iter().rev().find_map(|stmt| match &stmt.kind {
+        StatementKind::Assign(box (place, Rvalue::Cast(CastKind::Transmute, ..))) =>
+            Some(place.local),
+        _ => None
+    });
+
+    return cast_local.or_else(|| call_destination_local(bbd.terminator(), body));
+}
+
+/// Get the Local defined by a cast from an integer to a pointer in a BB.
+pub(crate) fn int_to_ptr_local<'tcx>(bbd: &BasicBlockData<'tcx>) -> Option<Local> {
+    return bbd.statements.iter().rev().find_map(|stmt| match &stmt.kind {
+        StatementKind::Assign(box (place,
+            Rvalue::Cast(CastKind::PointerFromExposedAddress, ..))) => Some(place.local),
+        _ => None
+    });
+}
+
+/// Check if a call is to a pointer arithmetic method of raw pointers, e.g.,
+/// "<*mut T>::add", or to a pointer arithmetic intrinsic. A function of other
+/// types with the same name, e.g., "<usize as Add>::add", is not one.
+pub(crate) fn is_ptr_arith_call<'tcx>(f: &Constant<'tcx>) -> bool {
+    if let ty::FnDef(def_id, _) = *f.literal.ty().kind() {
+        if !NATIVE_LIBS.contains(&get_crate_name(def_id)) ||
+            !PTR_ARITH.contains(&get_fn_name(def_id)) {
+            return false;
+        }
+        return ty::tls::with(|tcx| {
+            tcx.is_intrinsic(def_id) || tcx.impl_of_method(def_id).is_some_and(|impl_did|
+                tcx.type_of(impl_did).skip_binder().is_unsafe_ptr())
+        });
+    }
+
+    false
+}
+
+/// Get a synthetic FnID for the unknown callee of a call through a function
+/// pointer. It is unique to the call site, i.e., the caller and the BB.
+pub(crate) fn get_fn_ptr_callee_id(caller: FnID, bb_index: u32) -> FnID {
+    let mut hasher = StableHasher::new();
+    caller.as_value().hash(&mut hasher);
+    bb_index.hash(&mut hasher);
+    FnID(DefPathHash(hasher.finish::<Fingerprint>()))
 }
 
 /// Get the inner value of DefPathHash (Fingerprint) of a function.
 pub(crate) fn get_fn_fingerprint<'tcx>(tcx: TyCtxt<'tcx>, def_id: DefId) -> FnID {
-    FnID(tcx.def_path_hash(def_id).0.as_value())
+    FnID(tcx.def_path_hash(def_id))
 }
 
 
@@ -287,10 +1120,11 @@ pub(crate) fn get_place_in_stmt<'tcx>(stmt: &Statement<'tcx>,
             // Will the "box ..." syntax creates a new heap object?
             // If so this might be too slow.
         },
-        StatementKind::FakeRead(box (_cause, _place)) => {
-            print_stmt("FakeRead", stmt);
-            // TODO?: Handle FakeRead
-            panic!("Need to examine this FakeRead");
+        StatementKind::FakeRead(box (_cause, place)) => {
+            // FakeRead is left by pattern matches and let-bindings in MIR
+            // before CleanupPostBorrowck. It reads the Place.
+            if _DEBUG { print_stmt("FakeRead", stmt); }
+            places.push(*place);
         },
         StatementKind::SetDiscriminant {box place, ..} => {
             places.push(*place);
@@ -344,8 +1178,8 @@ pub(crate) fn get_place_in_terminator<'tcx>(body: &'tcx Body<'tcx>,
                 get_place_in_operand(arg, places);
             }
             // Get the Place of the LHS if the call returns something.
-            if is_empty_ty(body.local_decls[destination.local].ty) {
-                // Ignore return type of "()".
+            if call_destination_local(terminator, body).is_none() {
+                // Ignore return type of "()" and diverging calls.
                 return;
             }
             // Question: Should we ignore all locals, i.e., Place whose
@@ -353,13 +1187,35 @@ pub(crate) fn get_place_in_terminator<'tcx>(body: &'tcx Body<'tcx>,
             places.push(*destination);
         },
         TerminatorKind::Assert{cond, ..} => {
-            // Do we need to handle assertions?
+            // The condition is always a bool temporary computed by earlier
+            // statements, e.g., "_5 = Lt(_4, _6)" of a bounds check. It is
+            // never a dereference, so it is not an unsafe access itself.
             get_place_in_operand(cond, places);
         },
         TerminatorKind::Yield{value, resume: _, resume_arg, ..} => {
             get_place_in_operand(value, places);
             places.push(*resume_arg);
         },
+        TerminatorKind::InlineAsm{operands, ..} => {
+            // Inline assembly is always unsafe and may read or write memory
+            // via the pointers passed to it.
+            for operand in operands {
+                match operand {
+                    InlineAsmOperand::In{value, ..} => {
+                        get_place_in_operand(value, places);
+                    },
+                    InlineAsmOperand::Out{place, ..} => {
+                        places.extend(place);
+                    },
+                    InlineAsmOperand::InOut{in_value, out_place, ..} => {
+                        get_place_in_operand(in_value, places);
+                        places.extend(out_place);
+                    },
+                    InlineAsmOperand::Const{..} | InlineAsmOperand::SymFn{..} |
+                        InlineAsmOperand::SymStatic{..} => {}
+                }
+            }
+        },
         _ => {}
     }
 }
@@ -7,12 +7,19 @@
 use std::fs::{read_dir, read_to_string};
 use std::fs::{remove_dir_all};
 use rustc_data_structures::fx::{FxHashMap, FxHashSet};
+use rustc_data_structures::flock::Lock;
 use std::{fmt, io};
 use std::collections::VecDeque;
 use std::process::{Command, Stdio};
 use std::fs;
+use std::path::Path;
 
-use super::summarize_fn::{Summary, FnID, DefSite};
+use super::archive;
+use super::stats::{self, CrateStats};
+use super::error::{SandboxError, SandboxResult};
+use super::summarize_fn::{Summary, SummaryFile, FnID, DefSite, Callee, PtrMutability};
+use super::summarize_fn::{KEEP_MARKER, SUMMARY_LOCK, SUMMARY_TMP_EXT};
+use super::summarize_fn::{keep_summary_dir, summary_generation, unsafe_roots_only};
 use super::utils::*;
 
 static _DEBUG: bool = false;
@@ -27,12 +34,22 @@
 /// It is semantically more clear to recursively use CallGraphNode for a node's
 /// callers and callees. However, it might not be possible to do so using safe
 /// Rust. See: https://github.com/URSec/SURust/issues/3
+/// Instead, a node refers to its callers and callees by FnID, and to its own
+/// Summary, which outlives the call graph, so that the analyses need not look
+/// it up again.
 struct CallGraphNode<'a> {
+    fn_id: FnID,
+    /// Summary of the function. It is None for a function that is only seen
+    /// as a callee, e.g., a native or foreign function.
+    summary: Option<&'a Summary>,
     // crete_name and fn_name are for debugging. No need for them for analysis.
     crate_name: &'a str,
     fn_name: &'a str,
     callees: FxHashSet<FnID>,
     callers: FxHashSet<FnID>,
+    /// Methods in the vtables created by this function. They are not callees
+    /// as there are no calls (and thus no arg_defs) to them in this function.
+    vtable_fns: FxHashSet<FnID>,
 }
 
 impl fmt::Debug for CallGraphNode<'_> {
@@ -42,7 +59,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 }
 
 /// Call graph of the whole program.
-struct CallGraph<'a> (FxHashMap<FnID, CallGraphNode<'a>>);
+pub(crate) struct CallGraph<'a> (FxHashMap<FnID, CallGraphNode<'a>>);
 
 impl<'a> CallGraph<'a> {
     /// Get the CallGraphNode of a function.
@@ -50,10 +67,202 @@ fn get(&self, fn_id: &FnID) -> &CallGraphNode<'_> {
         return &self.0.get(fn_id).unwrap();
     }
 
+    /// Get the Summary of a function, if it has one.
+    pub(crate) fn get_summary(&self, fn_id: &FnID) -> Option<&'a Summary> {
+        return self.0.get(fn_id).and_then(|node| node.summary);
+    }
+
+    /// Get all the summaries in the call graph.
+    pub(crate) fn summaries(&self) -> impl Iterator<Item = &'a Summary> + '_ {
+        self.0.values().filter_map(|node| node.summary)
+    }
+
     /// Get a function's callers.
-    fn get_callers(&self, fn_id: &FnID) -> &FxHashSet<FnID> {
+    pub(crate) fn get_callers(&self, fn_id: &FnID) -> &FxHashSet<FnID> {
         return &CallGraph::get(self, fn_id).callers;
     }
+
+    /// Get a function's callees.
+    pub(crate) fn get_callees(&self, fn_id: &FnID) -> &FxHashSet<FnID> {
+        return &CallGraph::get(self, fn_id).callees;
+    }
+
+    /// Get the functions reachable from the given roots, including the roots
+    /// themselves. Besides the callees, a function reaches the methods in the
+    /// vtables it creates, the closures it coerces to Fn trait objects, and
+    /// the functions it coerces to fn pointers, as they may be called via
+    /// the trait objects or fn pointers.
+    pub(crate) fn reachable_from(&self, roots: Vec<FnID>) -> FxHashSet<FnID> {
+        let mut reachable = FxHashSet::<FnID>::default();
+        let mut worklist = roots;
+        while let Some(fn_id) = worklist.pop() {
+            if !reachable.insert(fn_id) {
+                continue;
+            }
+            let Some(node) = self.0.get(&fn_id) else {
+                continue;
+            };
+            worklist.extend(node.callees.iter().copied());
+            worklist.extend(node.vtable_fns.iter().copied());
+            if let Some(summary) = node.summary {
+                worklist.extend(summary.dyn_fn_closures.iter().map(|(closure, _)| closure.fn_id));
+                worklist.extend(summary.fn_ptr_fns.iter().map(|f| f.fn_id));
+            }
+        }
+
+        reachable
+    }
+
+    /// Get the functions reachable from the entry points of the program,
+    /// i.e., main(). Without main(), e.g., when analyzing a library, the
+    /// exported functions are the entry points. If there is neither, e.g.,
+    /// the summaries are partial, every function is considered reachable.
+    pub(crate) fn reachable_fns(&self) -> FxHashSet<FnID> {
+        let roots_by = |is_root: fn(&Summary) -> bool| self.summaries()
+            .filter(|summary| is_root(summary)).map(|summary| summary.fn_id)
+            .collect::<Vec<_>>();
+        let mut roots = roots_by(|summary| summary.is_entry);
+        if roots.is_empty() {
+            roots = roots_by(|summary| summary.is_exported);
+        }
+        if roots.is_empty() {
+            roots = self.0.keys().copied().collect();
+        }
+
+        self.reachable_from(roots)
+    }
+
+    /// Compute the strongly connected components (SCC) of the call graph
+    /// with Tarjan's algorithm. Each SCC is a set of mutually recursive
+    /// functions, or a single function. The SCCs are in reverse topological
+    /// order of the condensed DAG, i.e., an SCC comes after all the SCCs it
+    /// calls. The functions in each SCC are sorted by FnID.
+    ///
+    /// The DFS uses an explicit stack instead of recursion so that a long
+    /// call chain does not overflow the stack.
+    pub(crate) fn sccs(&self) -> Vec<Vec<FnID>> {
+        let sorted_callees = |fn_id: &FnID| {
+            let mut callees = self.get_callees(fn_id).iter().copied().collect::<Vec<_>>();
+            callees.sort();
+            callees
+        };
+        let mut fn_ids = self.0.keys().copied().collect::<Vec<_>>();
+        fn_ids.sort();
+
+        // DFS index and lowlink of each visited function.
+        let mut index = FxHashMap::<FnID, usize>::default();
+        let mut lowlink = FxHashMap::<FnID, usize>::default();
+        let mut stack = Vec::<FnID>::new();
+        let mut on_stack = FxHashSet::<FnID>::default();
+        let mut sccs = Vec::new();
+        for root in fn_ids {
+            if index.contains_key(&root) { continue; }
+            // Each frame is a function, its callees and the next callee to visit.
+            let mut dfs = Vec::<(FnID, Vec<FnID>, usize)>::new();
+            let mut new_fn = Some(root);
+            loop {
+                if let Some(fn_id) = new_fn.take() {
+                    let i = index.len();
+                    index.insert(fn_id, i);
+                    lowlink.insert(fn_id, i);
+                    stack.push(fn_id);
+                    on_stack.insert(fn_id);
+                    dfs.push((fn_id, sorted_callees(&fn_id), 0));
+                }
+                let Some((fn_id, callees, next)) = dfs.last_mut() else { break; };
+                let fn_id = *fn_id;
+                if let Some(callee) = callees.get(*next).copied() {
+                    *next += 1;
+                    if !index.contains_key(&callee) {
+                        new_fn = Some(callee);
+                    } else if on_stack.contains(&callee) {
+                        let low = lowlink[&fn_id].min(index[&callee]);
+                        lowlink.insert(fn_id, low);
+                    }
+                    continue;
+                }
+
+                // All the callees are visited.
+                dfs.pop();
+                if let Some((caller, ..)) = dfs.last() {
+                    let low = lowlink[caller].min(lowlink[&fn_id]);
+                    lowlink.insert(*caller, low);
+                }
+                if lowlink[&fn_id] == index[&fn_id] {
+                    // fn_id is the root of an SCC.
+                    let mut scc = Vec::new();
+                    loop {
+                        let member = stack.pop().unwrap();
+                        on_stack.remove(&member);
+                        scc.push(member);
+                        if member == fn_id { break; }
+                    }
+                    scc.sort();
+                    sccs.push(scc);
+                }
+            }
+        }
+
+        sccs
+    }
+
+    /// Update the node of a function whose summary changes from @old to @new,
+    /// without rebuilding the whole call graph. It removes the callee edges of
+    /// @old, adds those of @new, and fixes up the callers of the callees on
+    /// both sides. @old is None for a function that is not in the graph yet.
+    pub(crate) fn update_node(&mut self, old: Option<&Summary>, new: &'a Summary) {
+        let caller_id = new.fn_id;
+        if let Some(old) = old {
+            for callee in &old.callees {
+                if let Some(callee_node) = self.0.get_mut(&callee.fn_id) {
+                    callee_node.callers.remove(&caller_id);
+                }
+            }
+        }
+
+        // Create a new CallGraphNode for the fn if not exist. The callers of
+        // an existing node are kept as they do not depend on its summary.
+        let node = self.0.entry(caller_id).or_insert_with(|| CallGraphNode {
+            fn_id: caller_id,
+            summary: None,
+            crate_name: &new.crate_name,
+            fn_name: &new.fn_name,
+            callees: FxHashSet::default(),
+            callers: FxHashSet::default(),
+            vtable_fns: FxHashSet::default()
+        });
+        node.summary = Some(new);
+        node.callees = new.callees.iter().map(|callee| callee.fn_id).collect();
+        node.vtable_fns = new.vtable_fns.iter().map(|method| method.fn_id).collect();
+
+        // Add the fn to each callee's caller set.
+        for callee in &new.callees {
+            self.0.entry(callee.fn_id).or_insert_with(|| CallGraphNode {
+                fn_id: callee.fn_id,
+                summary: None,
+                crate_name: &callee.crate_name,
+                fn_name: &callee.fn_name,
+                callees: FxHashSet::default(),
+                callers: FxHashSet::default(),
+                vtable_fns: FxHashSet::default()
+            }).callers.insert(caller_id);
+        }
+
+        // Link the methods that may be called via the vtables created by
+        // this fn, so that a method only reachable via trait objects is still
+        // in the call graph.
+        for method in &new.vtable_fns {
+            self.0.entry(method.fn_id).or_insert_with(|| CallGraphNode {
+                fn_id: method.fn_id,
+                summary: None,
+                crate_name: &method.crate_name,
+                fn_name: &method.fn_name,
+                callees: FxHashSet::default(),
+                callers: FxHashSet::default(),
+                vtable_fns: FxHashSet::default()
+            });
+        }
+    }
 }
 
 /// A def site in the global perspective.
@@ -69,11 +278,58 @@ fn eq(&self, other: &GlobalDefSite) -> bool {
     }
 }
 
+/// A deallocation site, i.e., a Drop terminator, a call to a deallocation
+/// function of the global allocator API (see summarize_fn::unsafe_def::
+/// find_drop_defs()), or a call to a deallocation function (see
+/// Summary::free_callees), located by the function and the BB.
+#[derive(Hash, Eq, PartialEq, Ord, PartialOrd, Clone, Copy, Debug)]
+pub struct DeallocSite {
+    pub fn_id: FnID,
+    pub bb: u32,
+}
+
 /// Whole-program summary.
 pub type WPSummary = FxHashMap<FnID, FxHashSet<DefSite>>;
 
-/// All the unsafe sources.
-pub(crate) type UnsafeSources = Vec::<(FnID, FxHashSet<DefSite>)>;
+/// Queries of the unsafe sources in a WPSummary that do not depend on how the
+/// WPSummary stores them.
+pub trait UnsafeSites {
+    /// Iterate over each unsafe source as (fn, def site), sorted by FnID and
+    /// then by DefSite.
+    fn iter_unsafe_sites(&self) -> Box<dyn Iterator<Item = (FnID, DefSite)> + '_>;
+
+    /// Count the unsafe sources of each kind: (HeapAlloc and BoxAlloc,
+    /// NativeCall, OtherCall, Arg). The other kinds are not counted.
+    fn count_by_kind(&self) -> (usize, usize, usize, usize);
+}
+
+impl UnsafeSites for WPSummary {
+    fn iter_unsafe_sites(&self) -> Box<dyn Iterator<Item = (FnID, DefSite)> + '_> {
+        let mut sites = self.iter()
+            .flat_map(|(fn_id, def_sites)| def_sites.iter().map(|def_site| (*fn_id, *def_site)))
+            .collect::<Vec<_>>();
+        sites.sort();
+        Box::new(sites.into_iter())
+    }
+
+    fn count_by_kind(&self) -> (usize, usize, usize, usize) {
+        let mut counts = (0, 0, 0, 0);
+        for def_site in self.values().flatten() {
+            match def_site {
+                DefSite::HeapAlloc(_) | DefSite::BoxAlloc(_) => counts.0 += 1,
+                DefSite::NativeCall(_) => counts.1 += 1,
+                DefSite::OtherCall(_) => counts.2 += 1,
+                DefSite::Arg(_) => counts.3 += 1,
+                _ => {}
+            }
+        }
+        counts
+    }
+}
+
+/// All the unsafe sources, each function with whether it is reachable from
+/// the entry points of the program (see CallGraph::reachable_fns()).
+pub(crate) type UnsafeSources = Vec::<(FnID, FxHashSet<DefSite>, bool)>;
 
 /// Count the number of summary files in the temporary summary directory.
 /// Essentially, it gets the result of `ls | wc -l` and converts it to an u32.
@@ -86,16 +342,89 @@ fn curr_dep_crate_num(summary_dir: &str) -> io::Result<u32> {
 
 /// Read the fn summaries of each crate from the summary files, and then put
 /// them to a HashMap for later use.
-fn read_summaries() -> io::Result<FxHashMap<FnID, Summary>> {
-    let summary_dir = get_summary_dir();
+fn read_summaries() -> SandboxResult<FxHashMap<FnID, Summary>> {
     // When the main crate is being compiled, all its dependent should be ready.
+    let mut dirs = get_extra_summary_dirs();
+    dirs.push(get_summary_dir());
+    read_summary_dirs(&dirs)
+}
+
+/// Read the fn summaries in multiple summary directories. A Summary in a later
+/// directory replaces the one of the same function in an earlier directory, so
+/// the summary directory of the current build should be the last one.
+pub(crate) fn read_summary_dirs(summary_dirs: &[String])
+    -> SandboxResult<FxHashMap<FnID, Summary>> {
+    let mut summaries = FxHashMap::<FnID, Summary>::default();
+    for summary_dir in summary_dirs {
+        summaries.extend(read_summary_dir(summary_dir)?);
+    }
+
+    Ok(summaries)
+}
+
+/// Find the Summary of a function by its crate name and fn name, e.g., "std"
+/// and "f". The fn name is not unique: methods of different types, e.g., "new",
+/// have the same name. It returns None if no function or more than one
+/// function has the name; see find_all_by_name() for the latter.
+pub fn find_by_name<'a>(summaries: &'a FxHashMap<FnID, Summary>,
+                        crate_name: &str, fn_name: &str) -> Option<&'a Summary> {
+    match find_all_by_name(summaries, crate_name, fn_name)[..] {
+        [summary] => Some(summary),
+        _ => None
+    }
+}
+
+/// Find the Summary of all the functions with a crate name and a fn name,
+/// sorted by FnID. All the instances of a generic function share one Summary.
+pub fn find_all_by_name<'a>(summaries: &'a FxHashMap<FnID, Summary>,
+                            crate_name: &str, fn_name: &str) -> Vec<&'a Summary> {
+    let mut found = summaries.values()
+        .filter(|summary| summary.crate_name == crate_name && summary.fn_name == fn_name)
+        .collect::<Vec<_>>();
+    found.sort_by_key(|summary| summary.fn_id);
 
+    found
+}
+
+/// Read the fn summaries in all the files of a summary directory. The
+/// summaries of a crate may be split into multiple files (chunks) by
+/// summarize_fn::write_summary_chunks(); each chunk is a complete SummaryFile.
+///
+/// Other rustc processes may be writing to the directory, so the files are
+/// read while holding the lock file of the directory shared; see
+/// summarize_fn::write_summary_files(). The lock file, the temporary files
+/// left by a failed writer, and the files that are not a SummaryFile are
+/// skipped, and so are the files written before the directory was last kept;
+/// see KEEP_MARKER.
+pub(crate) fn read_summary_dir(summary_dir: &str)
+    -> SandboxResult<FxHashMap<FnID, Summary>> {
+    let _lock = Lock::new(&Path::new(summary_dir).join(SUMMARY_LOCK), true, true, false)?;
     let mut dep_summaries = FxHashMap::<FnID, Summary>::default();
+    let generation = summary_generation(summary_dir);
     // Collect summaries.
     for summaries in read_dir(summary_dir)? {
-        let summaries_str = read_to_string(summaries?.path())?;
-        let summaries_vec = serde_json::from_str::<Vec<Summary>>(&summaries_str)?;
-        for summary in summaries_vec {
+        let path = summaries?.path();
+        if path.file_name() == Some(KEEP_MARKER.as_ref()) ||
+            path.file_name() == Some(SUMMARY_LOCK.as_ref()) ||
+            path.extension() == Some(SUMMARY_TMP_EXT.as_ref()) {
+            continue;
+        }
+        let summaries_str = read_to_string(&path)?;
+        let summary_file =
+            match serde_json::from_str::<SummaryFile<Vec<Summary>>>(&summaries_str) {
+                Ok(summary_file) => summary_file,
+                Err(err) => {
+                    eprintln!("Sandbox: skipped {}: {}", path.display(), err);
+                    continue;
+                }
+            };
+        // A stale summary file from a build that kept the directory.
+        if summary_file.header.generation < generation { continue; }
+        if _DEBUG {
+            println!("Summaries of {} with cfg {:?}", summary_file.header.target,
+                summary_file.header.cfg);
+        }
+        for summary in summary_file.summaries {
             // Is it deep copy for summary here?
             dep_summaries.insert(summary.fn_id, summary);
         }
@@ -104,58 +433,197 @@ fn read_summaries() -> io::Result<FxHashMap<FnID, Summary>> {
     Ok(dep_summaries)
 }
 
-/// Write the result of the WPA to a file that will be used by all compile units.
+/// Format the unsafe sources of each function as a human-readable report. The
+/// functions are sorted by name, and each def site is listed with its kind
+/// and, for a call, the name of the callee(s). A function not in reachable is
+/// tagged as unreachable, e.g.,
+///
+/// ```text
+/// krate::foo
+///     HeapAlloc at BB2: alloc::exchange_malloc
+///     Arg 1
+/// krate::bar (unreachable)
+///     NativeCall at BB0: krate::malloc
+/// ```
+pub(crate) fn format_wp_summary(wp_summary: &WPSummary,
+                                summaries: &FxHashMap<FnID, Summary>,
+                                reachable: &FxHashSet<FnID>) -> String {
+    let mut fns = wp_summary.iter().map(|(fn_id, def_sites)| {
+        let name = summaries.get(fn_id).map_or(format!("{:?}", fn_id), |s| s.name());
+        (name, fn_id, def_sites)
+    }).collect::<Vec<_>>();
+    fns.sort_by(|a, b| (&a.0, a.1).cmp(&(&b.0, b.1)));
+
+    let mut report = String::new();
+    for (name, fn_id, def_sites) in fns {
+        report += &name;
+        if !reachable.contains(fn_id) {
+            report += " (unreachable)";
+        }
+        report += "\n";
+        let mut lines = def_sites.iter().map(|def_site| {
+            let (kind, bb) = match def_site {
+                DefSite::HeapAlloc(bb) => ("HeapAlloc", bb),
+                DefSite::NativeCall(bb) => ("NativeCall", bb),
+                DefSite::OtherCall(bb) => ("OtherCall", bb),
+                DefSite::DropInPlace(bb) => ("DropInPlace", bb),
+                DefSite::Uninit(bb) => ("Uninit", bb),
+                DefSite::Transmute(bb) => ("Transmute", bb),
+                DefSite::RawSlice(bb) => ("RawSlice", bb),
+                DefSite::Arg(arg) => return format!("Arg {}", arg),
+                DefSite::Global(_) => return format!("{:?}", def_site),
+                DefSite::UnionRead(bb) => return format!("UnionRead at BB{}", bb),
+                DefSite::IntToPtr(bb) => return format!("IntToPtr at BB{}", bb),
+                DefSite::BoxAlloc(bb) => return format!("BoxAlloc at BB{}", bb),
+            };
+            // The callees of the call at the BB.
+            let callees = summaries.get(fn_id).map_or(Vec::new(), |summary| {
+                summary.callees.iter().filter(|callee| callee.arg_defs.contains_key(bb))
+                    .map(|callee| callee.name()).collect::<Vec<_>>()
+            });
+            if callees.is_empty() {
+                format!("{} at BB{}", kind, bb)
+            } else {
+                format!("{} at BB{}: {}", kind, bb, callees.join(", "))
+            }
+        }).collect::<Vec<_>>();
+        lines.sort();
+        for line in lines {
+            report += &format!("    {}\n", line);
+        }
+    }
+
+    report
+}
+
+/// Format the unsafe sites found by the WPA, one per line: where unsafe heap
+/// memory is freed, what each unsafe heap allocation allocates, possible leaks
+/// of unsafe heap memory, unsafe sources in code that the program never runs,
+/// self-referential unsafe structure candidates, and the longest propagation
+/// chain of an unsafe source.
+pub(crate) fn format_site_report(wpa_result: &WpaResult<'_>, summaries: &FxHashMap<FnID, Summary>)
+    -> SandboxResult<String> {
+    let mut report = String::new();
+    for (dealloc_site, allocs) in wpa_result.dealloc_pairs()? {
+        report += &format!("Unsafe deallocation: {} BB{} frees {} allocation(s)\n",
+            summaries[&dealloc_site.fn_id].name(), dealloc_site.bb, allocs.len());
+    }
+    for (fn_id, def_site, alloc_type) in wpa_result.unsafe_alloc_types() {
+        report += &format!("Unsafe heap allocation of {}: {} {:?}\n",
+            alloc_type, summaries[&fn_id].name(), def_site);
+    }
+    for (fn_id, def_site) in wpa_result.unpaired_allocs()? {
+        report += &format!("Possibly leaked unsafe allocation: {} {:?}\n",
+            summaries[&fn_id].name(), def_site);
+    }
+    for fn_id in wpa_result.unreachable_unsafe_fns() {
+        report += &format!("Unsafe source in unreachable fn: {}\n",
+            summaries.get(&fn_id).map_or(format!("{:?}", fn_id), |s| s.name()));
+    }
+    for (fn_id, def_site) in wpa_result.self_referential_allocs() {
+        report += &format!("Self-referential unsafe structure candidate: {} {:?}\n",
+            summaries[&fn_id].name(), def_site);
+    }
+    if wpa_result.max_propagation_depth() > 0 {
+        report += &format!("Longest propagation chain ({} hops): {}\n",
+            wpa_result.max_propagation_depth(), wpa_result.longest_chain().join(" -> "));
+    }
+
+    Ok(report)
+}
+
+/// Write the result of the WPA to a file that will be used by all compile units,
+/// and a human-readable report of it, followed by @site_report, to a sibling
+/// file.
 ///
 /// Since we just deleted the directory of summaries, here we simply put
 /// the overall summary file in "/tmp".
-fn write_wpa_summary(summary: WPSummary) {
+fn write_wpa_summary(summary: WPSummary, summaries: &FxHashMap<FnID, Summary>,
+                     reachable: &FxHashSet<FnID>, site_report: &str) -> SandboxResult<()> {
+    fs::write(get_wp_report_path(),
+              format_wp_summary(&summary, summaries, reachable) + site_report)?;
+    let serialized = serialize_wp_summary(&summary, reachable)?;
+    if let Some(path) = get_crate_wp_summary_path() {
+        fs::write(path, &serialized)?;
+    }
+    fs::write(get_wp_summary_path(), serialized)?;
+
+    Ok(())
+}
+
+/// Serialize the whole-program summary, which is read back as UnsafeSources.
+/// Each function is tagged with whether it is in @reachable. The functions
+/// are sorted by FnID and the def sites of each function are sorted, so that
+/// the same summary is always serialized as the same bytes.
+pub(crate) fn serialize_wp_summary(summary: &WPSummary, reachable: &FxHashSet<FnID>)
+    -> SandboxResult<String> {
     // We need to move the analysis results to a vector because the original
     // summary's key is FnID, which is not a string and thus cannot be
     // serialized by serde_json.
-    let mut summary_vec = UnsafeSources::new();
+    let mut summary_vec = Vec::<(FnID, Vec<DefSite>, bool)>::new();
     for (fn_id, def_sites) in summary {
-        summary_vec.push((fn_id, def_sites));
+        let mut def_sites = def_sites.iter().copied().collect::<Vec<_>>();
+        def_sites.sort();
+        summary_vec.push((*fn_id, def_sites, reachable.contains(fn_id)));
     }
-    let serialized = serde_json::to_string(&summary_vec).unwrap();
-    fs::write(get_wp_summary_path(), &serialized).expect(
-        "Write whole-program summary to file");
+    summary_vec.sort();
+
+    Ok(serde_json::to_string(&summary_vec)?)
 }
 
-/// Build the call graph using all the fn summaries.
-fn build_call_graph<'a>(summaries: &'a FxHashMap<FnID, Summary>) -> CallGraph<'a> {
-    let mut cg = CallGraph(FxHashMap::default());
-    for (caller_id, summary) in summaries {
-        // Create a new CallGraphNode for the current fn if not exist.
-        if !cg.0.contains_key(caller_id) {
-            cg.0.insert(*caller_id, CallGraphNode {
-                crate_name: &summary.crate_name,
-                fn_name: &summary.fn_name,
-                callees: FxHashSet::default(),
-                callers: FxHashSet::default()
-            });
+/// Resolve each call to a Fn/FnMut/FnOnce method on a trait object to all the
+/// closures that are coerced to a Fn trait object in the whole program.
+///
+/// Each such closure is added as a (dyn) Callee of the call. A call of a Fn
+/// method has two arguments: the callable and the tupled arguments, while the
+/// body of a closure takes its environment followed by each argument
+/// untupled. So the def sites of the callable are used for the environment,
+/// and the def sites of the tuple are used for each untupled argument.
+pub(crate) fn resolve_dyn_fn_calls(summaries: &mut FxHashMap<FnID, Summary>) {
+    let mut closures = Vec::<(Callee, u32)>::new();
+    for summary in summaries.values() {
+        for (closure, arg_count) in &summary.dyn_fn_closures {
+            if !closures.iter().any(|(c, _)| c.fn_id == closure.fn_id) {
+                closures.push((closure.clone(), *arg_count));
+            }
         }
+    }
+    if closures.is_empty() { return; }
 
-        // Iterate over this fn's callee list and writes each caller-callee
-        // data to CallGraph.
-        for callee in &summary.callees {
-            let callee_id = callee.fn_id;
-            // Add callee to caller's callee set.
-            cg.0.get_mut(&caller_id).unwrap().callees.insert(callee_id);
-            // Add caller to callee's caller set.
-            if let Some(callee_node) = cg.0.get_mut(&callee_id) {
-                callee_node.callers.insert(*caller_id);
-            } else {
-                let mut callee_node = CallGraphNode {
-                    crate_name: &callee.crate_name,
-                    fn_name: &callee.fn_name,
-                    callees: FxHashSet::default(),
-                    callers: FxHashSet::default()
-                };
-                callee_node.callers.insert(*caller_id);
-                cg.0.insert(callee_id, callee_node);
+    for summary in summaries.values_mut() {
+        for bb in summary.dyn_fn_calls.clone() {
+            let Some(call) = summary.get_callee_bb(bb).first().copied() else {
+                continue;
+            };
+            let (Some(callable_defs), Some(tuple_defs)) =
+                (call.get_arg_defs(bb, 1).cloned(), call.get_arg_defs(bb, 2).cloned()) else {
+                continue;
+            };
+            for (closure, arg_count) in &closures {
+                let mut arg_defs = vec![callable_defs.clone()];
+                for _ in 1..*arg_count {
+                    arg_defs.push(tuple_defs.clone());
+                }
+                if let Some(callee) = summary.callees.iter_mut()
+                    .find(|callee| callee.fn_id == closure.fn_id) {
+                    callee.arg_defs.insert(bb, arg_defs);
+                } else {
+                    let mut callee = closure.clone();
+                    callee.arg_defs.insert(bb, arg_defs);
+                    summary.callees.push(callee);
+                }
+                summary.dyn_callees.insert(closure.fn_id);
             }
         }
     }
+}
+
+/// Build the call graph using all the fn summaries.
+pub(crate) fn build_call_graph<'a>(summaries: &'a FxHashMap<FnID, Summary>) -> CallGraph<'a> {
+    let mut cg = CallGraph(FxHashMap::default());
+    for summary in summaries.values() {
+        cg.update_node(None, summary);
+    }
 
     cg
 }
@@ -172,10 +640,88 @@ fn update_wp_summary(wp_summary: &mut WPSummary,
     }
 }
 
+/// The contributors to the return value of a function that do not depend on
+/// the call to it: its non-arg ret_defs and, transitively, those of the
+/// callees whose return values flow to its return value.
+#[derive(Default, Clone)]
+struct RetSources {
+    /// Terminal sources (see DefSite::is_terminal_source()), in any of the
+    /// functions.
+    sources: FxHashSet<GlobalDefSite>,
+    /// The calls whose return values flow to the return value. The def sites
+    /// of their arguments depend on the caller and are traced per call.
+    calls: FxHashSet<GlobalDefSite>,
+    /// The functions whose non-arg ret_defs are included.
+    fns: FxHashSet<FnID>,
+}
+
+/// Compute the RetSources of each function bottom-up over the SCCs of the
+/// call graph (see CallGraph::sccs()). The callees in other SCCs are done
+/// before an SCC, and the functions of the SCC are iterated until their
+/// RetSources no longer change, so a cycle of recursive calls is analyzed
+/// once for all the calls to its functions.
+fn compute_ret_sources<'a>(cg: &CallGraph<'a>)
+    -> SandboxResult<FxHashMap<FnID, RetSources>> {
+    let mut ret_sources = FxHashMap::<FnID, RetSources>::default();
+    for scc in cg.sccs() {
+        let mut changed = true;
+        while changed {
+            changed = false;
+            for fn_id in &scc {
+                let Some(summary) = cg.get_summary(fn_id) else { continue; };
+                let mut sources = ret_sources.remove(fn_id).unwrap_or_default();
+                let old_len = (sources.sources.len(), sources.calls.len(), sources.fns.len());
+                sources.fns.insert(*fn_id);
+                for def_site in &summary.ret_defs.0 {
+                    let def_site_glob = GlobalDefSite { fn_id: *fn_id, def_site: *def_site };
+                    match def_site {
+                        _ if def_site.is_terminal_source() => {
+                            sources.sources.insert(def_site_glob);
+                        },
+                        DefSite::OtherCall(bb) => {
+                            sources.calls.insert(def_site_glob);
+                            for callee in summary.get_callee_bb(*bb) {
+                                if summary.is_foreign_callee(&callee.fn_id) {
+                                    continue;
+                                }
+                                let Some(callee_sources) = ret_sources.get(&callee.fn_id) else {
+                                    continue;
+                                };
+                                sources.sources.extend(callee_sources.sources.iter().copied());
+                                sources.calls.extend(callee_sources.calls.iter().copied());
+                                sources.fns.extend(callee_sources.fns.iter().copied());
+                            }
+                        },
+                        _ => {
+                            return Err(SandboxError::UnexpectedDefSite {
+                                def_site: format!("{:?}", def_site),
+                                fn_name: summary.name(),
+                            });
+                        }
+                    }
+                }
+                changed |= old_len !=
+                    (sources.sources.len(), sources.calls.len(), sources.fns.len());
+                ret_sources.insert(*fn_id, sources);
+            }
+        }
+    }
+
+    Ok(ret_sources)
+}
+
 /// Find unsafe heap allocation sites. We use a worklist-based algorithm to
 /// handle the recursive nature of the process of finding def site. There are
-/// four variants of DefSite. HeapAlloc means a heap alloc site is found.
-/// NativeCall is ignored because we do not analyze native libraries.
+/// several variants of DefSite. HeapAlloc and BoxAlloc mean a heap alloc site
+/// is found, and Uninit, a possibly invalid value, Global, a static or thread-local
+/// variable, UnionRead, a read of a union field, Transmute, RawSlice, a
+/// slice built from a raw pointer, and IntToPtr, a pointer cast from an
+/// integer, are collected the same way. The taint a transmute, a raw slice or
+/// an integer-to-pointer cast inherits from its operand is already tracked by
+/// the def sites of the operand.
+/// NativeCall is a call to a native function that originates the pointer it
+/// returns (see is_native_ptr_origin()). We do not analyze native libraries,
+/// so it is collected as where the unsafe value originates, like HeapAlloc.
 /// OtherCall is the most complex case. We need to find the def site for the
 /// return value of the callee, and those def sites have two types:
 /// arguments of the callee, which come from the def sites in the body of the
@@ -183,127 +729,297 @@ fn update_wp_summary(wp_summary: &mut WPSummary,
 /// The last type is Arg. We need to examine all the callers of the
 /// currently processed function to find the def sites in the callers that
 /// contribute to the target arguments of the call to the callee.
-fn find_unsafe_alloc<'a>(summaries: &FxHashMap<FnID, Summary>,
-                         cg: &CallGraph<'a>,
-                         wp_summary: &mut WPSummary) {
+fn find_unsafe_alloc<'a>(cg: &CallGraph<'a>,
+                         ret_sources: &FxHashMap<FnID, RetSources>,
+                         wp_summary: &mut WPSummary) -> SandboxResult<()> {
     // A worklist of GlobalDefSite to be processed.
     let mut to_process = VecDeque::<GlobalDefSite>::new();
-    // Record processed def sites to prevent infinite loop.
-    let mut processed = FxHashSet::<GlobalDefSite>::default();
 
     // Init: Put unsafe def sites collected from unsafe_def to the worklist.
-    for (fn_id, summary) in summaries {
+    for summary in cg.summaries() {
         if let Some(unsafe_defs) = &summary.unsafe_defs {
             for def_site in unsafe_defs {
                 to_process.push_back(GlobalDefSite {
-                    fn_id: *fn_id,
+                    fn_id: summary.fn_id,
                     def_site: *def_site
                 });
             }
         }
     }
 
+    trace_to_alloc(cg, ret_sources, to_process, wp_summary)?;
+
+    // Count the number of unsafe heap alloc. The unsafe sources of each kind
+    // are counted per crate in the statistics; see WpaResult::stats().
+    let mut unsafe_alloc = 0;
+    for (_, def_sites) in wp_summary.iter() {
+        unsafe_alloc += def_sites.len();
+    }
+    println!("Unsafe heap alloc #: {}", unsafe_alloc);
+
+    Ok(())
+}
+
+/// Find the heap allocation sites that the def sites in a worklist come from,
+/// inter-procedurally. See find_unsafe_alloc() for the algorithm.
+///
+/// Inputs:
+/// @ret_sources: The RetSources of each function; see compute_ret_sources().
+/// @to_process: The worklist of the def sites to start from.
+/// @wp_summary: The result heap allocation sites of each function.
+fn trace_to_alloc<'a>(cg: &CallGraph<'a>,
+                      ret_sources: &FxHashMap<FnID, RetSources>,
+                      mut to_process: VecDeque<GlobalDefSite>,
+                      wp_summary: &mut WPSummary) -> SandboxResult<()> {
+    // Record processed def sites to prevent infinite loop.
+    let mut processed = FxHashSet::<GlobalDefSite>::default();
+    // Functions whose non-arg contributors to the return value have been
+    // examined. They do not depend on the call, so each function is examined
+    // only once, and with it all the functions in its RetSources.
+    let mut ret_examined = FxHashSet::<FnID>::default();
+
     // Worklist-based algorithm.
-    while !to_process.is_empty() {
-        let def_site_glob = to_process.pop_front().unwrap();
-        if processed.contains(&def_site_glob) {
+    while let Some(def_site_glob) = to_process.pop_front() {
+        if !processed.insert(def_site_glob) {
             continue;
         }
-        processed.insert(def_site_glob);
 
-        let (fn_id, def_site) = (def_site_glob.fn_id, def_site_glob.def_site);
-        match def_site {
-            DefSite::HeapAlloc(_) => {
-                // Found a heap allocation site. Put it to results.
-                update_wp_summary(wp_summary, &fn_id, &def_site);
-            },
-            DefSite::NativeCall(_) => {
-                // No need to do anything as we do not analyze native fn.
-            },
-            DefSite::OtherCall(bb) => {
-                // Find all the DefSite that contribute to the return value
-                // of the callee in bb. There are might be multiple callees
-                // due to trait object.
-                let caller_summary = summaries.get(&fn_id).unwrap();
-                for callee in caller_summary.get_callee_bb(bb) {
-                    let callee_id = callee.fn_id;
-                    if caller_summary.is_foreign_callee(&callee_id) {
-                        // Skip FFI calls.
+        let step = trace_step(cg, ret_sources, def_site_glob, &mut ret_examined)?;
+        for source in &step.sources {
+            update_wp_summary(wp_summary, &source.fn_id, &source.def_site);
+        }
+        to_process.extend(step.next);
+    }
+
+    Ok(())
+}
+
+/// Where a def site is traced to in one step of trace_to_alloc().
+#[derive(Default)]
+struct TraceStep {
+    /// Terminal sources the def site comes from (see
+    /// DefSite::is_terminal_source()).
+    sources: Vec<GlobalDefSite>,
+    /// Def sites the def site comes from that are to be traced further.
+    next: Vec<GlobalDefSite>,
+}
+
+/// Trace a def site one step back to where it comes from.
+///
+/// Inputs:
+/// @ret_sources: The RetSources of each function; see compute_ret_sources().
+/// @def_site_glob: The def site to trace.
+/// @ret_examined: Functions whose RetSources have been added to a previous
+///                step. They are not added again, and the functions in the
+///                RetSources of a newly examined function are added to it.
+fn trace_step<'a>(cg: &CallGraph<'a>,
+                  ret_sources: &FxHashMap<FnID, RetSources>,
+                  def_site_glob: GlobalDefSite,
+                  ret_examined: &mut FxHashSet<FnID>) -> SandboxResult<TraceStep> {
+    let mut step = TraceStep::default();
+    let (fn_id, def_site) = (def_site_glob.fn_id, def_site_glob.def_site);
+    match def_site {
+        _ if def_site.is_terminal_source() => {
+            // Found where the unsafe value originates, e.g., a heap
+            // allocation site or a native call, as we do not analyze native
+            // fn. Put it to results.
+            step.sources.push(def_site_glob);
+        },
+        DefSite::DropInPlace(_) => {
+            // Not a source of unsafe memory.
+        },
+        DefSite::OtherCall(bb) => {
+            // Find all the DefSite that contribute to the return value of the
+            // callee in bb. There are might be multiple callees due to trait
+            // object.
+            let caller_summary = cg.get_summary(&fn_id).unwrap();
+            let callees = caller_summary.get_callee_bb(bb);
+            if callees.is_empty() {
+                // The call was recorded but its callee was not, e.g., it has
+                // been filtered out. Nothing to propagate.
+                if _DEBUG {
+                    println!("No callee at BB{} of {}", bb, caller_summary.name());
+                }
+                return Ok(step);
+            }
+            for callee in callees {
+                let callee_id = callee.fn_id;
+                if caller_summary.is_foreign_callee(&callee_id) {
+                    // Skip FFI calls.
+                    continue;
+                }
+                let callee_summary = cg.get_summary(&callee_id);
+                if callee_summary.is_none() {
+                    // With SANDBOX_UNSAFE_ROOTS_ONLY=1, a callee in another
+                    // crate is not summarized unless it is rooted there; see
+                    // select_unsafe_rooted().
+                    if caller_summary.is_dyn_callee(&callee_id) || unsafe_roots_only() {
                         continue;
                     }
-                    let callee_summary = summaries.get(&callee_id);
-                    if callee_summary.is_none() {
-                        if caller_summary.is_dyn_callee(&callee_id) {
-                            continue;
-                        }
-                        panic!("Cannot find callee {}, called by {}",
-                            callee.name(), caller_summary.name());
-                    }
+                    return Err(SandboxError::MissingCallee {
+                        callee: callee.name(),
+                        caller: caller_summary.name(),
+                    });
+                }
 
-                    let callee_summary = callee_summary.unwrap();
-                    for def_site in &callee_summary.ret_defs.0 {
-                        // Examine non-arg contributors to the return value.
-                        match def_site {
-                            DefSite::HeapAlloc(_) => {
-                                // Found a heap alloc site.
-                                update_wp_summary(wp_summary, &callee_id, &def_site);
-                            },
-                            DefSite::OtherCall(_) => {
-                                to_process.push_back(GlobalDefSite {
-                                    fn_id: callee_id,
-                                    def_site: *def_site
+                let callee_summary = callee_summary.unwrap();
+                if ret_examined.insert(callee_id) &&
+                    let Some(sources) = ret_sources.get(&callee_id) {
+                    // Examine non-arg contributors to the return value.
+                    step.sources.extend(sources.sources.iter().copied());
+                    step.next.extend(sources.calls.iter().copied());
+                    ret_examined.extend(sources.fns.iter().copied());
+                }
+                for def_site in &callee_summary.ret_defs.1 {
+                    // Examine argument contributors to the return value.
+                    match def_site {
+                        DefSite::Arg(arg) => {
+                            // Skip an invalid argument instead of panicking.
+                            let arg_defs = callee.get_arg_defs(bb, *arg);
+                            for arg_def in arg_defs.into_iter().flatten() {
+                                step.next.push(GlobalDefSite {
+                                    fn_id: fn_id,
+                                    def_site: *arg_def,
                                 });
-                            },
-                            _ => {
-                                panic!("Not a DefSite::HeapAlloc or OtherCall");
-                            }
-                        }
-                    }
-                    for def_site in &callee_summary.ret_defs.1 {
-                        // Examine argument contributors to the return value.
-                        match def_site {
-                            DefSite::Arg(arg) => {
-                                for arg_def in callee.get_arg_defs(bb, *arg) {
-                                    to_process.push_back(GlobalDefSite {
-                                        fn_id: fn_id,
-                                        def_site: *arg_def,
-                                    });
-                                }
-                            },
-                            _ => {
-                                panic!("Not a DefSite::Arg");
                             }
+                        },
+                        _ => {
+                            return Err(SandboxError::UnexpectedDefSite {
+                                def_site: format!("{:?}", def_site),
+                                fn_name: callee_summary.name(),
+                            });
                         }
                     }
-
                 }
-            },
-            DefSite::Arg(arg_loc) => {
-                // Examine all callers of fn_id to find their corresponding
-                // calls to fn_id, and then find the def sites of the target
-                // argument in the calls.
-                for caller_id in cg.get_callers(&fn_id) {
-                    let caller_sumamry = summaries.get(caller_id).unwrap();
-                    let callee = caller_sumamry.get_callee_global(&fn_id);
-                    for arg_defs in callee.arg_defs.values() {
-                        for def_site in &arg_defs[(arg_loc - 1) as usize] {
-                            to_process.push_back(GlobalDefSite {
-                                fn_id: *caller_id,
-                                def_site: *def_site,
-                            });
-                        }
+            }
+        },
+        DefSite::Arg(arg_loc) => {
+            // Examine all callers of fn_id to find their corresponding calls
+            // to fn_id, and then find the def sites of the target argument in
+            // the calls.
+            for caller_id in cg.get_callers(&fn_id) {
+                let caller_sumamry = cg.get_summary(caller_id).unwrap();
+                let callee = caller_sumamry.get_callee_global(&fn_id);
+                for bb in callee.arg_defs.keys() {
+                    let arg_defs = callee.get_arg_defs(*bb, arg_loc);
+                    for def_site in arg_defs.into_iter().flatten() {
+                        step.next.push(GlobalDefSite {
+                            fn_id: *caller_id,
+                            def_site: *def_site,
+                        });
                     }
                 }
             }
         }
     }
 
-    // Count the number of unsafe heap alloc
-    let mut unsafe_alloc = 0;
-    for (_, def_sites) in wp_summary {
-        unsafe_alloc += def_sites.len();
+    Ok(step)
+}
+
+/// Trace the def sites of each deallocation site (see find_dealloc_sites())
+/// to the terminal sources they come from.
+///
+/// Unlike running trace_to_alloc() per deallocation site, each def site is
+/// traced only once for all the deallocation sites. The sources of a def site
+/// are then its own and those of the def sites it is traced to, which are
+/// iterated until no longer changed as the def sites may form cycles, e.g.,
+/// through recursive calls.
+fn trace_dealloc_sites<'a>(cg: &CallGraph<'a>,
+                           ret_sources: &FxHashMap<FnID, RetSources>)
+    -> SandboxResult<Vec<(DeallocSite, FxHashSet<GlobalDefSite>)>> {
+    let dealloc_sites = find_dealloc_sites(cg);
+
+    // Trace each def site reachable from any deallocation site. The RetSources
+    // of a callee are added to every call to it, so that each def site has
+    // all of its own sources.
+    let mut steps = FxHashMap::<GlobalDefSite, TraceStep>::default();
+    let mut order = Vec::<GlobalDefSite>::new();
+    let mut to_process = dealloc_sites.iter()
+        .flat_map(|(_, def_sites)| def_sites.iter().copied())
+        .collect::<VecDeque<_>>();
+    while let Some(def_site_glob) = to_process.pop_front() {
+        if steps.contains_key(&def_site_glob) {
+            continue;
+        }
+        let step = trace_step(cg, ret_sources, def_site_glob, &mut FxHashSet::default())?;
+        to_process.extend(step.next.iter().copied());
+        steps.insert(def_site_glob, step);
+        order.push(def_site_glob);
     }
-    println!("Unsafe heap alloc #: {}", unsafe_alloc);
+
+    // Later def sites are mostly traced to from earlier ones, so iterating in
+    // reverse order usually needs a single round.
+    let mut sources = order.iter()
+        .map(|def_site_glob| {
+            (*def_site_glob, steps[def_site_glob].sources.iter().copied().collect())
+        })
+        .collect::<FxHashMap<GlobalDefSite, FxHashSet<GlobalDefSite>>>();
+    let mut changed = true;
+    while changed {
+        changed = false;
+        for def_site_glob in order.iter().rev() {
+            let mut reached = std::mem::take(sources.get_mut(def_site_glob).unwrap());
+            let old_len = reached.len();
+            for next in &steps[def_site_glob].next {
+                reached.extend(sources[next].iter().copied());
+            }
+            changed |= reached.len() != old_len;
+            sources.insert(*def_site_glob, reached);
+        }
+    }
+
+    Ok(dealloc_sites.into_iter().map(|(dealloc_site, def_sites)| {
+        let reached = def_sites.iter()
+            .flat_map(|def_site_glob| sources[def_site_glob].iter().copied())
+            .collect();
+        (dealloc_site, reached)
+    }).collect())
+}
+
+/// Find the deallocation sites of all the functions, each with the local def
+/// sites of the pointer it frees. The result is sorted by DeallocSite.
+fn find_dealloc_sites<'a>(cg: &CallGraph<'a>) -> Vec<(DeallocSite, Vec<GlobalDefSite>)> {
+    let mut dealloc_sites = FxHashMap::<DeallocSite, Vec<GlobalDefSite>>::default();
+    for summary in cg.summaries() {
+        let fn_id = summary.fn_id;
+        // Def sites of the Place dropped in the function.
+        for (bb, def_sites) in &summary.drop_defs {
+            dealloc_sites.entry(DeallocSite { fn_id, bb: *bb }).or_default()
+                .extend(def_sites.iter().map(|def_site| GlobalDefSite {
+                    fn_id, def_site: *def_site
+                }));
+        }
+        // Def sites of the pointer passed to a deallocation function.
+        for callee in &summary.callees {
+            if !summary.is_free_callee(&callee.fn_id) { continue; }
+            for (bb, arg_defs) in &callee.arg_defs {
+                dealloc_sites.entry(DeallocSite { fn_id, bb: *bb }).or_default()
+                    .extend(arg_defs.first().into_iter().flatten().map(|def_site| {
+                        GlobalDefSite { fn_id, def_site: *def_site }
+                    }));
+            }
+        }
+    }
+
+    let mut dealloc_sites = dealloc_sites.into_iter().collect::<Vec<_>>();
+    dealloc_sites.sort_by_key(|(dealloc_site, _)| *dealloc_site);
+
+    dealloc_sites
+}
+
+/// Find the heap allocation sites that may be deallocated, i.e., whose memory
+/// may reach any deallocation site (see find_dealloc_sites()) on any path in
+/// any function.
+fn find_freed_alloc<'a>(cg: &CallGraph<'a>,
+                        ret_sources: &FxHashMap<FnID, RetSources>)
+    -> SandboxResult<WPSummary> {
+    let to_process = find_dealloc_sites(cg).into_iter()
+        .flat_map(|(_, def_sites)| def_sites).collect::<VecDeque<_>>();
+
+    let mut freed = WPSummary::default();
+    trace_to_alloc(cg, ret_sources, to_process, &mut freed)?;
+
+    Ok(freed)
 }
 
 /// Find unsafe fn arguments and non-heap-alloc calls that return unsafe value.
@@ -322,20 +1038,24 @@ fn find_unsafe_alloc<'a>(summaries: &FxHashMap<FnID, Summary>,
 /// Second, if an unsafe source contributes to the return value of the function
 /// that contains it, then for all the callers of this function, the calls to
 /// it are also unsafe sources.
-fn find_unsafe_arg_call<'a>(summaries: &FxHashMap<FnID, Summary>,
-                            cg: &CallGraph<'a>,
-                            wp_summary: &mut WPSummary) {
+///
+/// If @unsafe_fn_calls is true, the calls to unsafe functions are also put to
+/// the worklist as unsafe sources; see find_unsafe_fn_calls().
+///
+/// It returns the longest propagation chain, and records the deallocation
+/// sites reached by unsafe sources in @unsafe_deallocs; see
+/// propagate_unsafe_sources().
+fn find_unsafe_arg_call<'a>(cg: &CallGraph<'a>, wp_summary: &mut WPSummary,
+                            unsafe_deallocs: &mut FxHashSet<DeallocSite>,
+                            unsafe_fn_calls: bool) -> Vec<GlobalDefSite> {
     // A worklist of GlobalDefSite to be processed.
     let mut to_process = VecDeque::<GlobalDefSite>::new();
-    // Record processed GlobalDefSite to prevent infinite loop.
-    let mut processed = FxHashSet::<GlobalDefSite>::default();
 
     // Init: Put all the unsafe heap allocation sites to the worklist.
     for (fn_id, def_sites) in wp_summary.iter() {
         for def_site in def_sites {
-            // Ensure all the DefSite collected before are HeapAlloc.
-            assert!(matches!(*def_site, DefSite::HeapAlloc(_)),
-                "Not a heap allocation");
+            // Ensure all the DefSite collected before are terminal sources.
+            assert!(def_site.is_terminal_source(), "Not a terminal source: {:?}", def_site);
             to_process.push_back(GlobalDefSite {
                 fn_id: *fn_id,
                 def_site: *def_site
@@ -343,83 +1063,469 @@ fn find_unsafe_arg_call<'a>(summaries: &FxHashMap<FnID, Summary>,
         }
     }
 
+    if unsafe_fn_calls {
+        for unsafe_call_site in find_unsafe_fn_calls(cg) {
+            update_wp_summary(wp_summary, &unsafe_call_site.fn_id,
+                              &unsafe_call_site.def_site);
+            to_process.push_back(unsafe_call_site);
+        }
+    }
+
+    let longest_chain = propagate_unsafe_sources(cg, to_process, wp_summary,
+                                                 unsafe_deallocs);
+
+    // Print out the number of fn with unsafe sources, and the total number
+    // of functions analyzed.
+    println!("Unsafe_fn / total_fn: {} / {}", wp_summary.len(), cg.summaries().count());
+
+    longest_chain
+}
+
+/// Find the calls to the functions that are unsafe as a whole, i.e., unsafe fn
+/// (see Summary::is_unsafe_fn). The caller of an unsafe fn asserts the
+/// invariants that the callee relies on, so the value returned by the call may
+/// be treated as an unsafe source.
+fn find_unsafe_fn_calls<'a>(cg: &CallGraph<'a>) -> Vec<GlobalDefSite> {
+    let mut unsafe_call_sites = Vec::new();
+    for summary in cg.summaries() {
+        if !summary.is_unsafe_fn {
+            continue;
+        }
+        for caller_id in cg.get_callers(&summary.fn_id) {
+            let caller_summary = cg.get_summary(caller_id).unwrap();
+            let callee = caller_summary.get_callee_global(&summary.fn_id);
+            for call_site in callee.arg_defs.keys() {
+                unsafe_call_sites.push(GlobalDefSite {
+                    fn_id: *caller_id,
+                    def_site: DefSite::OtherCall(*call_site)
+                });
+            }
+        }
+    }
+
+    unsafe_call_sites
+}
+
+/// Core procedure of find_unsafe_arg_call. Starting from the unsafe sources in
+/// the worklist, propagate them to callee arguments and to the calls to the
+/// functions that return them, and record the new unsafe sources in wp_summary.
+///
+/// Each propagation step crosses a function boundary. The parent of each
+/// GlobalDefSite, i.e., the one it is first propagated from, is recorded.
+/// Since the worklist is FIFO, following the parents gives the shortest chain
+/// from an initial unsafe source. It returns the longest of these chains,
+/// which measures how far the worst unsafe source reaches.
+///
+/// An unsafe source is also followed into the deallocation sites that free it,
+/// i.e., a Drop or a deallocation call whose pointer it defines. Such sites are
+/// recorded in @unsafe_deallocs for pairing unsafe allocations with frees.
+fn propagate_unsafe_sources<'a>(cg: &CallGraph<'a>,
+                                mut to_process: VecDeque<GlobalDefSite>,
+                                wp_summary: &mut WPSummary,
+                                unsafe_deallocs: &mut FxHashSet<DeallocSite>)
+                                -> Vec<GlobalDefSite> {
+    // Record processed GlobalDefSite to prevent infinite loop.
+    let mut processed = FxHashSet::<GlobalDefSite>::default();
+    // Functions whose return value is known to be unsafe. The calls to such a
+    // function are unsafe no matter which def site taints the return value,
+    // so they are put to the worklist only once per function.
+    let mut unsafe_rets = FxHashSet::<FnID>::default();
+    // Parent of each GlobalDefSite, and the number of hops from its initial
+    // unsafe source.
+    let mut parents = FxHashMap::<GlobalDefSite, GlobalDefSite>::default();
+    let mut depths = to_process.iter().map(|site| (*site, 0))
+        .collect::<FxHashMap<GlobalDefSite, usize>>();
+    let mut deepest = to_process.front().copied();
+    let mut max_depth = 0;
+
     // A worklist-based algorithm.
     while !to_process.is_empty() {
         let def_site_glob = to_process.pop_front().unwrap();
         if !processed.insert(def_site_glob) {
             continue;
         }
+        let depth = depths[&def_site_glob];
+        if depth > max_depth {
+            max_depth = depth;
+            deepest = Some(def_site_glob);
+        }
+        // Record the parent of a newly found GlobalDefSite.
+        let mut add_child = |child: GlobalDefSite| {
+            if !depths.contains_key(&child) {
+                depths.insert(child, depth + 1);
+                parents.insert(child, def_site_glob);
+            }
+        };
 
         // For the currently-processed unsafe GlobalDefSite, get the FnID of the
         // function that contains it, and the local DefSite of it.
         let (fn_id, def_site) = (def_site_glob.fn_id, def_site_glob.def_site);
-        match def_site {
-            DefSite::HeapAlloc(_) | DefSite::OtherCall(_) | DefSite::Arg(_) => {
-                let fn_summary = summaries.get(&fn_id);
-                if fn_summary.is_none() {
-                    // It is possible that fn_id is a native library function.
-                    // This happens for DefSite:Arg.
+        if !def_site.is_terminal_source() &&
+            !matches!(def_site, DefSite::OtherCall(_) | DefSite::Arg(_)) {
+            // Not a source of unsafe memory, e.g., DropInPlace.
+            continue;
+        }
+        let fn_summary = cg.get_summary(&fn_id);
+        if fn_summary.is_none() {
+            // It is possible that fn_id is a native library function.
+            // This happens for DefSite:Arg.
+            continue;
+        }
+        let fn_summary = fn_summary.unwrap();
+
+        // Find the Drop terminators in this function that free the
+        // unsafe source.
+        for (bb, def_sites) in &fn_summary.drop_defs {
+            if def_sites.contains(&def_site) {
+                unsafe_deallocs.insert(DeallocSite { fn_id, bb: *bb });
+            }
+        }
+
+        // Find calls in this function that use the unsafe source as an
+        // argument, and put the corresponding argument to the worklist.
+        for callee in &fn_summary.callees {
+            for (bb, all_arg_defs) in &callee.arg_defs {
+                if def_site.call_bb() == Some(*bb) {
+                    // Skip the unsafe call iteself.
                     continue;
                 }
-                let fn_summary = fn_summary.unwrap();
-
-                // Find calls in this function that use the unsafe source as an
-                // argument, and put the corresponding argument to the worklist.
-                for callee in &fn_summary.callees {
-                    for (bb, all_arg_defs) in &callee.arg_defs {
-                        match def_site {
-                            DefSite::HeapAlloc(unsafe_call) |
-                            DefSite::OtherCall(unsafe_call) => {
-                                if *bb == unsafe_call {
-                                    // Skip the unsafe call iteself.
-                                    continue;
-                                }
-                            },
-                            _ => {}
-                        }
 
-                        // Check if the def sites for any argument of a call
-                        // contains the target unsafe def_site.
-                        for arg in 1..=all_arg_defs.len() {
-                            if all_arg_defs[arg - 1].contains(&def_site) {
-                                let unsafe_arg = GlobalDefSite {
-                                    fn_id: callee.fn_id,
-                                    def_site: DefSite::Arg(arg as u32)
-                                };
-                                update_wp_summary(wp_summary, &unsafe_arg.fn_id,
-                                                  &unsafe_arg.def_site);
-                                to_process.push_back(unsafe_arg);
-                            }
-                        }
-                    }
+                // A deallocation function frees its first argument.
+                // It is usually a native function without a Summary,
+                // so record the call instead of following it.
+                if fn_summary.is_free_callee(&callee.fn_id) &&
+                    all_arg_defs.first().is_some_and(|defs| defs.contains(&def_site)) {
+                    unsafe_deallocs.insert(DeallocSite { fn_id, bb: *bb });
                 }
 
-                // If the current unsafe def_site contributes to the return of
-                // the current function, find all calls to this function and
-                // put them to the worklist.
-                if fn_summary.ret_defs_contains(&def_site) {
-                    for caller_id in cg.get_callers(&fn_id) {
-                        let caller_summary = summaries.get(caller_id).unwrap();
-                        let callee = caller_summary.get_callee_global(&fn_id);
-                        for call_site in callee.arg_defs.keys() {
-                            let unsafe_call_site = GlobalDefSite {
-                                fn_id: *caller_id,
-                                def_site: DefSite::OtherCall(*call_site)
-                            };
-                            update_wp_summary(wp_summary, &caller_id,
-                                              &unsafe_call_site.def_site);
-                            to_process.push_back(unsafe_call_site);
-                        }
+                // Check if the def sites for any argument of a call
+                // contains the target unsafe def_site.
+                for arg in 1..=all_arg_defs.len() {
+                    if all_arg_defs[arg - 1].contains(&def_site) {
+                        let unsafe_arg = GlobalDefSite {
+                            fn_id: callee.fn_id,
+                            def_site: DefSite::Arg(arg as u32)
+                        };
+                        update_wp_summary(wp_summary, &unsafe_arg.fn_id,
+                                          &unsafe_arg.def_site);
+                        add_child(unsafe_arg);
+                        to_process.push_back(unsafe_arg);
                     }
                 }
-            },
-            _ => {}
+            }
+        }
+
+        // If the current unsafe def_site contributes to the return of
+        // the current function, find all calls to this function and
+        // put them to the worklist.
+        if fn_summary.ret_defs_contains(&def_site) && unsafe_rets.insert(fn_id) {
+            for caller_id in cg.get_callers(&fn_id) {
+                let caller_summary = cg.get_summary(caller_id).unwrap();
+                let callee = caller_summary.get_callee_global(&fn_id);
+                for call_site in callee.arg_defs.keys() {
+                    let unsafe_call_site = GlobalDefSite {
+                        fn_id: *caller_id,
+                        def_site: DefSite::OtherCall(*call_site)
+                    };
+                    update_wp_summary(wp_summary, &caller_id,
+                                      &unsafe_call_site.def_site);
+                    add_child(unsafe_call_site);
+                    to_process.push_back(unsafe_call_site);
+                }
+            }
         }
     }
 
-    // Print out the number of fn with unsafe sources, and the total number
-    // of functions analyzed.
-    println!("Unsafe_fn / total_fn: {} / {}", wp_summary.len(), summaries.len());
+    // Follow the parents from the deepest GlobalDefSite back to its source.
+    let mut chain = Vec::new();
+    let mut site = deepest;
+    while let Some(curr) = site {
+        chain.push(curr);
+        site = parents.get(&curr).copied();
+    }
+    chain.reverse();
+
+    chain
+}
+
+/// Result of the whole-program analysis, together with the summaries and the
+/// call graph it is computed from, which are needed to query the result.
+pub struct WpaResult<'a> {
+    summaries: &'a FxHashMap<FnID, Summary>,
+    cg: CallGraph<'a>,
+    /// Non-arg contributors to the return value of each function.
+    ret_sources: FxHashMap<FnID, RetSources>,
+    /// Unsafe sources of each function.
+    pub wp_summary: WPSummary,
+    /// Deallocation sites that may free an unsafe source.
+    pub unsafe_deallocs: FxHashSet<DeallocSite>,
+    /// The longest chain of propagating an unsafe source across functions.
+    longest_chain: Vec<GlobalDefSite>,
+    /// Functions reachable from the entry points (see reachable_fns()).
+    pub reachable: FxHashSet<FnID>,
+}
+
+impl<'a> WpaResult<'a> {
+    /// Build the call graph and find all the unsafe sources of a program. The
+    /// calls to unsafe fn are unsafe sources if SANDBOX_UNSAFE_FN_CALLS is set
+    /// to 1; see with_unsafe_fn_calls().
+    pub fn new(summaries: &'a FxHashMap<FnID, Summary>) -> SandboxResult<WpaResult<'a>> {
+        WpaResult::with_unsafe_fn_calls(summaries, unsafe_fn_calls_as_sources())
+    }
+
+    /// Same as new(), but the calls to unsafe fn are unsafe sources if
+    /// @unsafe_fn_calls is true.
+    pub fn with_unsafe_fn_calls(summaries: &'a FxHashMap<FnID, Summary>, unsafe_fn_calls: bool)
+        -> SandboxResult<WpaResult<'a>> {
+        // Build a call graph.
+        let cg = build_call_graph(summaries);
+
+        // Whole-program summary for later analysis to find unsafe memory accesses.
+        // Question: Will it be a little faster to use Vec<DefSite> in the HashMap?
+        let mut wp_summary = WPSummary::default();
+
+        // Find unsafe heap allocations.
+        let ret_sources = compute_ret_sources(&cg)?;
+        find_unsafe_alloc(&cg, &ret_sources, &mut wp_summary)?;
+
+        // Find may-unsafe function arguments and non-heap-alloc calls, and
+        // where the unsafe sources are freed.
+        let mut unsafe_deallocs = FxHashSet::<DeallocSite>::default();
+        let longest_chain = find_unsafe_arg_call(&cg, &mut wp_summary,
+                                                 &mut unsafe_deallocs, unsafe_fn_calls);
+
+        let reachable = cg.reachable_fns();
+
+        Ok(WpaResult {
+            summaries, cg, ret_sources, wp_summary, unsafe_deallocs, longest_chain, reachable
+        })
+    }
+
+    /// Get the number of inter-procedural hops of the longest propagation
+    /// chain of an unsafe source.
+    pub fn max_propagation_depth(&self) -> usize {
+        self.longest_chain.len().saturating_sub(1)
+    }
+
+    /// Get each function in the call graph and its callees, sorted.
+    pub fn call_graph_edges(&self) -> Vec<(FnID, Vec<FnID>)> {
+        let mut edges = Vec::new();
+        for node in self.cg.0.values() {
+            let mut callees = node.callees.iter().copied().collect::<Vec<_>>();
+            callees.sort();
+            edges.push((node.fn_id, callees));
+        }
+        edges.sort();
+
+        edges
+    }
+
+    /// Get the call graph in the Graphviz DOT format, in which the functions
+    /// with unsafe sources are highlighted. See CallGraph::to_dot().
+    pub fn call_graph_dot(&self) -> String {
+        self.cg.to_dot(&self.wp_summary)
+    }
+
+    /// Get the functions ("crate::fn") along the longest propagation chain of
+    /// an unsafe source, starting from the function of the source.
+    pub fn longest_chain(&self) -> Vec<String> {
+        self.longest_chain.iter().map(|site| {
+            let node = self.cg.get(&site.fn_id);
+            format!("{}::{}", node.crate_name, node.fn_name)
+        }).collect()
+    }
+
+    /// Compute the "blast radius" of a function, i.e., the set of functions
+    /// that would have unsafe sources if the return value of the function were
+    /// unsafe. It seeds the calls to the function as unsafe sources and then
+    /// propagates them as find_unsafe_arg_call does.
+    pub fn blast_radius(&self, fn_id: &FnID) -> FxHashSet<FnID> {
+        let mut to_process = VecDeque::<GlobalDefSite>::new();
+        let mut wp_summary = WPSummary::default();
+        if let Some(node) = self.cg.0.get(fn_id) {
+            for caller_id in &node.callers {
+                let caller_summary = self.cg.get_summary(caller_id).unwrap();
+                let callee = caller_summary.get_callee_global(fn_id);
+                for call_site in callee.arg_defs.keys() {
+                    let unsafe_call_site = GlobalDefSite {
+                        fn_id: *caller_id,
+                        def_site: DefSite::OtherCall(*call_site)
+                    };
+                    update_wp_summary(&mut wp_summary, caller_id,
+                                      &unsafe_call_site.def_site);
+                    to_process.push_back(unsafe_call_site);
+                }
+            }
+        }
+
+        propagate_unsafe_sources(&self.cg, to_process, &mut wp_summary,
+                                 &mut FxHashSet::default());
+
+        wp_summary.into_keys().collect()
+    }
+
+    /// Get the statistics of each crate: the functions analyzed, the functions
+    /// with unsafe sources, the unsafe sources by kind and by mutability (see
+    /// unsafe_source_mutability()), and the low-level functions (see
+    /// low_level_fn_counts()).
+    pub fn stats(&self) -> CrateStats {
+        let mut stats = CrateStats::default();
+        for summary in self.summaries.values() {
+            let crate_stats = stats.entry(summary.crate_name.clone()).or_default();
+            crate_stats.fns_analyzed += 1;
+            crate_stats.inline_asm_fns += summary.has_inline_asm as usize;
+            crate_stats.intrinsic_call_fns += summary.has_intrinsic_call as usize;
+            for (_, mutability) in &summary.source_mutability {
+                match mutability {
+                    PtrMutability::Mut => crate_stats.mut_sources += 1,
+                    PtrMutability::Const => crate_stats.const_sources += 1,
+                }
+            }
+        }
+        for (fn_id, def_sites) in &self.wp_summary {
+            let Some(summary) = self.summaries.get(fn_id) else { continue; };
+            let crate_stats = stats.entry(summary.crate_name.clone()).or_default();
+            crate_stats.unsafe_fns += 1;
+            for def_site in def_sites {
+                match def_site {
+                    DefSite::HeapAlloc(_) | DefSite::BoxAlloc(_) => {
+                        crate_stats.heap_alloc_sites += 1
+                    },
+                    DefSite::NativeCall(_) => crate_stats.native_calls += 1,
+                    DefSite::OtherCall(_) => crate_stats.other_call_sources += 1,
+                    DefSite::Arg(_) => crate_stats.arg_sources += 1,
+                    DefSite::Uninit(_) => crate_stats.uninit_sources += 1,
+                    DefSite::Global(_) => crate_stats.global_sources += 1,
+                    DefSite::UnionRead(_) => crate_stats.union_read_sources += 1,
+                    DefSite::Transmute(_) => crate_stats.transmute_sources += 1,
+                    DefSite::RawSlice(_) => crate_stats.raw_slice_sources += 1,
+                    DefSite::IntToPtr(_) => crate_stats.int_to_ptr_sources += 1,
+                    _ => {}
+                }
+            }
+        }
+
+        stats
+    }
+
+    /// Get the unsafe heap allocation sites that may be self-referential,
+    /// i.e., the allocation may store a pointer to itself. Such structures,
+    /// e.g., intrusive linked lists built with raw pointers, are prone to
+    /// move-invalidation bugs. The result is sorted by FnID and BB.
+    pub fn self_referential_allocs(&self) -> Vec<(FnID, DefSite)> {
+        let mut allocs = Vec::new();
+        for (fn_id, def_sites) in &self.wp_summary {
+            let Some(summary) = self.summaries.get(fn_id) else { continue; };
+            for bb in &summary.self_ref_allocs {
+                if def_sites.contains(&DefSite::HeapAlloc(*bb)) {
+                    allocs.push((*fn_id, *bb));
+                }
+            }
+        }
+        allocs.sort();
+
+        allocs.into_iter().map(|(fn_id, bb)| (fn_id, DefSite::HeapAlloc(bb))).collect()
+    }
+
+    /// Get the functions with any unsafe source that are not reachable from
+    /// the entry points of the program, sorted.
+    pub fn unreachable_unsafe_fns(&self) -> Vec<FnID> {
+        let mut fns = self.wp_summary.keys()
+            .filter(|fn_id| !self.reachable.contains(fn_id)).copied().collect::<Vec<_>>();
+        fns.sort();
+
+        fns
+    }
+
+    /// Get the type allocated at each unsafe heap allocation site whose type
+    /// is known, sorted by FnID and DefSite.
+    pub fn unsafe_alloc_types(&self) -> Vec<(FnID, DefSite, &'a str)> {
+        let mut allocs = Vec::new();
+        for (fn_id, def_sites) in &self.wp_summary {
+            let Some(summary) = self.summaries.get(fn_id) else { continue; };
+            for (def_site, alloc_type) in &summary.alloc_types {
+                if def_sites.contains(def_site) {
+                    allocs.push((*fn_id, *def_site, alloc_type.as_str()));
+                }
+            }
+        }
+        allocs.sort();
+
+        allocs
+    }
+
+    /// Get the unsafe heap allocation sites that are not paired with any
+    /// deallocation, i.e., possible leaks of unsafe memory. The result is
+    /// sorted by FnID and DefSite.
+    ///
+    /// This is an over-approximation: an allocation released only by native
+    /// or foreign code (e.g., passed to a C free()) or via a path the summaries
+    /// do not capture is reported as a possible leak. On the other hand, an
+    /// allocation that is freed on some path counts as paired even if it is
+    /// leaked on another path.
+    pub fn unpaired_allocs(&self) -> SandboxResult<Vec<(FnID, DefSite)>> {
+        let freed = find_freed_alloc(&self.cg, &self.ret_sources)?;
+        let mut allocs = Vec::new();
+        for (fn_id, def_sites) in &self.wp_summary {
+            for def_site in def_sites {
+                if def_site.is_heap_alloc() &&
+                    !freed.get(fn_id).is_some_and(|sites| sites.contains(def_site)) {
+                    allocs.push((*fn_id, *def_site));
+                }
+            }
+        }
+        allocs.sort();
+
+        Ok(allocs)
+    }
+
+    /// Pair each deallocation site with the unsafe heap allocation sites whose
+    /// memory it may free, as the groundwork of finding use-after-free
+    /// candidates. A deallocation site that frees no unsafe allocation is left
+    /// out. The result is sorted by DeallocSite, and the allocation sites of
+    /// each deallocation site by FnID and DefSite.
+    pub fn dealloc_pairs(&self) -> SandboxResult<Vec<(DeallocSite, Vec<(FnID, DefSite)>)>> {
+        let mut pairs = Vec::new();
+        for (dealloc_site, sources) in trace_dealloc_sites(&self.cg, &self.ret_sources)? {
+            let mut allocs = Vec::new();
+            for source in sources {
+                if source.def_site.is_heap_alloc() && self.wp_summary.get(&source.fn_id)
+                    .is_some_and(|sites| sites.contains(&source.def_site)) {
+                    allocs.push((source.fn_id, source.def_site));
+                }
+            }
+            if allocs.is_empty() { continue; }
+            allocs.sort();
+            pairs.push((dealloc_site, allocs));
+        }
+
+        Ok(pairs)
+    }
+
+    /// Get the number of functions that contain inline assembly and the number
+    /// of functions that call intrinsics. These functions work on the lowest
+    /// level and deserve a manual review no matter what the analysis finds.
+    pub fn low_level_fn_counts(&self) -> (usize, usize) {
+        let asm_num = self.summaries.values().filter(|s| s.has_inline_asm).count();
+        let intrinsic_num = self.summaries.values().filter(|s| s.has_intrinsic_call).count();
+
+        (asm_num, intrinsic_num)
+    }
+
+    /// Get the pointer mutability of each unsafe def site of each function,
+    /// sorted by FnID. Mutable pointers enable writes and aliasing bugs, so
+    /// they should be reviewed first.
+    pub fn unsafe_source_mutability(&self) -> Vec<(FnID, DefSite, PtrMutability)> {
+        let mut sources = Vec::new();
+        for (fn_id, summary) in self.summaries {
+            for (def_site, mutability) in &summary.source_mutability {
+                sources.push((*fn_id, *def_site, *mutability));
+            }
+        }
+        sources.sort_by_key(|(fn_id, def_site, _)| (*fn_id, *def_site));
+
+        sources
+    }
 }
 
 /// Dump the call graph of the main crate for debugging.
@@ -450,6 +1556,64 @@ fn dump(&self) {
             println!();
         }
     }
+
+    /// Get the call graph in the Graphviz DOT format, with one node per
+    /// function, labeled "crate::fn", and an edge from each caller to each of
+    /// its callees. The functions with unsafe sources in @wp_summary are
+    /// filled in a different color. Nodes and edges are sorted by FnID.
+    fn to_dot(&self, wp_summary: &WPSummary) -> String {
+        // Escape a string to be used in a quoted DOT ID.
+        let escape = |name: &str| name.replace('\\', "\\\\").replace('"', "\\\"");
+        let mut fn_ids = self.0.keys().copied().collect::<Vec<_>>();
+        fn_ids.sort();
+
+        let mut dot = "digraph call_graph {\n".to_owned();
+        for fn_id in &fn_ids {
+            let node = self.get(fn_id);
+            let label = escape(&format!("{}::{}", node.crate_name, node.fn_name));
+            let style = if wp_summary.contains_key(fn_id) {
+                ", style=filled, fillcolor=salmon"
+            } else {
+                ""
+            };
+            dot += &format!("    \"{}\" [label=\"{}\"{}];\n", fn_id.to_hex(), label, style);
+        }
+        for fn_id in &fn_ids {
+            let mut callees = self.get_callees(fn_id).iter().copied().collect::<Vec<_>>();
+            callees.sort();
+            for callee_id in callees {
+                dot += &format!("    \"{}\" -> \"{}\";\n", fn_id.to_hex(), callee_id.to_hex());
+            }
+        }
+        dot += "}\n";
+
+        dot
+    }
+}
+
+/// Put the fn summaries of the main crate and of its dependencies together,
+/// and resolve the dyn Fn calls among them. A Summary of the main crate
+/// replaces the one of the same function in the dependencies.
+fn link_summaries(main_summaries: Vec<Summary>,
+                  mut all_summaries: FxHashMap<FnID, Summary>) -> FxHashMap<FnID, Summary> {
+    for summary in main_summaries {
+        all_summaries.insert(summary.fn_id, summary);
+    }
+    resolve_dyn_fn_calls(&mut all_summaries);
+
+    all_summaries
+}
+
+/// Run the WPA on in-memory summaries of the main crate and its dependencies,
+/// without reading or writing any file, and get the unsafe sources of each
+/// function. wpa() does the same after reading the summaries of the
+/// dependencies, and then writes the result.
+pub fn wpa_in_memory(main_summaries: Vec<Summary>, deps: Vec<Summary>)
+    -> SandboxResult<WPSummary> {
+    let deps = deps.into_iter().map(|summary| (summary.fn_id, summary)).collect();
+    let all_summaries = link_summaries(main_summaries, deps);
+
+    Ok(WpaResult::new(&all_summaries)?.wp_summary)
 }
 
 /// Entrance of this module.
@@ -457,36 +1621,43 @@ fn dump(&self) {
 /// We currently only develop for projects built by invoking cargo.
 /// If an app is compiled directly by invoking rustc, there would be no
 /// summary files generated in /tmp/rust-sandbox-ppid.
-pub fn wpa(main_summaries: Vec<Summary>) {
-    if _DEBUG { debug(main_summaries); return; }
+pub fn wpa(main_summaries: Vec<Summary>) -> SandboxResult<()> {
+    if _DEBUG { debug(main_summaries); return Ok(()); }
 
-    let dep_summaries = read_summaries();
-    if dep_summaries.is_err() {
-        panic!("Failed to read in function summary files of dependent crates");
-    }
+    // Read in function summary files of dependent crates.
+    let all_summaries = link_summaries(main_summaries, read_summaries()?);
 
-    let mut all_summaries = dep_summaries.unwrap();
-    for summary in main_summaries {
-        all_summaries.insert(summary.fn_id, summary);
-    }
+    // Build a call graph and find all unsafe sources, as wpa_in_memory()
+    // does. The WpaResult is kept for the reports below.
+    let wpa_result = WpaResult::new(&all_summaries)?;
 
-    // Build a call graph.
-    let cg = build_call_graph(&all_summaries);
+    // Dump the call graph for visualizing how unsafe sources propagate.
+    if let Some(path) = get_call_graph_dot_path() {
+        fs::write(path, wpa_result.call_graph_dot())?;
+    }
 
-    // Whole-program summary for later analysis to find unsafe memory accesses.
-    // Question: Will it be a little faster to use Vec<DefSite> in the HashMap?
-    let mut wp_summary = WPSummary::default();
+    // Report each unsafe site along with the whole-program summary.
+    let site_report = format_site_report(&wpa_result, &all_summaries)?;
 
-    // Find unsafe heap allocations.
-    find_unsafe_alloc(&all_summaries, &cg, &mut wp_summary);
+    // Record the statistics of each crate to track the coverage of the
+    // analysis.
+    stats::write_wpa_stats(wpa_result.stats())?;
 
-    // Find may-unsafe function arguments and non-heap-alloc calls.
-    find_unsafe_arg_call(&all_summaries, &cg, &mut wp_summary);
+    // Bundle the whole analysis for offline tools.
+    if let Some(path) = get_archive_path() {
+        archive::write_archive(Path::new(&path), &all_summaries, &wpa_result)?;
+    }
 
     // Delete the summary folder. This is necessary because a compilation
-    // may happen to have the same ppid as one older compilation.
-    let _ = remove_dir_all(get_summary_dir());
+    // may happen to have the same ppid as one older compilation. When the
+    // folder is kept for debugging, mark its summary files as stale instead.
+    if keep_summaries() {
+        keep_summary_dir(&get_summary_dir())?;
+    } else {
+        let _ = remove_dir_all(get_summary_dir());
+    }
 
     // Write the final whole-program summary to a file for later analysis.
-    write_wpa_summary(wp_summary);
+    write_wpa_summary(wpa_result.wp_summary, &all_summaries, &wpa_result.reachable,
+                      &site_report)
 }
@@ -197,7 +197,8 @@
 };
 
 // Sandboxing
-use rustc_mir_transform::sandbox::{summarize_fn, wpa, unsafe_access};
+use rustc_mir_transform::sandbox::{summarize_fn, wpa, unsafe_access, stats};
+use rustc_mir_transform::sandbox::context::AnalysisCtxt;
 
 #[derive(PartialEq)]
 pub enum MonoItemCollectionMode {
@@ -313,27 +314,49 @@ fn sandbox_unsafe<'tcx>(tcx: TyCtxt<'tcx>,
     // Do local analysis to find unsafe memory accesses, if the previous
     // WPA result is ready.
     if tcx.sess.opts.cg.sandbox_unsafe_access {
+        let mut cx = AnalysisCtxt::new(tcx);
         let mut processed = FxHashSet::default();
         let mut unsafe_accesses_all = Vec::<unsafe_access::UnsafeAccesses>::new();
         // Total memory accesses (dereferences to Place).
         let mut total_deref: u32 = 0;
-        let unsafe_sources = unsafe_access::read_wpa();
+        let unsafe_sources = match unsafe_access::read_wpa() {
+            Ok(unsafe_sources) => unsafe_sources,
+            Err(err) => {
+                eprintln!("Sandbox: failed to read the WPA result: {err}");
+                return;
+            }
+        };
         for item in visited.get_ref() {
             match item {
                 MonoItem::Fn(instance) => {
                     let def_id = instance.def_id();
                     if processed.insert(def_id) {
-                        unsafe_access::analyze(tcx, def_id, &unsafe_sources,
-                                               &mut unsafe_accesses_all,
-                                               &mut total_deref);
+                        if let Err(err) = unsafe_access::analyze(&mut cx, def_id, &unsafe_sources,
+                                                                 &mut unsafe_accesses_all,
+                                                                 &mut total_deref) {
+                            eprintln!("Sandbox: failed to analyze {:?}: {err}", def_id);
+                        }
                     }
                 },
                 _ => {}
             }
         }
 
-        let unsafe_deref_num = unsafe_access::unsafe_access_num(&unsafe_accesses_all);
-        unsafe_access::write_result(unsafe_deref_num, total_deref);
+        let unsafe_deref_num = unsafe_access::unsafe_access_num(&unsafe_accesses_all, false);
+        let unsafe_write_num = unsafe_access::unsafe_access_num(&unsafe_accesses_all, true);
+        if let Err(err) = unsafe_access::write_result(unsafe_deref_num, unsafe_write_num,
+                                                      total_deref) {
+            eprintln!("Sandbox: failed to write the unsafe access result: {err}");
+        }
+        if let Err(err) = unsafe_access::write_access_sites(tcx, &unsafe_accesses_all) {
+            eprintln!("Sandbox: failed to write the unsafe access sites: {err}");
+        }
+        if let Err(err) = unsafe_access::write_access_locs(&unsafe_accesses_all) {
+            eprintln!("Sandbox: failed to write the unsafe access locations: {err}");
+        }
+        if let Err(err) = stats::write_unsafe_derefs(unsafe_deref_num) {
+            eprintln!("Sandbox: failed to write the statistics: {err}");
+        }
 
         return;
     }
@@ -341,33 +364,67 @@ fn sandbox_unsafe<'tcx>(tcx: TyCtxt<'tcx>,
     // Do local anlaysis to summarize functiosn and then do WPA to find unsafe
     // heap allocation site, arguments, and non-heap-alloc sites.
     let mut summaries = Vec::<summarize_fn::Summary>::new();
+    // Whether each fn is ignored, its FnID and MIR, shared by the passes below.
+    let mut cx = AnalysisCtxt::new(tcx);
     // rustc actually only keeps one copy of MIR for all the MonoItem that are
     // from the same function with generic type parameter(s).
     let mut processed = FxHashSet::default();
+    let mut to_summarize = Vec::new();
     for item in visited.get_ref() {
         match item {
             MonoItem::Fn(instance) => {
-                let def_id = instance.def_id();
-                if processed.insert(def_id) {
-                    summarize_fn::summarize(tcx, def_id, &mut summaries);
+                to_summarize.push(instance.def_id());
+            },
+            MonoItem::Static(def_id) => {
+                if let Err(err) = summarize_fn::summarize_static(tcx, *def_id, &mut summaries) {
+                    eprintln!("Sandbox: failed to summarize {:?}: {err}", def_id);
                 }
             },
             _ => {}
         }
     }
-
-    let mut main_num = 0;
-    for summary in &summaries {
-        if summarize_fn::is_main(tcx, summary) { main_num += 1; }
+    // Focus on the functions with unsafe code and their callers and callees.
+    if summarize_fn::unsafe_roots_only() {
+        let def_ids = to_summarize.iter().copied().collect::<FxHashSet<_>>();
+        let selected = summarize_fn::select_unsafe_rooted(&mut cx, &def_ids);
+        if let Err(err) = stats::write_selected_fns(def_ids.len(), selected.len()) {
+            eprintln!("Sandbox: failed to write the statistics: {err}");
+        }
+        to_summarize.retain(|def_id| selected.contains(def_id));
     }
-    assert!(main_num < 2, "There are more than one main()");
-    if main_num == 0 {
+    // Methods in the vtables created by a summarized fn may not be collected
+    // as a MonoItem of this crate. Summarize them as well.
+    while let Some(def_id) = to_summarize.pop() {
+        if processed.insert(def_id) {
+            let summary_num = summaries.len();
+            if let Err(err) = summarize_fn::summarize(&mut cx, def_id, &mut summaries) {
+                eprintln!("Sandbox: failed to summarize {:?}: {err}", def_id);
+            }
+            if summaries.len() > summary_num {
+                to_summarize.extend(summaries.last().unwrap().vtable_fn_def_ids()
+                    .into_iter().filter(|id| tcx.is_mir_available(*id)));
+            }
+        }
+    }
+
+    let has_main = match summarize_fn::has_main(tcx, &summaries) {
+        Ok(has_main) => has_main,
+        Err(err) => {
+            eprintln!("Sandbox: failed to find the main crate: {err}");
+            return;
+        }
+    };
+    if !has_main {
         // Write the summaries of a dependency crate to a temporal file.
-        summarize_fn::write_summaries_to_file(tcx, &summaries);
+        if let Err(err) = summarize_fn::write_summaries_to_file(tcx, &summaries) {
+            eprintln!("Sandbox: failed to write the summaries: {err}");
+        }
     } else {
         // This is the main crate.
         // Read all the summaries from crates and do analysis on them.
-        wpa::wpa(summaries);
+        if let Err(err) = wpa::wpa(summaries) {
+            eprintln!("Sandbox: failed to do the whole-program analysis: {err}");
+        }
     }
 }
 
@@ -1388,6 +1388,9 @@ pub(crate) fn parse_dump_solver_proof_tree(
         "whole-program analysis on MIR to find unsafe sources (calls and args)"),
     sandbox_unsafe_access: bool = (false, parse_bool, [UNTRACKED],
         "find unsafe memory accesses based on previous WPA"),
+    sandbox_deref_refs: bool = (false, parse_bool, [UNTRACKED],
+        "treat dereferences of references, not only raw pointers, as potential \
+        unsafe memory accesses (default: no)"),
     save_temps: bool = (false, parse_bool, [UNTRACKED],
         "save all temporary output files during compilation (default: no)"),
     soft_float: bool = (false, parse_bool, [TRACKED],
@@ -127,6 +127,7 @@
         Acquire,
         AddToDiagnostic,
         Alignment,
+        Allocator,
         Any,
         Arc,
         Argument,
@@ -120,6 +120,7 @@
 ///   could be detected via an allocator that tracks allocations by printing or otherwise
 ///   having side effects.
 #[stable(feature = "global_alloc", since = "1.28.0")]
+#[rustc_diagnostic_item = "GlobalAlloc"]
 pub unsafe trait GlobalAlloc {
     /// Allocate memory as described by the given `layout`.
     ///
@@ -106,6 +106,7 @@ fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
 ///
 /// [*currently allocated*]: #currently-allocated-memory
 #[unstable(feature = "allocator_api", issue = "32838")]
+#[rustc_diagnostic_item = "Allocator"]
 pub unsafe trait Allocator {
     /// Attempts to allocate a block of memory.
     ///
@@ -0,0 +1,74 @@
+#!/usr/bin/env python
+
+"""
+Benchmark the fast path of `-C sandbox` that skips the def site analysis of
+call arguments and return values that cannot carry taint (see can_skip_def_analysis() in
+compiler/rustc_mir_transform/src/sandbox/summarize_fn/calls.rs).
+
+It generates a safe-heavy library crate whose functions only pass bool and
+char values around, and compiles it with the fast path on and off
+(SANDBOX_NO_FAST_PATH=1). It prints the median wall time of each.
+
+sample usage: src/etc/sandbox-bench-fast-path.py build/host/stage1/bin/rustc
+"""
+
+import os
+import shutil
+import statistics
+import subprocess
+import sys
+import tempfile
+import time
+
+FNS = 2000
+RUNS = 5
+
+
+def generate(path):
+    """Generate FNS functions, each calling the previous two, with branches
+    so that the def site analysis has paths to walk."""
+    with open(path, 'w') as f:
+        f.write('#![allow(dead_code)]\n\n')
+        f.write('pub fn f0(a: bool, c: char) -> bool {\n    a && c == \'x\'\n}\n\n')
+        f.write('pub fn f1(a: bool, c: char) -> bool {\n    !f0(a, c)\n}\n\n')
+        for i in range(2, FNS):
+            f.write('pub fn f%d(a: bool, c: char) -> bool {\n' % i)
+            f.write('    if a { f%d(!a, c) } else { f%d(a, \'y\') || c == \'z\' }\n' % (i - 1, i - 2))
+            f.write('}\n\n')
+
+
+def run(rustc, src, tmp, fast_path):
+    summary_dir = os.path.join(tmp, 'summaries')
+    times = []
+    for _ in range(RUNS):
+        shutil.rmtree(summary_dir, ignore_errors=True)
+        os.mkdir(summary_dir)
+        env = dict(os.environ, SANDBOX_SUMMARY_DIR=summary_dir,
+                   SANDBOX_NO_FAST_PATH='0' if fast_path else '1')
+        start = time.time()
+        subprocess.check_call([rustc, '-C', 'sandbox', '--crate-type=lib',
+                               '--out-dir', tmp, src], env=env)
+        times.append(time.time() - start)
+    return statistics.median(times)
+
+
+def main():
+    if len(sys.argv) != 2:
+        sys.exit(__doc__)
+    rustc = sys.argv[1]
+    tmp = tempfile.mkdtemp()
+    try:
+        src = os.path.join(tmp, 'safe_heavy.rs')
+        generate(src)
+        with_fast_path = run(rustc, src, tmp, True)
+        without_fast_path = run(rustc, src, tmp, False)
+    finally:
+        shutil.rmtree(tmp)
+
+    print('fns: %d, runs: %d' % (FNS, RUNS))
+    print('fast path on:  %.3fs' % with_fast_path)
+    print('fast path off: %.3fs' % without_fast_path)
+
+
+if __name__ == '__main__':
+    main()
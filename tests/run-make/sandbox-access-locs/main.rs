@@ -0,0 +1,11 @@
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+fn main() {
+    let p = alloc();
+    let x = 2u8;
+    unsafe { *p = x };
+    let y = unsafe { *p };
+    std::process::exit(y.into());
+}
@@ -0,0 +1,9 @@
+fn main() {
+    let mut v = Vec::<u64>::with_capacity(1);
+    let p = v.as_mut_ptr();
+    let n = unsafe {
+        *p = 7;
+        *p
+    };
+    std::process::exit(n as i32);
+}
@@ -0,0 +1,13 @@
+fn alloc() -> *mut usize {
+    Box::into_raw(Box::new(1usize))
+}
+
+fn main() {
+    let heap = alloc();
+    let i = unsafe { *alloc() } % 1;
+    let j = std::env::args().count() - 1;
+    let a = unsafe { *heap.add(i) };
+    let b = unsafe { *heap.offset(i as isize) };
+    let c = unsafe { *heap.add(j) };
+    std::process::exit((a + b + c) as i32);
+}
@@ -0,0 +1,33 @@
+// A raw pointer is held across an ".await". The generator stores it in a
+// field of itself before polling tick() and loads it back after.
+
+use std::future::Future;
+use std::pin::pin;
+use std::task::{Context, Poll, RawWaker, RawWakerVTable, Waker};
+
+async fn tick() {}
+
+async fn hold() -> u8 {
+    let p = Box::into_raw(Box::new(1u8));
+    tick().await;
+    unsafe { *p }
+}
+
+fn noop_raw_waker() -> RawWaker {
+    fn clone(_: *const ()) -> RawWaker { noop_raw_waker() }
+    fn noop(_: *const ()) {}
+    static VTABLE: RawWakerVTable = RawWakerVTable::new(clone, noop, noop, noop);
+    RawWaker::new(std::ptr::null(), &VTABLE)
+}
+
+fn main() {
+    let waker = unsafe { Waker::from_raw(noop_raw_waker()) };
+    let mut cx = Context::from_waker(&waker);
+    let mut fut = pin!(hold());
+    let v = loop {
+        if let Poll::Ready(v) = fut.as_mut().poll(&mut cx) {
+            break v;
+        }
+    };
+    std::process::exit(v.into());
+}
@@ -0,0 +1,15 @@
+// A closure written in an unsafe block dereferences a raw pointer. The body
+// of the closure has no unsafe block of its own.
+
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(0u8))
+}
+
+fn main() {
+    let p = alloc();
+    let v = unsafe {
+        let read = || *p;
+        read()
+    };
+    std::process::exit(v.into());
+}
@@ -0,0 +1,9 @@
+// A dependency whose heap allocation is read in unsafe code by main.
+
+pub fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(7u8))
+}
+
+pub fn read(p: *const u8) -> u8 {
+    unsafe { *p }
+}
@@ -0,0 +1,11 @@
+// Uses the dependency and std, whose functions are ignored by their crate, so
+// that both the crates and the functions of AnalysisCtxt are queried.
+
+extern crate ctx_dep;
+
+fn main() {
+    let p = ctx_dep::alloc();
+    let v = vec![ctx_dep::read(p)];
+    let q = v.as_ptr();
+    println!("{}", unsafe { *q });
+}
@@ -0,0 +1,26 @@
+// Unsafe code over several statements and branches, so that the coverage
+// instrumentation puts counters between the def sites and their uses.
+
+fn alloc(n: u8) -> *mut u8 {
+    let b = Box::new(n);
+    if n > 1 {
+        Box::into_raw(b)
+    } else {
+        Box::into_raw(Box::new(n + 1))
+    }
+}
+
+fn read(p: *mut u8, flag: bool) -> u8 {
+    let q = if flag { p } else { unsafe { p.add(0) } };
+    let v = unsafe { *q };
+    unsafe {
+        *q = v + 1;
+    }
+    v
+}
+
+fn main() {
+    let flag = std::env::args().count() > 1;
+    let p = alloc(flag as u8);
+    std::process::exit(read(p, flag).into());
+}
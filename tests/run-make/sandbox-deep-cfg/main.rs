@@ -0,0 +1,14 @@
+// Each wrapping_add is a call that ends a BB, so the pointer written in
+// unsafe code is traced back through more than a thousand sequential BB to
+// its allocation.
+
+macro_rules! x4 {
+    ($($t:tt)*) => { $($t)* $($t)* $($t)* $($t)* };
+}
+
+fn main() {
+    let mut p = Box::into_raw(Box::new(0u8));
+    x4! { x4! { x4! { x4! { x4! { p = p.wrapping_add(0); } } } } }
+    unsafe { *p = 1 };
+    std::process::exit(unsafe { *p }.into());
+}
@@ -0,0 +1,7 @@
+fn main() {
+    let p = Box::into_raw(Box::new(1u8));
+    // A safe reference to the unsafe heap allocation, dereferenced in safe code.
+    let r: &u8 = unsafe { &*p };
+    let v = *r;
+    std::process::exit(v.into());
+}
@@ -0,0 +1,11 @@
+// Raw pointers bound by destructuring an array keep the array's taint.
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(0u8))
+}
+
+fn main() {
+    let ptrs = [alloc(), alloc()];
+    let [a, _] = &ptrs;
+    let v = unsafe { **a };
+    std::process::exit(v.into());
+}
@@ -0,0 +1,51 @@
+// An enum variant carrying a raw pointer, built by a Deinit, writes to the
+// fields of the variant and a SetDiscriminant, whose pointer is dereferenced
+// in unsafe code in read(). The allocated pointer is written to the first
+// field, so the enum must still be tracked past the write to the second one.
+// build() is written in custom MIR as MIR building does not emit this form.
+
+#![feature(core_intrinsics, custom_mir)]
+#![allow(internal_features)]
+
+use core::intrinsics::mir::*;
+
+enum Slot {
+    Empty,
+    Raw(*mut u8, usize),
+}
+
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+fn read(slot: Slot) -> u8 {
+    match slot {
+        Slot::Raw(p, _) => unsafe { *p },
+        Slot::Empty => 0,
+    }
+}
+
+#[custom_mir(dialect = "runtime", phase = "optimized")]
+fn build(len: usize) -> u8 {
+    mir!(
+        let p: *mut u8;
+        let slot: Slot;
+        {
+            Call(p = alloc(), init)
+        }
+        init = {
+            Deinit(slot);
+            place!(Field(Variant(slot, 1), 0)) = p;
+            place!(Field(Variant(slot, 1), 1)) = len;
+            SetDiscriminant(slot, 1);
+            Call(RET = read(Move(slot)), done)
+        }
+        done = {
+            Return()
+        }
+    )
+}
+
+fn main() {
+    std::process::exit(build(1).into());
+}
@@ -0,0 +1,8 @@
+// Memory from the custom allocator is dereferenced in unsafe code.
+
+extern crate my_alloc;
+
+fn main() {
+    let p = my_alloc::alloc_page();
+    std::process::exit(unsafe { *p }.into());
+}
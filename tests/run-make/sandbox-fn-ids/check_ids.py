@@ -0,0 +1,34 @@
+#!/usr/bin/env python
+
+# Check that the FnID of each summarized function of a crate, which is given
+# by AnalysisCtxt, is the FnID its callers record for it, which is given by
+# get_fn_fingerprint(). It fails if a callee or vtable method of the crate has
+# no summary with its FnID, or if any of the expected callees is not found.
+#
+# Usage: check_ids.py <summary dir> <crate name> <expected callee>...
+
+import json
+import os
+import sys
+
+summary_dir, crate, expected = sys.argv[1], sys.argv[2], set(sys.argv[3:])
+
+summaries = []
+for file in sorted(os.listdir(summary_dir)):
+    if file.startswith(crate + "-"):
+        with open(os.path.join(summary_dir, file)) as f:
+            summaries += json.load(f)["summaries"]
+
+fn_ids = set(tuple(summary["fn_id"]) for summary in summaries)
+checked = set()
+for summary in summaries:
+    for callee in summary["callees"] + summary["vtable_fns"]:
+        if callee["crate_name"] != crate:
+            continue
+        if tuple(callee["fn_id"]) not in fn_ids:
+            sys.exit("{} calls {} with FnID {}, which has no summary".format(
+                summary["fn_name"], callee["fn_name"], callee["fn_id"]))
+        checked.add(callee["fn_name"])
+
+if not expected <= checked:
+    sys.exit("callees not found: {}".format(sorted(expected - checked)))
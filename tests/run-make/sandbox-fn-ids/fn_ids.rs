@@ -0,0 +1,38 @@
+// Functions of each kind that are given a FnID by AnalysisCtxt when they are
+// summarized and by get_fn_fingerprint() when they are called: a free
+// function, an inherent method, a trait method in a vtable, and a closure.
+
+pub struct S(u8);
+
+pub trait T {
+    fn get(&self) -> u8;
+}
+
+impl T for S {
+    fn get(&self) -> u8 {
+        self.0
+    }
+}
+
+impl S {
+    pub fn new(v: u8) -> S {
+        S(v)
+    }
+}
+
+fn helper(x: u8) -> u8 {
+    x + 1
+}
+
+pub fn make() -> Box<dyn T> {
+    Box::new(S::new(helper(1)))
+}
+
+pub fn apply(v: u8) -> u8 {
+    let f = |x: u8| helper(x);
+    f(v)
+}
+
+pub fn call_all() -> u8 {
+    make().get() + apply(2)
+}
@@ -0,0 +1,22 @@
+// The pointer p is obtained once before the loop and dereferenced in every
+// iteration of it. After the dereference, in the same BB, it is updated to
+// point to the allocation of alloc_q(), which is only found by examining the
+// rest of that BB when the backward traversal reaches it via the back edge.
+
+fn alloc_q() -> *const u8 {
+    Box::into_raw(Box::new(2u8))
+}
+
+fn main() {
+    let v = vec![1u8, 2, 3];
+    let q = alloc_q();
+    let mut p = v.as_ptr();
+    let mut i = 0;
+    let mut sum = 0u8;
+    while i < 2 {
+        sum ^= unsafe { *p };
+        p = q;
+        i += 1;
+    }
+    std::process::exit(sum.into());
+}
@@ -0,0 +1,16 @@
+// A user-defined macro that hides an unsafe memory access.
+macro_rules! unsafe_read {
+    ($p:expr) => {
+        unsafe { *$p }
+    };
+}
+
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(0u8))
+}
+
+fn main() {
+    let p = alloc();
+    let v = unsafe_read!(p);
+    std::process::exit(v.into());
+}
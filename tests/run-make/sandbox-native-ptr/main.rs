@@ -0,0 +1,11 @@
+// A raw pointer returned by CString::into_raw(), a native call that takes no
+// pointer, is dereferenced.
+
+use std::ffi::CString;
+
+fn main() {
+    let p = CString::new("a").unwrap().into_raw();
+    let c = unsafe { *p };
+    drop(unsafe { CString::from_raw(p) });
+    std::process::exit(c.into());
+}
@@ -0,0 +1,15 @@
+#![feature(core_intrinsics)]
+use std::intrinsics::offset;
+
+fn alloc() -> *mut usize {
+    Box::into_raw(Box::new(1usize))
+}
+
+fn main() {
+    let stack = [7usize; 4];
+    let i = unsafe { *alloc() };
+    let heap = alloc();
+    let a = unsafe { *offset(stack.as_ptr(), i) };
+    let b = unsafe { *offset(heap as *const usize, i) };
+    std::process::exit((a + b) as i32);
+}
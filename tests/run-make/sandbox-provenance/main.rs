@@ -0,0 +1,13 @@
+// The pointer read by pick() is either its argument, which main() allocates,
+// or a Box that pick() allocates itself.
+
+fn pick(a: *mut u8, flag: bool) -> u8 {
+    let p = if flag { a } else { Box::into_raw(Box::new(1u8)) };
+    unsafe { *p }
+}
+
+fn main() {
+    let a = Box::into_raw(Box::new(2u8));
+    let v = pick(a, std::env::args().count() > 1);
+    std::process::exit(v.into());
+}
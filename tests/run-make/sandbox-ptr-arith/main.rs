@@ -0,0 +1,12 @@
+fn alloc() -> *mut usize {
+    Box::into_raw(Box::new(1usize))
+}
+
+fn main() {
+    let stack = [7usize; 4];
+    let i = unsafe { *alloc() };
+    let heap = alloc();
+    let a = unsafe { *stack.as_ptr().add(i) };
+    let b = unsafe { *heap.add(0) };
+    std::process::exit((a + b) as i32);
+}
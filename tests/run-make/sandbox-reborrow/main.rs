@@ -0,0 +1,15 @@
+// A reference to a field of an unsafe heap object keeps the object's taint.
+struct Node {
+    value: u8,
+}
+
+fn alloc() -> *mut Node {
+    Box::into_raw(Box::new(Node { value: 0 }))
+}
+
+fn main() {
+    let p = alloc();
+    let r = unsafe { &(*p).value };
+    let v = *r;
+    std::process::exit(v.into());
+}
@@ -0,0 +1,47 @@
+// "r = step(r, q)" reads and writes the same Local, so the call is not a def
+// site of its own first argument. If it were, the heap allocation that step()
+// returns via q would flow back into its argument p, and the write through p,
+// which points to the stack, would be reported. self_call() is written in
+// custom MIR so that the destination and the argument are the same Local.
+
+#![feature(core_intrinsics, custom_mir)]
+#![allow(internal_features)]
+
+use core::intrinsics::mir::*;
+
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+fn step(p: *mut u8, q: *mut u8) -> *mut u8 {
+    unsafe {
+        *q = 2;
+        *p = 1;
+    }
+    q
+}
+
+#[custom_mir(dialect = "runtime", phase = "optimized")]
+fn self_call(p: *mut u8) -> *mut u8 {
+    mir!(
+        let q: *mut u8;
+        let r: *mut u8;
+        {
+            Call(q = alloc(), next)
+        }
+        next = {
+            r = p;
+            Call(r = step(r, q), done)
+        }
+        done = {
+            RET = r;
+            Return()
+        }
+    )
+}
+
+fn main() {
+    let mut x = 0u8;
+    let p = self_call(&mut x as *mut u8);
+    std::process::exit(unsafe { *p }.into());
+}
@@ -0,0 +1,9 @@
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+fn main() {
+    let p = alloc();
+    let v = unsafe { *p };
+    std::process::exit(v.into());
+}
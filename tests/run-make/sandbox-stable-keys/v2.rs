@@ -0,0 +1,13 @@
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+fn main() {
+    // An unrelated edit that moves the access below and changes its BB.
+    if std::env::args().count() > 100 {
+        println!("too many arguments");
+    }
+    let p = alloc();
+    let v = unsafe { *p };
+    std::process::exit(v.into());
+}
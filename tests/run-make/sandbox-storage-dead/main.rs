@@ -0,0 +1,21 @@
+fn main() {
+    let v = 1u8;
+    let p = Box::into_raw(Box::new(2u8)) as *const u8;
+    let mut sum = 0u8;
+    // t points to the heap at the end of each iteration, but it is dead then
+    // and reassigned a pointer to the stack before y copies it in the next one.
+    for _ in 0..2 {
+        let mut t = &v as *const u8;
+        let y = t;
+        sum = sum.wrapping_add(unsafe { *y });
+        t = p;
+        sum = sum.wrapping_add(unsafe { *t });
+    }
+    // u points to the heap first and then to the stack, so only its first
+    // dereference may access unsafe memory.
+    let mut u = p;
+    sum = sum.wrapping_add(unsafe { *u });
+    u = &v as *const u8;
+    sum = sum.wrapping_add(unsafe { *u });
+    std::process::exit(sum.into());
+}
@@ -0,0 +1,14 @@
+// A function with a non-default ABI that is defined in Rust is analyzed like
+// any other function, while an FFI declaration of any ABI is foreign.
+
+extern "C" {
+    fn ext_read(p: *const u8) -> u8;
+}
+
+pub extern "system" fn system_read(p: *const u8) -> u8 {
+    unsafe { *p }
+}
+
+pub fn caller(p: *const u8) -> u8 {
+    system_read(p).wrapping_add(unsafe { ext_read(p) })
+}
@@ -0,0 +1,19 @@
+// A struct literal whose field 0 is a raw pointer and whose other fields are
+// scalars from calls. Only the pointer field is dereferenced in unsafe code,
+// so the calls defining the scalars are neither unsafe def sites nor def
+// sites of the return value.
+
+pub struct Buf {
+    p: *mut u8,
+    len: usize,
+    cap: usize,
+}
+
+fn size() -> usize {
+    8
+}
+
+pub fn first(p: *mut u8) -> u8 {
+    let buf = Buf { p, len: size(), cap: size() };
+    unsafe { *buf.p }
+}
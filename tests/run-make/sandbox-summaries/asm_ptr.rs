@@ -0,0 +1,9 @@
+// A raw pointer passed to inline assembly.
+
+use std::arch::asm;
+
+pub fn asm_ptr(p: *mut u8) {
+    unsafe {
+        asm!("/* {0} */", in(reg) p);
+    }
+}
@@ -0,0 +1,11 @@
+// A Box initialized in place, which is what Box::new() becomes once it is
+// inlined, and dereferenced in an unsafe block.
+
+#![feature(rustc_attrs, stmt_expr_attributes)]
+
+pub fn box_in_unsafe() -> u8 {
+    unsafe {
+        let b: Box<u8> = #[rustc_box] Box::new(1u8);
+        *Box::into_raw(b)
+    }
+}
@@ -0,0 +1,11 @@
+// A heap pointer captured by a Fn closure, which is called by
+// FnOnce::call_once. The call is resolved to the closure via a
+// ClosureOnceShim.
+
+#![feature(fn_traits, unboxed_closures)]
+
+pub fn call_closure_once() -> u8 {
+    let p = Box::into_raw(Box::new(1u8));
+    let read = move || unsafe { *p };
+    FnOnce::call_once(read, ())
+}
@@ -0,0 +1,28 @@
+// A call whose callee is a constant function pointer rather than a function
+// item. The constant is evaluated to the function it points to, which is
+// recorded as the callee, and the heap allocation it returns flows to the
+// return value of the caller through the call. It is written in custom MIR so
+// that the pointer stays a constant operand of the call.
+
+#![feature(core_intrinsics, custom_mir)]
+#![allow(internal_features)]
+
+use core::intrinsics::mir::*;
+
+pub fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+const ALLOC: fn() -> *mut u8 = alloc;
+
+#[custom_mir(dialect = "runtime", phase = "optimized")]
+pub fn call_const() -> *mut u8 {
+    mir!(
+        {
+            Call(RET = ALLOC(), done)
+        }
+        done = {
+            Return()
+        }
+    )
+}
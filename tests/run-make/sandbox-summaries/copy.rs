@@ -0,0 +1,12 @@
+// A heap buffer filled by ptr::copy_nonoverlapping in unsafe code. The copy
+// is a native call, and both of its pointers are unsafe.
+
+use std::ptr;
+
+pub fn copy_to_heap(src: &[u8; 4]) -> Box<[u8; 4]> {
+    let dst = Box::into_raw(Box::new([0u8; 4]));
+    unsafe {
+        ptr::copy_nonoverlapping(src.as_ptr(), dst as *mut u8, 4);
+        Box::from_raw(dst)
+    }
+}
@@ -0,0 +1,24 @@
+// Memory from a custom allocator: calls to the allocation methods of any
+// GlobalAlloc, including a user type, are heap allocations.
+
+use std::alloc::{GlobalAlloc, Layout, System};
+
+pub struct Counting;
+
+unsafe impl GlobalAlloc for Counting {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        System.alloc(layout)
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        System.dealloc(ptr, layout)
+    }
+}
+
+pub fn alloc_one() -> u8 {
+    unsafe {
+        let p = Counting.alloc(Layout::new::<u8>());
+        *p = 1;
+        *p
+    }
+}
@@ -0,0 +1,34 @@
+// A struct returned after being initialized field by field following a
+// Deinit. It is written in custom MIR as MIR building does not emit this form.
+
+#![feature(core_intrinsics, custom_mir)]
+#![allow(internal_features)]
+
+use core::intrinsics::mir::*;
+
+pub struct Pair {
+    a: *mut u8,
+    b: *mut u8,
+}
+
+pub fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+#[custom_mir(dialect = "runtime", phase = "optimized")]
+pub fn make_pair(p: *mut u8) -> Pair {
+    mir!(
+        let q: *mut u8;
+        let pair: Pair;
+        {
+            Call(q = alloc(), init)
+        }
+        init = {
+            Deinit(pair);
+            pair.a = p;
+            pair.b = q;
+            RET = Move(pair);
+            Return()
+        }
+    )
+}
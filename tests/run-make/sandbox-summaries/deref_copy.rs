@@ -0,0 +1,7 @@
+// A read through a pointer behind a reference, "**pp", is lowered to
+// "_2 = deref_copy (*_1)" followed by "(*_2)", so the pointer copied by the
+// CopyForDeref, i.e., the argument, is the def site.
+
+pub fn read_through(pp: &*mut u8) -> u8 {
+    unsafe { **pp }
+}
@@ -0,0 +1,14 @@
+// A diverging FFI call in unsafe code. It never writes its destination, so it
+// is not a def site: the only unsafe source is the pointer argument. If the
+// destination were tracked, the call would show up as an OtherCall.
+
+extern "C" {
+    fn abort() -> !;
+}
+
+pub fn checked_read(p: *const u8) -> u8 {
+    if p.is_null() {
+        unsafe { abort() }
+    }
+    unsafe { *p }
+}
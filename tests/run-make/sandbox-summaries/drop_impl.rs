@@ -0,0 +1,17 @@
+// Dropping a value whose type implements Drop runs its drop(), which is a
+// callee of the function that drops it. The pointer in the value flows into
+// the destructor as its only argument.
+
+pub struct Guard(*mut u8);
+
+impl Drop for Guard {
+    fn drop(&mut self) {
+        unsafe {
+            *self.0 = 0;
+        }
+    }
+}
+
+pub fn release(p: *mut u8) {
+    let _guard = Guard(p);
+}
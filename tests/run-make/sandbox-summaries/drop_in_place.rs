@@ -0,0 +1,8 @@
+// ptr::drop_in_place on a pointer to a heap allocation.
+
+pub fn drop_box() {
+    let p = Box::into_raw(Box::new(1u8));
+    unsafe {
+        std::ptr::drop_in_place(p);
+    }
+}
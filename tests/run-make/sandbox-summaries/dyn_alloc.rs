@@ -0,0 +1,22 @@
+// An allocation reached through a trait object.
+
+pub trait Alloc {
+    fn alloc(&self) -> *mut u8;
+}
+
+pub struct BoxAlloc;
+
+impl Alloc for BoxAlloc {
+    fn alloc(&self) -> *mut u8 {
+        Box::into_raw(Box::new(1u8))
+    }
+}
+
+pub fn use_dyn(a: &dyn Alloc) -> u8 {
+    let p = a.alloc();
+    unsafe { *p }
+}
+
+pub fn make() -> u8 {
+    use_dyn(&BoxAlloc)
+}
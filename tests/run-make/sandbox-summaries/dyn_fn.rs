@@ -0,0 +1,8 @@
+// A boxed closure that dereferences a captured raw pointer. The closure is
+// only called via the Fn trait object.
+
+pub fn call_boxed() -> u8 {
+    let p = Box::into_raw(Box::new(1u8));
+    let f: Box<dyn Fn() -> u8> = Box::new(move || unsafe { *p });
+    f()
+}
@@ -0,0 +1,29 @@
+// An allocation reached through a trait object, where the trait is defined
+// in one module and implemented in another.
+
+pub mod api {
+    pub trait Source {
+        fn ptr(&self) -> *mut u8;
+    }
+}
+
+pub mod heap {
+    use crate::api::Source;
+
+    pub struct Heap;
+
+    impl Source for Heap {
+        fn ptr(&self) -> *mut u8 {
+            Box::into_raw(Box::new(1u8))
+        }
+    }
+}
+
+pub fn read(s: &dyn api::Source) -> u8 {
+    let p = s.ptr();
+    unsafe { *p }
+}
+
+pub fn make() -> u8 {
+    read(&heap::Heap)
+}
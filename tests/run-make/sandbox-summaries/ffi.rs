@@ -0,0 +1,12 @@
+// Memory returned by an FFI call and read by unsafe code.
+
+extern "C" {
+    fn malloc(size: usize) -> *mut u8;
+}
+
+pub fn ffi_alloc() -> u8 {
+    unsafe {
+        let p = malloc(1);
+        *p
+    }
+}
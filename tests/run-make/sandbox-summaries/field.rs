@@ -0,0 +1,16 @@
+// A struct whose fields are defined by different calls, of which only the
+// field from a heap allocation is dereferenced in an unsafe block.
+
+pub struct Pair {
+    p: *mut u8,
+    q: *mut u8,
+}
+
+fn make_ptr() -> *mut u8 {
+    std::ptr::null_mut()
+}
+
+pub fn deref_one_field() -> u8 {
+    let pair = Pair { p: Box::into_raw(Box::new(1u8)), q: make_ptr() };
+    unsafe { *pair.p }
+}
@@ -0,0 +1,15 @@
+// A heap pointer passed to a call through a function pointer in an unsafe
+// block. The callee is unknown, so it is recorded as a dyn callee.
+
+fn write(p: *mut u8) {
+    unsafe { *p = 1 };
+}
+
+pub fn call_fn_ptr() -> u8 {
+    let p = Box::into_raw(Box::new(0u8));
+    let f: fn(*mut u8) = write;
+    unsafe {
+        f(p);
+        *p
+    }
+}
@@ -0,0 +1,18 @@
+// Box::from_raw frees the memory it takes back, but the functions that are
+// only named like a deallocation function do not.
+
+pub struct Pool;
+
+impl Pool {
+    pub fn dealloc(&self, _p: *mut u8) {}
+}
+
+pub fn free(_p: *mut u8) {}
+
+pub fn release(pool: &Pool, p: *mut u8) {
+    pool.dealloc(p);
+    free(p);
+    unsafe {
+        drop(Box::from_raw(p));
+    }
+}
@@ -0,0 +1,13 @@
+// A generic function instantiated for two types. Summaries are keyed by the
+// DefId of the generic function rather than by its instances, so there is a
+// single summary of id() for both instantiations.
+
+pub fn id<T>(x: T) -> T {
+    x
+}
+
+pub fn read(p: *mut u8, n: u32) -> u8 {
+    let q = id(p);
+    let _ = id(n);
+    unsafe { *q }
+}
@@ -0,0 +1,12 @@
+// Memory from the global allocator, written and read in an unsafe block.
+
+use std::alloc::{alloc, Layout};
+
+pub fn global_alloc_in_unsafe() -> u8 {
+    let layout = Layout::new::<u8>();
+    unsafe {
+        let p = alloc(layout);
+        *p = 1;
+        *p
+    }
+}
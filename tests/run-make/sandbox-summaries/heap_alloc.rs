@@ -0,0 +1,6 @@
+// A heap allocation whose pointer is dereferenced in an unsafe block.
+
+pub fn heap_alloc_in_unsafe() -> u8 {
+    let p = Box::into_raw(Box::new(1u8));
+    unsafe { *p }
+}
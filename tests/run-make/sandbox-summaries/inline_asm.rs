@@ -0,0 +1,9 @@
+// A function with inline assembly.
+
+use std::arch::asm;
+
+pub fn nop() {
+    unsafe {
+        asm!("nop");
+    }
+}
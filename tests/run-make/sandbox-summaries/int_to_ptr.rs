@@ -0,0 +1,14 @@
+// A pointer forged from an integer is an unsafe source, while a cast between
+// pointers only carries the taint of the cast pointer.
+
+pub fn forge(addr: usize) {
+    let p = addr as *mut u8;
+    unsafe {
+        *p = 0;
+    }
+}
+
+pub fn recast(p: *const u32) -> u8 {
+    let q = p as *const u8;
+    unsafe { *q }
+}
@@ -0,0 +1,11 @@
+// An integer may hold an address that is later cast back to a pointer, so a
+// function that only passes integers around still has the def sites of its
+// calls and return value.
+
+pub fn id(x: usize) -> usize {
+    x
+}
+
+pub fn twice(x: usize) -> usize {
+    id(id(x))
+}
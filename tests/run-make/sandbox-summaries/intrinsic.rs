@@ -0,0 +1,7 @@
+// A function that calls a compiler intrinsic directly.
+
+#![feature(core_intrinsics)]
+
+pub fn popcount(x: u32, out: &mut u32) {
+    *out = std::intrinsics::ctpop(x);
+}
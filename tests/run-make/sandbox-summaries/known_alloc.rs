@@ -0,0 +1,31 @@
+// Only the constructors of the types known to allocate are heap allocations:
+// neither a user-defined new nor NonNull::new is one, but Vec::with_capacity is.
+
+use std::ptr::NonNull;
+
+pub struct Handle(*mut u8);
+
+impl Handle {
+    pub fn new(p: *mut u8) -> Handle {
+        Handle(p)
+    }
+}
+
+pub fn user_new(p: *mut u8) -> u8 {
+    let h = Handle::new(p);
+    unsafe { *h.0 }
+}
+
+pub fn core_new(p: *mut u8) -> u8 {
+    let q = NonNull::new(p).unwrap();
+    unsafe { *q.as_ptr() }
+}
+
+pub fn vec_with_capacity() -> u8 {
+    let mut v = Vec::<u8>::with_capacity(1);
+    let p = v.as_mut_ptr();
+    unsafe {
+        *p = 1;
+        *p
+    }
+}
@@ -0,0 +1,11 @@
+// Unsafe sources of a *const and a *mut pointer.
+
+pub fn read_const(p: *const u8) -> u8 {
+    unsafe { *p }
+}
+
+pub fn write_mut(p: *mut u8) {
+    unsafe {
+        *p = 1;
+    }
+}
@@ -0,0 +1,16 @@
+// A raw pointer returned by a native function that takes no pointer, e.g.,
+// CString::into_raw(), originates there, as there is no pointer argument to
+// trace it back to.
+
+use std::ffi::CString;
+
+pub fn leak(s: CString) -> u8 {
+    let p = s.into_raw();
+    unsafe { *p as u8 }
+}
+
+// A native call that takes and returns no pointer is not a source, even in an
+// unsafe function.
+pub unsafe fn step(n: usize) -> usize {
+    n.wrapping_add(1)
+}
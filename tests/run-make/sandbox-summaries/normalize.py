@@ -0,0 +1,77 @@
+#!/usr/bin/env python
+
+# Print the summaries of a crate in a form that can be compared with a golden
+# file: functions are keyed by "crate::fn", FnIDs are replaced by the names of
+# the functions they refer to, and def sites are reduced to their kinds
+# (arguments keep their index). Only callees in the same crate are kept so
+# that the output does not depend on how std is implemented. It fails if a
+# function has more than one summary.
+#
+# Usage: normalize.py <summary dir> <crate name>
+
+import json
+import os
+import sys
+
+summary_dir, crate = sys.argv[1], sys.argv[2]
+
+
+def def_site(site):
+    (kind, loc), = site.items()
+    return "Arg({})".format(loc) if kind == "Arg" else kind
+
+
+def def_sites(sites):
+    return sorted(set(def_site(site) for site in sites or []))
+
+
+def name(item):
+    return "{}::{}".format(item["crate_name"], item["fn_name"])
+
+
+summaries = []
+for file in sorted(os.listdir(summary_dir)):
+    if file.startswith(crate + "-"):
+        with open(os.path.join(summary_dir, file)) as f:
+            summaries += json.load(f)["summaries"]
+
+output = {}
+for summary in summaries:
+    # Two summaries of the same function, e.g., one per instance of a generic
+    # function, would otherwise be silently merged into one entry.
+    if name(summary) in output:
+        sys.exit("duplicate summaries of {}".format(name(summary)))
+    names = {}
+    for callee in summary["callees"] + summary["vtable_fns"]:
+        names[tuple(callee["fn_id"])] = name(callee)
+    local = lambda callees: sorted(set(
+        name(c) for c in callees if c["crate_name"] == crate))
+    output[name(summary)] = {
+        "callees": local(summary["callees"]),
+        "foreign_callees": sorted(set(
+            names[tuple(fn_id)] for fn_id in summary["foreign_callees"])),
+        "ret_defs": def_sites(summary["ret_defs"][0] + summary["ret_defs"][1]),
+        "unsafe_defs": def_sites(summary["unsafe_defs"]),
+        "vtable_fns": local(summary["vtable_fns"]),
+    }
+    if summary["free_callees"]:
+        output[name(summary)]["free_callees"] = sorted(set(
+            names[tuple(fn_id)] for fn_id in summary["free_callees"]))
+    if summary["self_ref_allocs"]:
+        output[name(summary)]["self_ref_allocs"] = len(summary["self_ref_allocs"])
+    if summary["dyn_fn_calls"]:
+        output[name(summary)]["dyn_fn_calls"] = len(summary["dyn_fn_calls"])
+    if summary["dyn_fn_closures"]:
+        output[name(summary)]["dyn_fn_closures"] = local(
+            closure for closure, _ in summary["dyn_fn_closures"])
+    if summary["fn_ptr_fns"]:
+        output[name(summary)]["fn_ptr_fns"] = local(summary["fn_ptr_fns"])
+    if summary["source_mutability"]:
+        output[name(summary)]["source_mutability"] = sorted(set(
+            "{}: {}".format(def_site(site), mutability)
+            for site, mutability in summary["source_mutability"]))
+    for flag in ["has_inline_asm", "has_intrinsic_call"]:
+        if summary[flag]:
+            output[name(summary)][flag] = True
+
+print(json.dumps(output, indent=4, sort_keys=True))
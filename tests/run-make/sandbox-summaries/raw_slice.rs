@@ -0,0 +1,9 @@
+// A slice built from a raw pointer and a length. The construction is an
+// unsafe source, and the slice carries the taint of the pointer but not of
+// the length.
+
+use std::slice;
+
+pub fn make<'a>(p: *const u8, len: usize) -> &'a [u8] {
+    unsafe { slice::from_raw_parts(p, len) }
+}
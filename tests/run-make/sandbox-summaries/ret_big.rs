@@ -0,0 +1,21 @@
+// A large struct, which the ABI returns via a hidden pointer to the caller's
+// return slot, written field by field and via a pointer to it. The heap
+// allocation stored in it is a def site of the return value either way.
+
+pub struct Big {
+    pub a: [u64; 8],
+    pub p: *mut u8,
+}
+
+pub fn field_wise(q: *mut u8) -> Big {
+    let mut big = Big { a: [0; 8], p: q };
+    big.p = Box::into_raw(Box::new(1u8));
+    big
+}
+
+pub fn via_ptr(q: *mut u8) -> Big {
+    let mut big = Big { a: [0; 8], p: q };
+    let r = &mut big;
+    r.p = Box::into_raw(Box::new(1u8));
+    big
+}
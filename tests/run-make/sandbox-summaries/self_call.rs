@@ -0,0 +1,14 @@
+// A call whose return is assigned to one of its arguments, "p = step(p, q)".
+// Once the copies of the arguments are propagated, the call may read and write
+// the same Local, e.g., "_1 = step(copy _1, move _2)". The def sites of both
+// arguments before the call must still be found.
+
+pub fn step(p: *mut u8, q: *mut u8) -> *mut u8 {
+    if p == q { q } else { p }
+}
+
+pub fn self_call(mut p: *mut u8) -> *mut u8 {
+    let q = Box::into_raw(Box::new(1u8));
+    p = step(p, q);
+    p
+}
@@ -0,0 +1,27 @@
+// A heap node that stores a pointer to itself.
+
+pub struct Node {
+    next: *mut Node,
+}
+
+pub fn self_loop() -> *mut Node {
+    let p = Box::into_raw(Box::new(Node { next: std::ptr::null_mut() }));
+    unsafe {
+        (*p).next = p;
+    }
+    p
+}
+
+pub struct Counter {
+    val: u32,
+}
+
+// The loaded value of a field is not a pointer to the allocation, so storing
+// it back does not make the allocation self-referential.
+pub fn bump() -> *mut Counter {
+    let p = Box::into_raw(Box::new(Counter { val: 0 }));
+    unsafe {
+        (*p).val = (*p).val + 1;
+    }
+    p
+}
@@ -0,0 +1,9 @@
+// A static whose initializer dereferences a raw pointer in an unsafe block.
+
+static BYTE: u8 = 1;
+
+pub const fn id(p: *const u8) -> *const u8 {
+    p
+}
+
+pub static COPY: u8 = unsafe { *id(&BYTE) };
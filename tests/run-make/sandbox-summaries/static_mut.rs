@@ -0,0 +1,7 @@
+// A static mut written in an unsafe block.
+
+static mut COUNTER: u32 = 0;
+
+pub fn reset() {
+    unsafe { COUNTER = 0; }
+}
@@ -0,0 +1,9 @@
+// A pointer transmuted from an integer argument. The transmute is an unsafe
+// source, and the pointer still carries the taint of the argument.
+
+use std::mem;
+
+pub fn deref_addr(addr: usize) -> u8 {
+    let p: *mut u8 = unsafe { mem::transmute(addr) };
+    unsafe { *p }
+}
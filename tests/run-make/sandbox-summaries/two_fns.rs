@@ -0,0 +1,20 @@
+// A heap pointer passed through two functions before it is used by unsafe
+// code. The allocation and the dereference are only connected by WPA.
+
+pub fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+pub fn pass(p: *mut u8) -> *mut u8 {
+    p
+}
+
+pub fn read(p: *mut u8) -> u8 {
+    unsafe { *p }
+}
+
+pub fn caller() -> u8 {
+    let p = alloc();
+    let q = pass(p);
+    read(q)
+}
@@ -0,0 +1,8 @@
+// A reference created by mem::zeroed, which is never a valid &u8, and read.
+
+use std::mem;
+
+pub fn zeroed_ref() -> u8 {
+    let r: &u8 = unsafe { mem::zeroed() };
+    *r
+}
@@ -0,0 +1,14 @@
+// Reading a field of a union reinterprets its bytes, so a pointer read from a
+// union is an unsafe source by itself, even though the union is built from
+// the argument.
+
+#[repr(C)]
+pub union IntOrPtr {
+    pub int: usize,
+    pub ptr: *mut u8,
+}
+
+pub fn read_ptr(addr: usize) -> u8 {
+    let u = IntOrPtr { int: addr };
+    unsafe { *u.ptr }
+}
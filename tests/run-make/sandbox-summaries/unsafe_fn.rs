@@ -0,0 +1,7 @@
+// The whole body of an unsafe function is unsafe, so all its arguments and
+// the results of all its calls are unsafe def sites.
+
+pub unsafe fn fill(dst: *mut u8, n: usize) -> Vec<u8> {
+    *dst = n as u8;
+    Vec::with_capacity(n)
+}
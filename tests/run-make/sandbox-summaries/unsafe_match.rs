@@ -0,0 +1,10 @@
+// A pattern match in an unsafe function. Pattern matches leave FakeRead
+// statements in MIR before CleanupPostBorrowck, but the summaries are built
+// from optimized MIR, which has none; test_fake_read_place covers FakeRead.
+
+pub unsafe fn read_some(v: Option<*mut u8>) -> u8 {
+    match v {
+        Some(p) => *p,
+        None => 0,
+    }
+}
@@ -0,0 +1,13 @@
+// A variadic foreign function called with a different number of arguments
+// at each call site.
+
+extern "C" {
+    fn printf(format: *const u8, ...) -> i32;
+}
+
+pub fn print(p: *const u8, n: i32) {
+    unsafe {
+        printf(b"%s\n\0".as_ptr(), p);
+        printf(b"%s %d\n\0".as_ptr(), p, n);
+    }
+}
@@ -0,0 +1,14 @@
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+fn main() {
+    let p = alloc();
+    let v = unsafe {
+        match *p {
+            0 => 0,
+            _ => 1,
+        }
+    };
+    std::process::exit(v);
+}
@@ -0,0 +1,14 @@
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+fn main() {
+    let p = alloc();
+    // Vec::default() is not a known allocation, so the pointer read from the
+    // Vec is only unsafe because the unsafe p is pushed to it.
+    let mut ptrs: Vec<*mut u8> = Vec::default();
+    ptrs.push(p);
+    let q = ptrs[0];
+    let v = unsafe { *q };
+    std::process::exit(v.into());
+}
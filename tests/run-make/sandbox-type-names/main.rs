@@ -0,0 +1,7 @@
+type Deep = Vec<Vec<Vec<Vec<Vec<u8>>>>>;
+
+fn main() {
+    let p: *mut Deep = Box::into_raw(Box::new(Vec::new()));
+    let n = unsafe { (*p).len() };
+    std::process::exit(n as i32);
+}
@@ -0,0 +1,23 @@
+// read() has unsafe code and calls helper(). wrap() calls read() and len(), a
+// safe fn whose summary is needed to trace what wrap() returns. other() has
+// nothing to do with unsafe code.
+
+fn helper(p: *const u8) -> *const u8 {
+    p
+}
+
+pub fn read(p: *const u8) -> u8 {
+    unsafe { *helper(p) }
+}
+
+fn len(v: &Vec<u8>) -> usize {
+    v.len()
+}
+
+pub fn wrap(v: &Vec<u8>) -> usize {
+    read(v.as_ptr()) as usize + len(v)
+}
+
+pub fn other(x: u32) -> u32 {
+    x + 1
+}
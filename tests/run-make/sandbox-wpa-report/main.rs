@@ -0,0 +1,15 @@
+// A heap allocation that flows into unsafe code in another function. Only
+// the WPA connects the allocation in alloc() to the dereference in read().
+
+fn alloc() -> *mut u8 {
+    Box::into_raw(Box::new(1u8))
+}
+
+fn read(p: *mut u8) -> u8 {
+    unsafe { *p }
+}
+
+fn main() {
+    let p = alloc();
+    std::process::exit(read(p).into());
+}